@@ -1,12 +1,146 @@
+use crate::command::exit_code::ExitCode;
 use crate::errors::{ArgumentError, ProgramError, arg_error};
+use crate::term_events::{KeyAction, KeyChord, parse_key_binding};
+use crate::tui::file_colors::parse_file_color;
+use crate::tui::theme::{Theme, parse_theme};
 use clap::{CommandFactory, FromArgMatches, Parser, builder::styling};
+use colored::Color;
 use regex::Regex;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
 /// Use this placeholder to substitute individual updated files in the command
 pub static FILE_SUBSTITUTION: &str = "{file}";
 /// Use this placeholder to substitute the list of updated files in the command
 pub static FILES_SUBSTITUTION: &str = "{files}";
+/// Use this placeholder to substitute the label of the watch in the command
+pub static LABEL_SUBSTITUTION: &str = "{label}";
+/// Use this placeholder to substitute the name of the updated file, without
+/// its directory nor extension
+pub static BASENAME_SUBSTITUTION: &str = "{basename}";
+/// Use this placeholder to substitute the directory of the updated file
+pub static DIR_SUBSTITUTION: &str = "{dir}";
+/// Use this placeholder to substitute the extension of the updated file
+pub static EXT_SUBSTITUTION: &str = "{ext}";
+/// Use this placeholder in --env values to substitute the command number
+pub static COMMAND_NUMBER_SUBSTITUTION: &str = "{n}";
+/// Placeholders for a single file, the command then runs once per file
+pub static PER_FILE_SUBSTITUTIONS: [&str; 4] =
+    [FILE_SUBSTITUTION, BASENAME_SUBSTITUTION, DIR_SUBSTITUTION, EXT_SUBSTITUTION];
+
+/// Checks if a command uses a placeholder. Escaped placeholders, with doubled
+/// braces like `{{file}}`, do not count
+pub fn has_placeholder(command: &str, placeholder: &str) -> bool {
+    command.replace(&format!("{{{placeholder}}}"), "").contains(placeholder)
+}
+
+/// Placeholders substituted in the command
+pub static COMMAND_SUBSTITUTIONS: [&str; 6] = [
+    FILE_SUBSTITUTION,
+    FILES_SUBSTITUTION,
+    LABEL_SUBSTITUTION,
+    BASENAME_SUBSTITUTION,
+    DIR_SUBSTITUTION,
+    EXT_SUBSTITUTION,
+];
+
+/// Finds the first `{name}` token of a command that is not a known
+/// placeholder. Escaped `{{name}}` and shell variables like `${name}` are
+/// not tokens, nor braces around anything else than a name
+pub fn unknown_placeholder(command: &str) -> Option<&str> {
+    let mut start = 0;
+    while let Some(open) = command[start..].find('{').map(|i| start + i) {
+        start = open + 1;
+        if command[..open].ends_with(['{', '$']) {
+            continue;
+        }
+        let Some(len) = command[start..].find('}') else {
+            break;
+        };
+        let token = &command[open..=start + len];
+        let name = &token[1..token.len() - 1];
+        if !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !COMMAND_SUBSTITUTIONS.contains(&token)
+        {
+            return Some(token);
+        }
+    }
+    None
+}
+
+/// Command line or env value with placeholders, split once into literal
+/// text and placeholders, so that each run only joins the values
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// Text kept as is
+    Literal(String),
+    /// Index of the placeholder in the list the template was parsed with
+    Placeholder(usize),
+}
+
+impl Template {
+    /// Splits a text on the placeholders, tried in the given order. Escaped
+    /// placeholders like `{{file}}` are kept as literal `{file}`
+    pub fn parse(text: &str, placeholders: &[&str]) -> Self {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        'next: while let Some(c) = rest.chars().next() {
+            for (i, placeholder) in placeholders.iter().enumerate() {
+                if let Some(after) = rest.strip_prefix(&format!("{{{placeholder}}}")) {
+                    literal.push_str(placeholder);
+                    rest = after;
+                    continue 'next;
+                }
+                if let Some(after) = rest.strip_prefix(placeholder) {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(i));
+                    rest = after;
+                    continue 'next;
+                }
+            }
+            literal.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Self { segments }
+    }
+
+    /// Replaces the placeholders with their values, given in the order of
+    /// the placeholders at parse()
+    pub fn render(&self, values: &[&str]) -> String {
+        let len = self.segments.iter().map(|s| s.text(values).len()).sum();
+        let mut rendered = String::with_capacity(len);
+        for segment in &self.segments {
+            rendered.push_str(segment.text(values));
+        }
+        rendered
+    }
+}
+
+impl Segment {
+    /// Text of the segment, with the values of the placeholders
+    fn text<'a>(&'a self, values: &[&'a str]) -> &'a str {
+        match self {
+            Self::Literal(literal) => literal,
+            Self::Placeholder(i) => values[*i],
+        }
+    }
+}
+
+/// Shortest poll interval in ms, shorter ones would keep the poll watcher busy
+pub const MIN_POLL_INTERVAL: u64 = 10;
 
 #[cfg(not(windows))]
 pub const DEFAULT_SHELL: &str = "sh -c";
@@ -20,13 +154,14 @@ const STYLES: styling::Styles = styling::Styles::styled()
     .literal(styling::AnsiColor::Blue.on_default().bold())
     .placeholder(styling::AnsiColor::Cyan.on_default());
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = env!("CARGO_PKG_NAME"), max_term_width = 80)]
 #[command(about = "Run commands when files are updated")]
 #[command(version)]
 pub struct Args {
     /// List of files or directories to watch. Will watch everything in the
-    /// current directory if none is specified
+    /// current directory if none is specified.
+    /// Use name=path to label the watch, e.g. -f web=frontend/
     #[arg(short, long = "file", name = "file/dir")]
     pub files: Vec<String>,
 
@@ -38,15 +173,53 @@ pub struct Args {
 Placeholders:
   Use {file} to substitute the updated file in the command
   Use {files} to substitute all updated files in the command
+  Use {label} to substitute the label of the watch (see -f name=path)
   By default if no placeholder is present, one command will be run for all executed files"#
     )]
     pub command: Vec<String>,
 
+    /// Command to run instead on Unix systems
+    #[arg(long, value_name = "COMMAND")]
+    pub command_unix: Option<String>,
+
+    /// Command to run instead on Windows
+    #[arg(long, value_name = "COMMAND")]
+    pub command_windows: Option<String>,
+
     /// List of file extensions to watch.
     #[arg(short, long = "extension", name = "extension")]
     pub extensions: Vec<String>,
 
-    /// Poll interval in ms for file updates
+    /// Run a command for the updated files with some extensions, e.g.
+    /// 'css=scss,sass:npm run css'. The task watches the files/dirs given
+    /// after its name, e.g. 'css@web/,theme/=scss:npm run css', or the ones of
+    /// -f. Can be repeated, the tasks run independently and replace the
+    /// command. Each task gives its own extensions instead of -e, the other
+    /// filter options apply to the files of every task
+    #[arg(long = "task", value_name = "NAME[@PATH,..]=EXT,..:COMMAND",
+          conflicts_with_all = ["command", "command_unix", "command_windows", "journal",
+                                "extension"])]
+    pub tasks: Vec<Task>,
+
+    /// Wait up to SECS for watched files/dirs that do not exist yet, e.g. a
+    /// build dir created by another process
+    #[arg(long, value_name = "SECS")]
+    pub wait_for_targets: Option<u64>,
+
+    /// Exact file names to watch, regardless of their directory,
+    /// e.g. --name Cargo.toml
+    #[arg(long = "name", value_name = "FILENAME")]
+    pub names: Vec<String>,
+
+    /// Only react to files at most N levels below the watched directories.
+    /// 1 keeps the files directly in them
+    #[arg(long, value_name = "N",
+          value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_depth: Option<usize>,
+
+    /// Poll interval in ms for file updates, at least 10.
+    /// Updates are seen up to one interval late, then wait for the
+    /// --debounce window like any other update
     #[arg(long, default_value_t = 200)]
     pub poll_interval: u64,
 
@@ -66,7 +239,8 @@ Placeholders:
     pub current_working_dir: Option<String>,
 
     /// Environment variables to set when the command is executed.
-    /// Format is KEY=VALUE
+    /// Format is KEY=VALUE. Values can use the command placeholders and {n}
+    /// for the command number, e.g. LOGFILE=build-{n}.log
     #[arg(short = 'E', long)]
     pub env: Vec<String>,
 
@@ -83,14 +257,34 @@ Placeholders:
     #[arg(long)]
     pub force_poll: bool,
 
+    /// File or directory on a network mount to watch, using polling.
+    /// Network mounts are also detected automatically on Linux
+    #[arg(long, value_name = "PATH")]
+    pub remote: Vec<String>,
+
+    /// Poll interval in ms for file updates on network mounts, at least 10
+    #[arg(long, default_value_t = 2000)]
+    pub remote_poll_interval: u64,
+
     /// Include hidden files and directories in updated files
     #[arg(long, short = 'H')]
     pub hidden: bool,
 
-    /// Do no respect .gitignore files.
+    /// Clear the terminal before each command run, to only see the output
+    /// of the latest one
+    #[arg(short, long)]
+    pub clear: bool,
+
+    /// Do no respect .gitignore files. .ignore and .rexignore files are
+    /// still respected
     #[arg(short = 'I', long)]
     pub no_gitignore: bool,
 
+    /// Only react to files tracked by git, untracked files are ignored even
+    /// if no .gitignore rule matches them
+    #[arg(long)]
+    pub tracked_only: bool,
+
     /// Invoke the command also when files are deleted and no longer exist
     #[arg(short, long)]
     pub deleted: bool,
@@ -104,10 +298,317 @@ Placeholders:
     #[arg(short, long)]
     pub abort_previous: bool,
 
-    /// Shell used to spawn the command
-    /// Not possible to specify manually for now
+    /// Like --abort-previous, but a running command is only aborted for a
+    /// different set of files: it keeps running if the same files are updated
+    /// again
+    #[arg(long, conflicts_with = "feed")]
+    pub abort_on_new: bool,
+
+    /// Start the command once and write updated files to its stdin, one per
+    /// line, instead of running it again for every update.
+    /// The command is restarted if it exits.
+    #[arg(long, conflicts_with = "abort_previous")]
+    pub feed: bool,
+
+    /// Let the command use the terminal directly (stdin/stdout/stderr), for
+    /// debuggers or prompts. Commands run one at a time and the UI steps back
+    /// while they run
+    #[arg(long, conflicts_with = "feed")]
+    pub interactive: bool,
+
+    /// Keep one instance of a long-running command, e.g. a dev server: it
+    /// starts right away, and every update stops it and starts a new one
+    #[arg(long, conflicts_with_all = ["feed", "foreach", "interactive", "until_success",
+          "abort_on_new", "dry_run_ignore"])]
+    pub restart: bool,
+
+    /// Drop file updates while a command is running, so that files written by
+    /// the command do not trigger it again.
+    /// Use --ignore-during-run=PATH to only drop updates under PATH
+    #[arg(long, value_name = "PATH", require_equals = true)]
+    pub ignore_during_run: Option<Option<String>>,
+
+    /// Run the command again, with a growing delay, until it succeeds.
+    /// Stops retrying once it succeeded, until the next file update
+    #[arg(long)]
+    pub until_success: bool,
+
+    /// Durations in ms under which a command is shown as fast (green) and
+    /// normal (yellow). Slower commands are shown in red
+    #[arg(long, value_name = "FAST,SLOW", default_value = "1000,10000")]
+    pub duration_thresholds: DurationThresholds,
+
+    /// Always accept the files matching this gitignore-style pattern, relative
+    /// to the watched directory, even if other filters would ignore them
+    #[arg(long, value_name = "GLOB")]
+    pub force: Vec<String>,
+
+    /// Show the stderr lines of the commands in a pane at the bottom of the
+    /// screen, apart from their stdout
+    #[arg(long)]
+    pub errors_pane: bool,
+
+    /// Ignore the files matching this gitignore-style pattern, relative to
+    /// the watched directory. The pattern is taken verbatim, trailing spaces
+    /// included
+    #[arg(long, value_name = "GLOB")]
+    pub ignore_raw: Vec<String>,
+
+    /// Regular expression extracting the progress of a command from its output,
+    /// with two capture groups: done and total, e.g. '(\d+)/(\d+)'
+    #[arg(long, value_name = "REGEX")]
+    pub parse_progress: Option<String>,
+
+    /// Experimental: write a unified diff of the updated text files to the
+    /// command stdin, against their content at the previous update
+    #[arg(long, conflicts_with_all = ["feed", "interactive"])]
+    pub diff: bool,
+
+    /// Read the watched files at startup, so that their first update is
+    /// already diffed by --diff, without running the command
+    #[arg(long, requires = "diff")]
+    pub no_run_on_startup_scan: bool,
+
+    /// Quit once the command ran for the first updates, with its exit code.
+    /// All the files of the batch, or of the run per file, are handled
+    #[arg(long, conflicts_with = "feed")]
+    pub once: bool,
+
+    /// Run the command once at startup, for the watched files/dirs, without
+    /// waiting for a file update
+    #[arg(long, visible_alias = "initial")]
+    pub run_at_start: bool,
+
+    /// Updates of a file reported by several overlapping watches within this
+    /// duration, in ms, count once. 0 disables it
+    #[arg(long, default_value_t = 200)]
+    pub dedup_window: u64,
+
+    /// Command to run instead when the command cannot start, e.g. because its
+    /// program is missing. As the command runs in a shell, any exit code 127
+    /// also runs it, after the output of the command. Supports the same
+    /// placeholders
+    #[arg(long, value_name = "CMD")]
+    pub fallback: Option<String>,
+
+    /// Color of the file names with an extension, e.g. 'rs=red'. Can be
+    /// repeated, replaces the default color of the extension
+    #[arg(long, value_name = "EXT=COLOR", value_parser = parse_file_color)]
+    pub file_color: Vec<(String, Color)>,
+
+    /// Colors of the UI: default, mono (no colors) or solarized
+    #[arg(long, value_name = "NAME", default_value = "default", value_parser = parse_theme)]
+    pub theme: Theme,
+
+    /// When to color the output. With auto, colors are used on a terminal,
+    /// unless the NO_COLOR environment variable is set
+    #[arg(long, value_name = "WHEN", value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Do not color the output, same as --color never
+    #[arg(long, conflicts_with = "color")]
+    pub no_color: bool,
+
+    /// Bind a key to an action, e.g. `ctrl-b=rerun`. Actions: quit, clear,
+    /// pause, abort, focus, focus-skip, queue, run, rerun, rerun-env, title
+    #[arg(long, value_name = "KEY=ACTION", value_parser = parse_key_binding)]
+    pub bind: Vec<(KeyChord, KeyAction)>,
+
+    /// Do not color the file names by extension
+    #[arg(long)]
+    pub no_file_colors: bool,
+
+    /// Time without file updates, in ms, before running the command.
+    /// Updates arriving together are still run once with 0
+    #[arg(long, value_name = "MS", default_value_t = 200)]
+    pub debounce: u64,
+
+    /// Time to wait, in ms, after updates settle and before running the
+    /// command. Updates arriving meanwhile join the same run
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub delay: u64,
+
+    /// Delay each command by a random duration up to this one, in ms, so
+    /// that commands started together do not all run at once
+    #[arg(long, value_name = "MS")]
+    pub jitter: Option<u64>,
+
+    /// Time without file deletions, in ms, before running the command for
+    /// deleted files. Other updates keep the --debounce settle window
+    #[arg(long)]
+    pub delete_debounce: Option<u64>,
+
+    /// Do not run the command for files that are empty when it starts, e.g.
+    /// files created by a generator that did not fill them yet
+    #[arg(long)]
+    pub skip_empty: bool,
+
+    /// Run the command for every line read on stdin, or when Enter is pressed
+    /// if stdin is a terminal
+    #[arg(long, conflicts_with = "interactive")]
+    pub trigger_stdin: bool,
+
+    /// Append the file updates to this journal, and mark them as processed
+    /// once a command ran for them. See --replay
+    #[arg(long, value_name = "PATH")]
+    pub journal: Option<PathBuf>,
+
+    /// At startup, run the command for the last N updates of the --journal
+    /// that no command ran for, e.g. because rex was stopped or crashed
+    #[arg(long, value_name = "N", requires = "journal")]
+    pub replay: Option<usize>,
+
+    /// Rewrite this file with a JSON summary of each finished command
+    #[arg(long, value_name = "PATH")]
+    pub status_file: Option<PathBuf>,
+
+    /// Maximum number of pending events handled at once from a channel,
+    /// before checking the other channels again
+    #[arg(long, default_value_t = 16, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub event_batch_size: usize,
+
+    /// Warn once when a command is still running after this duration, in ms
+    #[arg(long)]
+    pub warn_after: Option<u64>,
+
+    /// Stop a command still running after this duration, in ms
+    #[arg(long, value_name = "MS")]
+    pub timeout: Option<u64>,
+
+    /// Signal sent to a command at --timeout. A command still running a
+    /// moment later is killed with SIGKILL (Unix only)
+    #[arg(long, value_name = "SIGNAL", value_enum, default_value_t = TimeoutSignal::Sigterm,
+          requires = "timeout")]
+    pub timeout_signal: TimeoutSignal,
+
+    /// Learn the files written by the command right after a run, and do not
+    /// let them trigger it again for a while
+    #[arg(long)]
+    pub auto_ignore_outputs: bool,
+
+    /// Maximum number of files passed to one command in batch mode.
+    /// Larger batches are split and the command runs once per chunk
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub chunk_size: Option<usize>,
+
+    /// Collect the updated files like in batch mode, then run the command
+    /// once per file, one after the other
+    #[arg(long, conflicts_with_all = ["feed", "chunk_size"])]
+    pub foreach: bool,
+
+    /// Maximum number of commands running at the same time. Files updated
+    /// meanwhile wait for a command to finish, unless they abort the running
+    /// commands
+    #[arg(short, long, value_name = "N", default_value_t = 3, conflicts_with = "interactive",
+          value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub jobs: usize,
+
+    /// Print "(no output)" for commands that finish without printing anything
+    #[arg(long)]
+    pub mark_empty: bool,
+
+    /// Ring the terminal bell when a command fails, but only once N commands
+    /// in a row have failed
+    #[arg(long, value_name = "N",
+          value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub notify_after: Option<usize>,
+
+    /// Show a desktop notification when a command fails
+    #[arg(long)]
+    pub notify: bool,
+
+    /// Finished commands getting a desktop notification with --notify
+    #[arg(long, value_name = "WHEN", value_enum, default_value_t = NotifyOn::Failure,
+          requires = "notify")]
+    pub notify_on: NotifyOn,
+
+    /// Prefix the output lines with the number of the command that printed
+    /// them, e.g. "#3|", to tell apart the outputs of concurrent commands
+    #[arg(long)]
+    pub prefix_output: bool,
+
+    /// Prefix the output lines of each command with the name of the file it
+    /// runs for, when one command runs per file
+    #[arg(long)]
+    pub prefix_file: bool,
+
+    /// Print a separator line with the command number before the output of
+    /// each command
+    #[arg(long)]
+    pub group_by_command_number: bool,
+
+    /// Keep the carriage returns of \r\n line endings in the command outputs
+    #[arg(long)]
+    pub keep_crlf: bool,
+
+    /// Maximum length of an output line in bytes, longer lines are split.
+    /// 0 does not limit the length
+    #[arg(long, value_name = "BYTES", default_value_t = 64 * 1024)]
+    pub max_line_length: usize,
+
+    /// Show a status line at the bottom of the screen, with the running
+    /// commands, pending files and last exit code
+    #[arg(long)]
+    pub footer: bool,
+
+    /// Maximum virtual memory of the command, in MB (Unix only)
+    #[arg(long, value_name = "MB")]
+    pub mem_limit: Option<u64>,
+
+    /// Maximum CPU time of the command, in seconds (Unix only)
+    #[arg(long, value_name = "SECS")]
+    pub cpu_time_limit: Option<u64>,
+
+    /// Scheduling priority of the command, from -20 (highest) to 19 (lowest)
+    /// (Unix only)
+    #[arg(long, value_name = "N", allow_negative_numbers = true,
+          value_parser = clap::value_parser!(i32).range(-20..=19))]
+    pub nice: Option<i32>,
+
+    /// Print the ignore files consulted for this path and the rule
+    /// deciding if it is ignored, then exit
+    #[arg(long, value_name = "PATH")]
+    pub explain_ignore: Option<PathBuf>,
+
+    /// Print the watch and the event receiver each file update comes from,
+    /// to debug overlapping watches
+    #[arg(long)]
+    pub show_source: bool,
+
+    /// Watch as usual, but print for each file update whether it would run
+    /// the command, or why it is ignored. The command never runs
+    #[arg(long, conflicts_with_all = ["run_at_start", "trigger_stdin"])]
+    pub dry_run_ignore: bool,
+
+    /// Print nothing while commands run. On quit, print a single line with
+    /// the totals: runs=N ok=N failed=N duration_ms=N
+    #[arg(long)]
+    pub count_only: bool,
+
+    /// Format of the output: pretty progress bars, or one JSON object per
+    /// line for each start, output line and end of the commands
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = OutputFormat::Pretty,
+          conflicts_with = "count_only")]
+    pub format: OutputFormat,
+
+    /// Substitute absolute paths for the file placeholders, even for files
+    /// given with a relative path
+    #[arg(long)]
+    pub abs_paths: bool,
+
+    /// Reject the command, or the --fallback command, if it contains a
+    /// `{name}` token that is not a known placeholder, e.g. a typo like {fil}
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Shell used to spawn the command, given the command as last argument,
+    /// e.g. 'bash -c' or 'pwsh -Command'
+    #[arg(long, value_name = "COMMAND", default_value = DEFAULT_SHELL)]
+    pub shell: String,
+
+    /// Watch targets, parsed from the list of files
     #[clap(skip)]
-    pub shell: &'static str,
+    pub watches: Vec<WatchTarget>,
 
     /// Indicates is we batch execute, i.e. 1 exec for all modified files
     /// or if it is one execution per modified file
@@ -120,6 +621,176 @@ Placeholders:
     /// Compiled Negative Regexps (i.e. what filenames must not match)
     #[clap(skip)]
     pub ignored_regexps: Vec<Regex>,
+    /// Compiled --parse-progress regexp
+    #[clap(skip)]
+    pub progress_regex: Option<Regex>,
+    /// Name of the --task these arguments run the command of
+    #[clap(skip)]
+    pub task_name: Option<String>,
+}
+
+/// A file or directory to watch
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchTarget {
+    /// Path to the file or directory
+    pub path: String,
+    /// Optional human label, shown in the UI and passed to the command
+    pub label: Option<String>,
+    /// Is the target on a network mount
+    pub remote: bool,
+}
+
+impl WatchTarget {
+    /// Parses a watch target given as `path` or `label=path`.
+    /// Values that are existing paths are never split.
+    pub fn parse(value: &str) -> Self {
+        if !std::path::Path::new(value).exists()
+            && let Some((label, path)) = value.split_once('=')
+            && !label.is_empty()
+            && !path.is_empty()
+        {
+            return Self { path: path.to_string(), label: Some(label.to_string()), remote: false };
+        }
+        Self { path: value.to_string(), label: None, remote: false }
+    }
+}
+
+/// Command run for the files with some extensions, with --task
+#[derive(Debug, Clone, PartialEq)]
+pub struct Task {
+    /// Name of the task, shown in the UI and passed to the command as label
+    pub name: String,
+    /// Files/dirs watched by the task, the ones of -f if empty
+    pub paths: Vec<String>,
+    /// Extensions of the files the task runs for, any file if empty
+    pub extensions: Vec<String>,
+    /// Command of the task, with the usual placeholders
+    pub command: String,
+}
+
+impl FromStr for Task {
+    type Err = String;
+
+    /// Parses `name=ext,ext:command` or `name@path,path=ext,ext:command`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let format = "expected name[@path,..]=ext,..:command, e.g. css@web/=scss,sass:npm run css";
+        let (name, rest) = value.split_once('=').ok_or(format)?;
+        let (extensions, command) = rest.split_once(':').ok_or(format)?;
+        let (name, paths) = match name.split_once('@') {
+            Some((name, paths)) => {
+                let paths: Vec<String> =
+                    paths.split(',').filter(|p| !p.is_empty()).map(String::from).collect();
+                if paths.is_empty() {
+                    return Err(format.to_string());
+                }
+                (name, paths)
+            }
+            None => (name, Vec::new()),
+        };
+        if name.is_empty() || command.trim().is_empty() {
+            return Err(format.to_string());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            paths,
+            extensions: extensions.split(',').filter(|e| !e.is_empty()).map(String::from).collect(),
+            command: command.to_string(),
+        })
+    }
+}
+
+/// When to color the output, for --color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Tells if the output is colored, depending on NO_COLOR being set and on
+    /// the output being a terminal
+    pub fn enabled(self, no_color_env: bool, terminal: bool) -> bool {
+        match self {
+            Self::Auto => !no_color_env && terminal,
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// Finished commands notified on the desktop, for --notify-on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum NotifyOn {
+    Failure,
+    Success,
+    Always,
+}
+
+impl NotifyOn {
+    /// Tells if a command that finished with this exit code is notified.
+    /// Commands that did not finish count as failures
+    pub fn matches(self, exit_code: ExitCode) -> bool {
+        match self {
+            Self::Failure => exit_code != Some(0),
+            Self::Success => exit_code == Some(0),
+            Self::Always => true,
+        }
+    }
+}
+
+/// Signal stopping the commands at --timeout
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TimeoutSignal {
+    #[default]
+    #[value(name = "SIGTERM", alias = "sigterm")]
+    Sigterm,
+    #[value(name = "SIGKILL", alias = "sigkill")]
+    Sigkill,
+}
+
+#[cfg(unix)]
+impl TimeoutSignal {
+    /// Number of the signal
+    pub fn number(self) -> libc::c_int {
+        match self {
+            Self::Sigterm => libc::SIGTERM,
+            Self::Sigkill => libc::SIGKILL,
+        }
+    }
+}
+
+/// Format of the output, for --format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Pretty,
+    Json,
+}
+
+/// Limits used to color the duration of the finished commands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DurationThresholds {
+    /// Commands faster than this are shown in green
+    pub fast: Duration,
+    /// Commands slower than this are shown in red
+    pub slow: Duration,
+}
+
+impl FromStr for DurationThresholds {
+    type Err = String;
+
+    /// Parses `fast,slow`, both in ms
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (fast, slow) =
+            value.split_once(',').ok_or("expected two durations in ms, e.g. 1000,10000")?;
+        let parse = |v: &str| v.trim().parse::<u64>().map(Duration::from_millis);
+        let fast = parse(fast).map_err(|e| e.to_string())?;
+        let slow = parse(slow).map_err(|e| e.to_string())?;
+        if fast > slow {
+            return Err("the fast threshold must not exceed the slow one".to_string());
+        }
+        Ok(Self { fast, slow })
+    }
 }
 
 impl Args {
@@ -131,21 +802,60 @@ impl Args {
         Ok(args)
     }
 
+    /// Arguments of the queue of a --task: the command and extensions of the
+    /// task, the other options shared. To call before validate()
+    pub fn for_task(&self, task: &Task) -> Result<Self, ProgramError> {
+        let mut args = self.clone();
+        args.command = vec![task.command.clone()];
+        args.extensions = task.extensions.clone();
+        if !task.paths.is_empty() {
+            args.files = task.paths.clone();
+            args.remote = Vec::new();
+        }
+        args.tasks = Vec::new();
+        args.task_name = Some(task.name.clone());
+        args.validate()?;
+        Ok(args)
+    }
+
     pub fn validate(&mut self) -> Result<(), ProgramError> {
         // Validate regexps
         for r in &self.regex {
             let regex_res = Regex::new(r);
             match regex_res {
                 Ok(regex) => self.regexps.push(regex),
-                Err(e) => return Err(arg_error!(InvalidRegex, r.clone(), e.to_string())),
+                Err(e) => {
+                    return Err(arg_error!(InvalidRegex, "-r/--regex", r.clone(), e.to_string()));
+                }
             }
         }
         for r in &self.ignored_regex {
             let regex_res = Regex::new(r);
             match regex_res {
                 Ok(regex) => self.ignored_regexps.push(regex),
-                Err(e) => return Err(arg_error!(InvalidRegex, r.clone(), e.to_string())),
+                Err(e) => {
+                    let flag = "-R/--ignored-regex";
+                    return Err(arg_error!(InvalidRegex, flag, r.clone(), e.to_string()));
+                }
+            }
+        }
+        for (flag, interval) in [
+            ("--poll-interval", self.poll_interval),
+            ("--remote-poll-interval", self.remote_poll_interval),
+        ] {
+            if interval < MIN_POLL_INTERVAL {
+                return Err(arg_error!(PollIntervalTooShort, flag, interval, MIN_POLL_INTERVAL));
+            }
+        }
+        if let Some(r) = &self.parse_progress {
+            let flag = "--parse-progress";
+            let regex = Regex::new(r)
+                .map_err(|e| arg_error!(InvalidRegex, flag, r.clone(), e.to_string()))?;
+            if regex.captures_len() < 3 {
+                let reason = "expected two capture groups: done and total".to_string();
+                return Err(arg_error!(InvalidRegex, flag, r.clone(), reason));
             }
+            self.progress_regex = Some(regex);
         }
 
         // Remove all trailings dots if the user has given extensions with
@@ -162,19 +872,51 @@ impl Args {
 
         self.extensions = parsed_extensions;
 
-        // If no files are passed, we watch the current directory for changes
-        if self.files.is_empty() {
+        // If no files are passed, we watch the current directory for changes,
+        // unless every --task watches its own files/dirs
+        let shared_watches = self.tasks.is_empty() || self.tasks.iter().any(|t| t.paths.is_empty());
+        if self.files.is_empty() && self.remote.is_empty() && shared_watches {
             self.files.push(String::from("."));
         }
+        self.watches = self.files.iter().map(|f| WatchTarget::parse(f)).collect();
+        self.watches.extend(
+            self.remote
+                .iter()
+                .map(|f| WatchTarget { remote: true, ..WatchTarget::parse(f) }),
+        );
+        // The files/dirs of the tasks are watched too
+        for path in self.tasks.iter().flat_map(|t| &t.paths) {
+            if !self.watches.iter().any(|w| w.path == *path) {
+                self.watches
+                    .push(WatchTarget { path: path.clone(), label: None, remote: false });
+            }
+        }
+
+        // A command for the current platform replaces the default one
+        let platform_command =
+            if cfg!(windows) { &self.command_windows } else { &self.command_unix };
+        if let Some(command) = platform_command {
+            self.command = vec![command.clone()];
+        }
 
         // Ensure we have a command to execute
-        if self.command.is_empty() {
+        if self.command.is_empty() && self.tasks.is_empty() && self.explain_ignore.is_none() {
             return Err(arg_error!(EmptyCommand));
         }
 
         // Assemble the command in 1 piece
         let command = self.command.join(" ");
 
+        if cfg!(not(unix)) && (self.mem_limit.is_some() || self.cpu_time_limit.is_some()) {
+            return Err(arg_error!(
+                UnsupportedOnPlatform,
+                "--mem-limit/--cpu-time-limit".to_string()
+            ));
+        }
+        if cfg!(not(unix)) && self.nice.is_some() {
+            return Err(arg_error!(UnsupportedOnPlatform, "--nice".to_string()));
+        }
+
         if let Some(path) = &self.current_working_dir {
             let p = std::path::Path::new(path);
             if !p.exists() {
@@ -182,16 +924,36 @@ impl Args {
             }
         }
 
+        if self.strict {
+            for cmd in [Some(&command), self.fallback.as_ref()].into_iter().flatten() {
+                if let Some(token) = unknown_placeholder(cmd) {
+                    let known = COMMAND_SUBSTITUTIONS.join(", ");
+                    let message = format!("Unknown placeholder {token}, known ones are {known}");
+                    return Err(arg_error!(CommandParseError, cmd.clone(), message));
+                }
+            }
+        }
+
         // Fill up whether we execute once or one time per file
-        self.batch_exec = !command.contains(FILE_SUBSTITUTION);
-        if command.contains(FILES_SUBSTITUTION) {
+        self.batch_exec = !PER_FILE_SUBSTITUTIONS.iter().any(|p| has_placeholder(&command, p));
+        if self.feed {
+            // Files are passed on stdin, the command itself is never substituted
+            if !self.batch_exec || has_placeholder(&command, FILES_SUBSTITUTION) {
+                return Err(arg_error!(
+                    CommandParseError,
+                    command,
+                    "Placeholders cannot be used with --feed".to_string()
+                ));
+            }
+        } else if has_placeholder(&command, FILES_SUBSTITUTION) {
             if !self.batch_exec {
                 // If substitutions are used, it's only single files or all files
                 return Err(arg_error!(
                     CommandParseError,
                     self.command.join(" "),
                     format!(
-                        "Command cannot contain both {FILE_SUBSTITUTION} and {FILES_SUBSTITUTION}"
+                        "Command cannot contain both {FILES_SUBSTITUTION} and {}",
+                        PER_FILE_SUBSTITUTIONS.join(", ")
                     )
                 ));
             }
@@ -199,14 +961,342 @@ impl Args {
             self.deleted = true;
             self.abort_previous = true;
         }
+        if self.abort_on_new {
+            self.abort_previous = true;
+        }
+        if self.no_color {
+            self.color = ColorChoice::Never;
+        }
+        if self.restart {
+            if !self.batch_exec {
+                return Err(arg_error!(
+                    CommandParseError,
+                    command,
+                    format!("{} cannot be used with --restart", PER_FILE_SUBSTITUTIONS.join(", "))
+                ));
+            }
+            self.abort_previous = true;
+            self.run_at_start = true;
+        }
 
         // Just replace the command with a single string
         self.command = vec![command];
 
-        // Fill up the default shell
-        self.shell = DEFAULT_SHELL;
+        // The shell is split like a command line, and needs a program
+        match shell_words::split(&self.shell) {
+            Ok(parts) if !parts.is_empty() => {}
+            Ok(_) => {
+                let message = "The shell cannot be empty".to_string();
+                return Err(arg_error!(CommandParseError, self.shell.clone(), message));
+            }
+            Err(e) => {
+                return Err(arg_error!(CommandParseError, self.shell.clone(), e.to_string()));
+            }
+        }
 
         //dbg!(&self);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_watch_target_label() {
+        let target = WatchTarget::parse("web=frontend/src");
+        assert_eq!(target.path, "frontend/src");
+        assert_eq!(target.label.as_deref(), Some("web"));
+    }
+
+    #[test]
+    fn test_watch_target_without_label() {
+        assert_eq!(WatchTarget::parse("src/").label, None);
+        assert_eq!(WatchTarget::parse("=src/").path, "=src/");
+        assert_eq!(WatchTarget::parse("web=").path, "web=");
+    }
+
+    #[test]
+    fn test_task() {
+        let task: Task = "css=.SCSS,sass:npm run css -- {file}".parse().unwrap();
+        assert_eq!(task.command, "npm run css -- {file}");
+        assert!("css:npm run css".parse::<Task>().is_err());
+        assert!("css=scss:".parse::<Task>().is_err());
+        assert!(task.paths.is_empty());
+        let task: Task = "css@web/,theme/=scss:npm run css".parse().unwrap();
+        assert_eq!(task.name, "css");
+        assert_eq!(task.paths, ["web/", "theme/"]);
+        assert!("css@=scss:npm run css".parse::<Task>().is_err());
+
+        let args =
+            Args::try_parse_from(["rex", "-a", "--task", "css=.SCSS,sass:npm run css {file}"])
+                .unwrap();
+        let mut main = args.clone();
+        main.validate().unwrap();
+        let task = args.for_task(&args.tasks[0]).unwrap();
+        assert_eq!(task.command, ["npm run css {file}"]);
+        assert_eq!(task.extensions, ["scss", "sass"]);
+        assert_eq!(task.task_name.as_deref(), Some("css"));
+        assert!(!task.batch_exec);
+
+        // Tasks watching their own files/dirs do not need the current dir
+        let dir = tempfile::tempdir().unwrap();
+        let task = format!("css@{}=scss:npm run css", dir.path().display());
+        let args = Args::try_parse_from(["rex", "--task", &task]).unwrap();
+        let mut main = args.clone();
+        main.validate().unwrap();
+        let watched: Vec<_> = main.watches.iter().map(|w| w.path.as_str()).collect();
+        assert_eq!(watched, [dir.path().to_str().unwrap()]);
+        let task = args.for_task(&args.tasks[0]).unwrap();
+        assert_eq!(task.watches, main.watches);
+
+        // The tasks give their own extensions
+        assert!(Args::try_parse_from(["rex", "-e", "rs", "--task", "css=scss:make"]).is_err());
+    }
+
+    #[test]
+    fn test_color_choice() {
+        let mut args = Args::try_parse_from(["rex", "--no-color", "make"]).unwrap();
+        args.validate().unwrap();
+        assert_eq!(args.color, ColorChoice::Never);
+        assert!(Args::try_parse_from(["rex", "--no-color", "--color", "always", "make"]).is_err());
+
+        assert!(ColorChoice::Auto.enabled(false, true));
+        assert!(!ColorChoice::Auto.enabled(true, true));
+        assert!(!ColorChoice::Auto.enabled(false, false));
+        assert!(ColorChoice::Always.enabled(true, false));
+        assert!(!ColorChoice::Never.enabled(false, true));
+    }
+
+    #[test]
+    fn test_shell() {
+        let mut args = Args::try_parse_from(["rex", "make"]).unwrap();
+        args.validate().unwrap();
+        assert_eq!(args.shell, DEFAULT_SHELL);
+
+        for shell in ["", "  ", "bash -c 'oops"] {
+            let mut args = Args::try_parse_from(["rex", "--shell", shell, "make"]).unwrap();
+            assert!(args.validate().is_err(), "{shell:?}");
+        }
+    }
+
+    #[test]
+    fn test_strict_placeholders() {
+        let mut args = Args::try_parse_from(["rex", "echo {fil}"]).unwrap();
+        args.validate().unwrap();
+
+        let mut args = Args::try_parse_from(["rex", "--strict", "echo {fil}"]).unwrap();
+        assert!(args.validate().is_err());
+        let cli = ["rex", "--strict", "--fallback", "echo {fil}", "echo"];
+        let mut args = Args::try_parse_from(cli).unwrap();
+        assert!(args.validate().is_err());
+
+        let command = "echo {file} {{fil}} ${HOME} {a,b} {} awk '{ print $1 }'";
+        let mut args = Args::try_parse_from(["rex", "--strict", command]).unwrap();
+        args.validate().unwrap();
+        assert_eq!(unknown_placeholder("cp {dir}/{basename}.{typo} out"), Some("{typo}"));
+    }
+
+    #[test]
+    fn test_notify_on() {
+        let args = Args::try_parse_from(["rex", "--notify", "make"]).unwrap();
+        assert_eq!(args.notify_on, NotifyOn::Failure);
+        assert!(args.notify_on.matches(Some(2)) && args.notify_on.matches(None));
+        assert!(!args.notify_on.matches(Some(0)));
+        assert!(NotifyOn::Success.matches(Some(0)) && !NotifyOn::Success.matches(None));
+        assert!(Args::try_parse_from(["rex", "--notify-on", "always", "make"]).is_err());
+    }
+
+    #[test]
+    fn test_timeout_signal() {
+        let args = Args::try_parse_from(["rex", "--timeout", "100", "make"]).unwrap();
+        assert_eq!(args.timeout_signal, TimeoutSignal::Sigterm);
+        let cli = ["rex", "--timeout", "100", "--timeout-signal", "SIGKILL", "make"];
+        assert_eq!(Args::try_parse_from(cli).unwrap().timeout_signal, TimeoutSignal::Sigkill);
+        assert!(Args::try_parse_from(["rex", "--timeout-signal", "SIGKILL", "make"]).is_err());
+        let cli = ["rex", "--timeout", "100", "--timeout-signal", "SIGHUP", "make"];
+        assert!(Args::try_parse_from(cli).is_err());
+    }
+
+    #[test]
+    fn test_restart() {
+        let mut args = Args::try_parse_from(["rex", "--restart", "cargo run"]).unwrap();
+        args.validate().unwrap();
+        assert!(args.abort_previous && args.run_at_start);
+
+        let mut args = Args::try_parse_from(["rex", "--restart", "cargo run {file}"]).unwrap();
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn test_nice_range() {
+        let args = Args::try_parse_from(["rex", "--nice", "-5", "make"]).unwrap();
+        assert_eq!(args.nice, Some(-5));
+        assert!(Args::try_parse_from(["rex", "--nice", "20", "make"]).is_err());
+        assert!(Args::try_parse_from(["rex", "--nice", "-21", "make"]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_regex_names_flag() {
+        let mut args = Args::try_parse_from(["rex", "-r", "(", "make"]).unwrap();
+        let error = args.validate().unwrap_err().to_string();
+        assert!(error.contains("-r/--regex: ("), "{error}");
+
+        let mut args = Args::try_parse_from(["rex", "-r", "ok", "-R", "[", "make"]).unwrap();
+        let error = args.validate().unwrap_err().to_string();
+        assert!(error.contains("-R/--ignored-regex: ["), "{error}");
+    }
+
+    #[test]
+    fn test_platform_command() {
+        let mut args = Args::try_parse_from([
+            "rex",
+            "--command-unix",
+            "ls {file}",
+            "--command-windows",
+            "dir {file}",
+            "echo",
+        ])
+        .unwrap();
+        args.validate().unwrap();
+        #[cfg(windows)]
+        assert_eq!(args.command, vec!["dir {file}"]);
+        #[cfg(not(windows))]
+        assert_eq!(args.command, vec!["ls {file}"]);
+        assert!(!args.batch_exec);
+
+        // The default command is optional when the platform has its own
+        #[cfg(not(windows))]
+        let cli = ["rex", "--command-unix", "ls"];
+        #[cfg(windows)]
+        let cli = ["rex", "--command-windows", "dir"];
+        let mut args = Args::try_parse_from(cli).unwrap();
+        args.validate().unwrap();
+
+        #[cfg(not(windows))]
+        let cli = ["rex", "--command-windows", "dir", "ls"];
+        #[cfg(windows)]
+        let cli = ["rex", "--command-unix", "ls", "dir"];
+        let mut args = Args::try_parse_from(cli).unwrap();
+        args.validate().unwrap();
+        assert_eq!(args.command, vec![cli[3]]);
+    }
+
+    #[test]
+    fn test_poll_interval_minimum() {
+        let mut args = Args::try_parse_from(["rex", "--poll-interval", "0", "make"]).unwrap();
+        let error = args.validate().unwrap_err();
+        assert!(matches!(
+            error,
+            ProgramError::ArgumentError(ArgumentError::PollIntervalTooShort(
+                "--poll-interval",
+                0,
+                _
+            ))
+        ));
+        assert!(error.to_string().contains("the minimum is 10ms"), "{error}");
+
+        let mut args =
+            Args::try_parse_from(["rex", "--remote-poll-interval", "5", "make"]).unwrap();
+        assert!(args.validate().is_err());
+
+        let mut args = Args::try_parse_from(["rex", "--poll-interval", "10", "make"]).unwrap();
+        args.validate().unwrap();
+    }
+
+    #[test]
+    fn test_escaped_placeholders() {
+        let mut args = Args::try_parse_from(["rex", "tmpl -o {{file}} {files}"]).unwrap();
+        args.validate().unwrap();
+        assert!(args.batch_exec);
+        assert!(has_placeholder(&args.command[0], FILES_SUBSTITUTION));
+        assert!(!has_placeholder(&args.command[0], FILE_SUBSTITUTION));
+
+        let (placeholders, values) = ([FILE_SUBSTITUTION, LABEL_SUBSTITUTION], ["a.rs", "web"]);
+        let render = |text| Template::parse(text, &placeholders).render(&values);
+        assert_eq!(render("{file} {{file}} {label}"), "a.rs {file} web");
+        assert_eq!(render("{{{file}}} {{x}}"), "{{file}} {{x}}");
+    }
+
+    /// Substitution searching the placeholders at every run, as done before
+    /// templates were parsed once
+    fn substitute(command: &str, values: &[(&str, &str)]) -> String {
+        let mut substituted = String::with_capacity(command.len());
+        let mut rest = command;
+        'next: while let Some(c) = rest.chars().next() {
+            for (placeholder, value) in values {
+                if let Some(after) = rest.strip_prefix(&format!("{{{placeholder}}}")) {
+                    substituted.push_str(placeholder);
+                    rest = after;
+                    continue 'next;
+                }
+                if let Some(after) = rest.strip_prefix(placeholder) {
+                    substituted.push_str(value);
+                    rest = after;
+                    continue 'next;
+                }
+            }
+            substituted.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        substituted
+    }
+
+    #[test]
+    fn test_template_matches_substitution() {
+        let placeholders = [FILES_SUBSTITUTION, FILE_SUBSTITUTION, DIR_SUBSTITUTION];
+        let values = ["a.rs b.rs", "src/a.rs", "src"];
+        let pairs: Vec<(&str, &str)> = placeholders.into_iter().zip(values).collect();
+        for text in [
+            "",
+            "make",
+            "{file}",
+            "cargo test --test {file} -- {files} {{file}}",
+            "{dir}/{file}{file}é{{{files}}}{",
+            "cp {file} {dir}/backup-{{dir}}}",
+        ] {
+            let template = Template::parse(text, &placeholders);
+            for _ in 0..1000 {
+                let rendered = template.render(&values);
+                assert_eq!(rendered, substitute(text, &pairs), "{text:?}");
+                // Allocated once, to the exact size
+                assert_eq!(rendered.capacity(), rendered.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_per_file_placeholders() {
+        let mut args = Args::try_parse_from(["rex", "convert {dir}/{basename}.{ext}"]).unwrap();
+        args.validate().unwrap();
+        assert!(!args.batch_exec);
+
+        let mut args = Args::try_parse_from(["rex", "cp {files} {dir}"]).unwrap();
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn test_duration_thresholds() {
+        let args = Args::try_parse_from(["rex", "make"]).unwrap();
+        assert_eq!(args.duration_thresholds.fast, Duration::from_secs(1));
+        assert_eq!(args.duration_thresholds.slow, Duration::from_secs(10));
+
+        let args =
+            Args::try_parse_from(["rex", "--duration-thresholds", "200,500", "make"]).unwrap();
+        assert_eq!(args.duration_thresholds.slow, Duration::from_millis(500));
+        assert!(Args::try_parse_from(["rex", "--duration-thresholds", "500", "make"]).is_err());
+        assert!(Args::try_parse_from(["rex", "--duration-thresholds", "5,1", "make"]).is_err());
+    }
+
+    #[test]
+    fn test_ignore_during_run_scope() {
+        let args = Args::try_parse_from(["rex", "--ignore-during-run", "make"]).unwrap();
+        assert_eq!(args.ignore_during_run, Some(None));
+        assert_eq!(args.command, vec!["make"]);
+
+        let args = Args::try_parse_from(["rex", "--ignore-during-run=target", "make"]).unwrap();
+        assert_eq!(args.ignore_during_run, Some(Some("target".to_string())));
+    }
+}
@@ -1,18 +1,70 @@
 use crate::command::exit_code::ExitCode;
+use notify::EventKind;
 
 #[derive(Debug)]
 pub enum ExecMessage {
     Start(ExecStart),
     Output(ExecOutput),
+    Running(ExecRunning),
+    Error(ExecError),
     Finish(ExecCode),
 }
 
+/// Kind of change that happened to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl From<EventKind> for ChangeKind {
+    fn from(kind: EventKind) -> Self {
+        match kind {
+            EventKind::Create(_) => Self::Created,
+            EventKind::Remove(_) => Self::Deleted,
+            _ => Self::Modified,
+        }
+    }
+}
+
+impl ChangeKind {
+    /// Combines a pending change with a newer change of the same file
+    pub fn merge(self, newer: Self) -> Self {
+        match (self, newer) {
+            // A file created then modified is still a new file
+            (Self::Created, Self::Modified) => Self::Created,
+            (_, newer) => newer,
+        }
+    }
+
+    /// Lowercase name of the change, e.g. for the REX_EVENT variable
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Modified => "modified",
+            Self::Deleted => "deleted",
+        }
+    }
+}
+
+/// A file associated with a run
+#[derive(Debug)]
+pub struct ExecFile {
+    /// Name of the file
+    pub name: String,
+    /// What happened to the file
+    pub kind: ChangeKind,
+}
+
 #[derive(Debug)]
 pub struct ExecStart {
     /// ID of the command being run
     pub command_number: usize,
     /// List of files associated with the run
-    pub files: Vec<String>,
+    pub files: Vec<ExecFile>,
+    /// Label(s) of the watches the files belong to
+    pub label: Option<String>,
 }
 
 #[derive(Debug)]
@@ -25,6 +77,22 @@ pub struct ExecOutput {
     pub stderr: Option<String>,
 }
 
+#[derive(Debug)]
+pub struct ExecRunning {
+    /// ID of the command being run
+    pub command_number: usize,
+    /// How long the command has been running
+    pub elapsed: std::time::Duration,
+}
+
+#[derive(Debug)]
+pub struct ExecError {
+    /// ID of the command that could not run
+    pub command_number: usize,
+    /// Why it could not run, e.g. the shell is missing
+    pub error: String,
+}
+
 #[derive(Debug)]
 pub struct ExecCode {
     /// ID of the command being run
@@ -33,4 +101,6 @@ pub struct ExecCode {
     pub exit_code: ExitCode,
     /// Total execution duration (measured in the worker thread)
     pub duration: Option<std::time::Duration>,
+    /// The command was stopped by rex, e.g. to restart it with newer changes
+    pub stopped: bool,
 }
@@ -1,4 +1,5 @@
-use colored::Colorize;
+use crate::tui::theme::{Theme, Themed};
+use colored::{ColoredString, Colorize};
 use std::process::ExitStatus;
 
 pub type ExitCode = Option<i32>;
@@ -14,14 +15,68 @@ pub fn get_exit_code(status: ExitStatus) -> ExitCode {
     status.code()
 }
 
-pub fn get_exit_code_string(exit_code: ExitCode) -> String {
-    if let Some(c) = exit_code {
-        match c {
-            0 => format!("{:<3}", "✔️".green()),
-            130 => format!("{:<3}", "130".yellow()),
-            c => format!("{c:<3}").red().to_string(),
+/// Describes why a command was terminated by a signal, for the signals that
+/// point at a resource limit or a crash
+#[cfg(unix)]
+pub fn get_exit_reason(exit_code: ExitCode) -> Option<&'static str> {
+    match exit_code? - 128 {
+        libc::SIGXCPU => Some("CPU time limit exceeded"),
+        libc::SIGXFSZ => Some("file size limit exceeded"),
+        libc::SIGSEGV => Some("segmentation fault"),
+        libc::SIGBUS => Some("bus error"),
+        libc::SIGABRT => Some("aborted"),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn get_exit_reason(_exit_code: ExitCode) -> Option<&'static str> {
+    None
+}
+
+/// Exit code of rex for the exit code of its last command: 0 if no command
+/// finished, 1 if the exit code of the command is unknown
+pub fn process_exit_code(last: Option<ExitCode>) -> i32 {
+    match last {
+        None => 0,
+        Some(code) => code.unwrap_or(1),
+    }
+}
+
+pub fn get_exit_code_string(exit_code: ExitCode, theme: &Theme) -> String {
+    format!("{:<3}", exit_code_label(exit_code, theme))
+}
+
+/// Replaces the exit code of a command stopped for a restart, with --restart
+pub fn get_restarted_string(theme: &Theme) -> String {
+    format!("{:<3}", "↻".themed(theme.dim))
+}
+
+/// Exit code as shown in the UI, in the colors of the theme
+fn exit_code_label(exit_code: ExitCode, theme: &Theme) -> ColoredString {
+    match exit_code {
+        Some(0) => "✔️".themed(theme.success),
+        Some(130) => "130".themed(theme.warning),
+        Some(c) => format!("{c:<3}").themed(theme.failure),
+        None => "?? ".themed(theme.unknown).bold(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use colored::Color;
+
+    #[test]
+    fn test_exit_code_colors() {
+        let theme = Theme::default();
+        assert_eq!(exit_code_label(Some(0), &theme).fgcolor, Some(Color::Green));
+        assert_eq!(exit_code_label(Some(2), &theme).fgcolor, Some(Color::Red));
+
+        let mono = Theme::mono();
+        for exit_code in [Some(0), Some(2), Some(130), None] {
+            assert_eq!(exit_code_label(exit_code, &mono).fgcolor, None);
         }
-    } else {
-        format!("{:<3}", "?? ".bold().bright_yellow())
+        assert_eq!(exit_code_label(Some(2), &mono).input, "2  ");
     }
 }
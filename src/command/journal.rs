@@ -0,0 +1,144 @@
+use crate::command::execution_report::ChangeKind;
+use crate::errors::{ProgramError, RuntimeError, runtime_error};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Unprocessed updates kept when the journal is opened, older ones are lost
+const MAX_JOURNAL_UPDATES: usize = 1000;
+
+/// File update written to the journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// Updated file
+    pub path: PathBuf,
+    /// Top level watch the update was reported from
+    pub watch: PathBuf,
+    /// Kind of change
+    pub kind: ChangeKind,
+}
+
+/// Journal of the file updates queued for the command, for --journal.
+/// An update is appended when it is queued, and marked as processed once a
+/// command started for its file, so that the updates a stopped rex did not
+/// handle can be replayed at the next start.
+///
+/// One line per record, with tab separated fields:
+/// `update <kind> <watch> <path>` or `run <path>`
+pub struct Journal {
+    file: File,
+}
+
+impl Journal {
+    /// Opens the journal and returns the updates that were never processed.
+    /// The journal is rewritten with only those, so that it does not grow
+    /// forever
+    pub fn open(path: &Path) -> Result<(Self, Vec<JournalEntry>), ProgramError> {
+        let error = |e: std::io::Error| {
+            runtime_error!(FileError, path.display().to_string(), e.to_string())
+        };
+        let pending = match std::fs::read_to_string(path) {
+            Ok(content) => unprocessed(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(error(e)),
+        };
+        let mut file = File::create(path).map_err(error)?;
+        for entry in &pending {
+            file.write_all(update_line(entry).as_bytes()).map_err(error)?;
+        }
+        let file = OpenOptions::new().append(true).open(path).map_err(error)?;
+        Ok((Self { file }, pending))
+    }
+
+    /// Appends a queued update
+    pub fn record_update(&mut self, entry: &JournalEntry) {
+        self.append(&update_line(entry));
+    }
+
+    /// Marks the updates of a file as processed
+    pub fn record_run(&mut self, path: &Path) {
+        self.append(&format!("run\t{}\n", path.display()));
+    }
+
+    fn append(&mut self, line: &str) {
+        if let Err(e) = self.file.write_all(line.as_bytes()) {
+            log::warn!("Could not write to the journal: {e}");
+        }
+    }
+}
+
+/// Formats an update as a journal line
+fn update_line(entry: &JournalEntry) -> String {
+    format!("update\t{}\t{}\t{}\n", entry.kind.name(), entry.watch.display(), entry.path.display())
+}
+
+/// Lists the updates of a journal without a later run of their file, oldest
+/// first. An update of a file replaces its previous unprocessed update
+fn unprocessed(content: &str) -> Vec<JournalEntry> {
+    let mut pending: Vec<JournalEntry> = Vec::new();
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[..] {
+            ["update", kind, watch, path] => {
+                let kind = match kind {
+                    "created" => ChangeKind::Created,
+                    "deleted" => ChangeKind::Deleted,
+                    _ => ChangeKind::Modified,
+                };
+                let path = PathBuf::from(path);
+                let kind = match pending.iter().position(|e| e.path == path) {
+                    Some(i) => pending.remove(i).kind.merge(kind),
+                    None => kind,
+                };
+                pending.push(JournalEntry { path, watch: watch.into(), kind });
+            }
+            ["run", path] => pending.retain(|e| e.path != Path::new(path)),
+            _ => log::warn!("Skipping invalid journal line: {line:?}"),
+        }
+    }
+    let skipped = pending.len().saturating_sub(MAX_JOURNAL_UPDATES);
+    pending.split_off(skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unprocessed_updates() {
+        let content = "update\tmodified\t/w\t/w/a.rs\n\
+                       update\tcreated\t/w\t/w/b.rs\n\
+                       run\t/w/a.rs\n\
+                       update\tmodified\t/w\t/w/b.rs\n\
+                       garbage\n\
+                       update\tdeleted\t/w\t/w/c.rs\n";
+        let pending = unprocessed(content);
+        let entry = |path: &str, kind| JournalEntry { path: path.into(), watch: "/w".into(), kind };
+        assert_eq!(
+            pending,
+            [entry("/w/b.rs", ChangeKind::Created), entry("/w/c.rs", ChangeKind::Deleted)]
+        );
+    }
+
+    #[test]
+    fn test_journal_compacted_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+        let entry = |name: &str| JournalEntry {
+            path: dir.path().join(name),
+            watch: dir.path().into(),
+            kind: ChangeKind::Modified,
+        };
+
+        let (mut journal, pending) = Journal::open(&path).unwrap();
+        assert!(pending.is_empty());
+        journal.record_update(&entry("a.rs"));
+        journal.record_update(&entry("b.rs"));
+        journal.record_run(&entry("a.rs").path);
+        drop(journal);
+
+        let (_, pending) = Journal::open(&path).unwrap();
+        assert_eq!(pending, [entry("b.rs")]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+}
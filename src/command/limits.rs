@@ -0,0 +1,75 @@
+use std::process::Command;
+
+/// Lowers a resource limit of the current process, never raising the current
+/// hard limit.
+#[cfg(unix)]
+macro_rules! set_rlimit {
+    ($resource:expr, $soft:expr, $hard:expr) => {
+        let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        if unsafe { libc::getrlimit($resource, &mut limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        limit.rlim_max = limit.rlim_max.min($hard as libc::rlim_t);
+        limit.rlim_cur = limit.rlim_max.min($soft as libc::rlim_t);
+        if unsafe { libc::setrlimit($resource, &limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    };
+}
+
+/// Resource limits applied to spawned commands
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ResourceLimits {
+    /// Maximum size of the virtual memory, in bytes
+    pub memory: Option<u64>,
+    /// Maximum CPU time, in seconds
+    pub cpu_time: Option<u64>,
+    /// Scheduling priority (niceness)
+    pub nice: Option<i32>,
+}
+
+impl ResourceLimits {
+    /// Checks if no limit is configured
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_none() && self.cpu_time.is_none() && self.nice.is_none()
+    }
+
+    /// Configures the command so that the limits are applied to the child
+    /// before it executes
+    #[cfg(unix)]
+    pub fn apply(&self, command: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        if self.is_empty() {
+            return;
+        }
+        let limits = *self;
+        // SAFETY: setrlimit/getrlimit/setpriority are plain syscalls and the closure
+        // does not allocate.
+        unsafe {
+            command.pre_exec(move || limits.set());
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(&self, _command: &mut Command) {}
+
+    /// Sets the limits on the current process
+    #[cfg(unix)]
+    fn set(&self) -> std::io::Result<()> {
+        if let Some(memory) = self.memory {
+            set_rlimit!(libc::RLIMIT_AS, memory, memory);
+        }
+        if let Some(cpu_time) = self.cpu_time {
+            // Keep the hard limit one second above, so that the child receives
+            // SIGXCPU instead of SIGKILL
+            set_rlimit!(libc::RLIMIT_CPU, cpu_time, cpu_time.saturating_add(1));
+        }
+        if let Some(nice) = self.nice
+            && unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::io::{BufRead, ErrorKind, Result};
+
+/// Iterator over the lines of a reader, like `BufRead::lines`, except that
+/// lines longer than a maximum length are split into chunks, so that a
+/// command printing a huge line without newline does not exhaust the memory.
+/// Chunks never split a UTF-8 character, and invalid UTF-8 is replaced with
+/// U+FFFD rather than failing the read
+pub struct LineChunks<R> {
+    reader: R,
+    /// Maximum length of a line or chunk, in bytes
+    max_len: usize,
+    /// Bytes of the current line read so far
+    pending: Vec<u8>,
+}
+
+impl<R: BufRead> LineChunks<R> {
+    /// Reads lines of at most `max_len` bytes. 0 does not limit the length
+    pub fn new(reader: R, max_len: usize) -> Self {
+        let max_len = if max_len == 0 { usize::MAX } else { max_len };
+        Self { reader, max_len, pending: Vec::new() }
+    }
+
+    /// Returns the pending bytes as a string. For a chunk, the bytes of a
+    /// character cut at the end are kept for the next chunk
+    fn take_pending(&mut self, chunk: bool) -> String {
+        let mut bytes = std::mem::take(&mut self.pending);
+        if chunk
+            && let Err(e) = std::str::from_utf8(&bytes)
+            && e.error_len().is_none()
+        {
+            // Only the end is cut if the rest is valid, but keep at most
+            // the 3 first bytes of a character, garbage is not carried over
+            let cut = e.valid_up_to().max(bytes.len().saturating_sub(3));
+            self.pending = bytes.split_off(cut);
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl<R: BufRead> Iterator for LineChunks<R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
+            };
+            // End of the stream, with maybe a last line without newline
+            if available.is_empty() {
+                if self.pending.is_empty() {
+                    return None;
+                }
+                return Some(Ok(self.take_pending(false)));
+            }
+
+            let room = self.max_len - self.pending.len();
+            let window = &available[..available.len().min(room)];
+            if let Some(i) = window.iter().position(|&b| b == b'\n') {
+                self.pending.extend_from_slice(&window[..i]);
+                self.reader.consume(i + 1);
+                return Some(Ok(self.take_pending(false)));
+            }
+            let read = window.len();
+            self.pending.extend_from_slice(window);
+            self.reader.consume(read);
+            if self.pending.len() >= self.max_len {
+                // A newline right after a full chunk ends the line, it does
+                // not start an empty one
+                if let Ok([b'\n', ..]) = self.reader.fill_buf() {
+                    self.reader.consume(1);
+                    return Some(Ok(self.take_pending(false)));
+                }
+                return Some(Ok(self.take_pending(true)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor, Read, Write};
+
+    #[test]
+    fn test_lines() {
+        let lines: Vec<String> =
+            LineChunks::new(Cursor::new("a\nbc\r\n\nlast"), 0).map(Result::unwrap).collect();
+        assert_eq!(lines, ["a", "bc\r", "", "last"]);
+    }
+
+    #[test]
+    fn test_long_line_is_chunked() {
+        // 10 MiB without any newline, read through a small buffer
+        let stream = std::io::repeat(b'a').take(10 * 1024 * 1024).chain(Cursor::new("\nend\n"));
+        let reader = BufReader::with_capacity(4096, stream);
+        let mut count = 0;
+        let mut last = String::new();
+        for chunk in LineChunks::new(reader, 64 * 1024) {
+            let chunk = chunk.unwrap();
+            assert!(chunk.len() <= 64 * 1024);
+            count += 1;
+            last = chunk;
+        }
+        assert_eq!(count, 161);
+        assert_eq!(last, "end");
+    }
+
+    #[test]
+    fn test_chunks_keep_characters_whole() {
+        let mut stream = Vec::new();
+        write!(stream, "aéé").unwrap();
+        let chunks: Vec<String> =
+            LineChunks::new(Cursor::new(stream), 2).map(Result::unwrap).collect();
+        assert_eq!(chunks, ["a", "é", "é"]);
+    }
+
+    #[test]
+    fn test_invalid_utf8_replaced() {
+        let stream = b"ok\n\xffbad\xfe\nend\xc3".to_vec();
+        let lines: Vec<String> =
+            LineChunks::new(Cursor::new(stream), 0).map(Result::unwrap).collect();
+        assert_eq!(lines, ["ok", "\u{fffd}bad\u{fffd}", "end\u{fffd}"]);
+
+        let chunks: Vec<String> = LineChunks::new(Cursor::new(b"a\xffb\xffc".to_vec()), 2)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(chunks, ["a\u{fffd}", "b\u{fffd}", "c"]);
+    }
+}
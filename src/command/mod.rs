@@ -1,7 +1,15 @@
 pub mod execution_report;
 pub mod exit_code;
+pub mod journal;
+pub mod limits;
+pub mod line_chunks;
+pub mod output_tracker;
 pub mod queue;
 pub mod queue_message;
+pub mod queue_status;
+pub mod queues;
+pub mod snapshots;
 
-pub use queue::Queue;
+pub use queue::{Queue, QueueHandle};
 pub use queue_message::QueueMessage;
+pub use queues::Queues;
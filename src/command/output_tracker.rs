@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long after a command finished we still consider updates as its outputs
+const OUTPUT_WINDOW: Duration = Duration::from_millis(500);
+/// How long a learned output stays ignored after it was learned
+const OUTPUT_TTL: Duration = Duration::from_secs(10);
+
+/// Learns which files are written by the command, so that they do not trigger
+/// it again.
+/// Files updated shortly after a command finished are considered as outputs
+/// and ignored for a while. Files updated while it runs are not learned, as
+/// they may be sources saved during a long run.
+#[derive(Debug, Default)]
+pub struct OutputTracker {
+    /// Last time a command was seen running
+    last_active: Option<Instant>,
+    /// Learned outputs, with the time they were learned
+    outputs: HashMap<PathBuf, Instant>,
+}
+
+impl OutputTracker {
+    /// Records that a command is running at the given time
+    pub fn update(&mut self, running: bool, now: Instant) {
+        if running {
+            self.last_active = Some(now);
+        }
+        self.outputs.retain(|_, seen| now.duration_since(*seen) < OUTPUT_TTL);
+    }
+
+    /// Checks if an updated file is an output of the command, learning it if
+    /// it was updated right after a run. Later updates of a learned output
+    /// do not make it last longer
+    pub fn is_output(&mut self, file: &Path, running: bool, now: Instant) -> bool {
+        self.update(running, now);
+        if self.outputs.contains_key(file) {
+            return true;
+        }
+
+        let in_window =
+            !running && self.last_active.is_some_and(|t| now.duration_since(t) < OUTPUT_WINDOW);
+        if in_window {
+            self.outputs.insert(file.to_path_buf(), now);
+        }
+        in_window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outputs_expire() {
+        let mut tracker = OutputTracker::default();
+        let start = Instant::now();
+        let file = Path::new("target/out.o");
+
+        assert!(!tracker.is_output(file, false, start));
+        // Files saved during a run are not learned
+        assert!(!tracker.is_output(Path::new("src/lib.rs"), true, start));
+        // The command finished
+        assert!(tracker.is_output(file, false, start + OUTPUT_WINDOW / 2));
+        // Learned outputs are still ignored once the window is over
+        let later = start + OUTPUT_WINDOW * 2;
+        assert!(tracker.is_output(file, false, later));
+        assert!(!tracker.is_output(Path::new("src/main.rs"), false, later));
+        assert!(!tracker.is_output(Path::new("src/lib.rs"), false, later));
+
+        // Hits do not refresh the learned outputs
+        assert!(!tracker.is_output(file, false, start + OUTPUT_WINDOW / 2 + OUTPUT_TTL));
+    }
+}
@@ -1,8 +1,9 @@
 use anyhow::Result;
-use std::collections::HashSet;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
 use std::thread::JoinHandle;
 
 #[cfg(unix)]
@@ -14,43 +15,168 @@ use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-const MAX_CONCURRENT_WORKERS: usize = 3;
+/// Placeholders substituted in the values of the env variables
+const ENV_PLACEHOLDERS: [&str; 7] = [
+    FILES_SUBSTITUTION,
+    FILE_SUBSTITUTION,
+    BASENAME_SUBSTITUTION,
+    DIR_SUBSTITUTION,
+    EXT_SUBSTITUTION,
+    LABEL_SUBSTITUTION,
+    COMMAND_NUMBER_SUBSTITUTION,
+];
+/// Placeholders substituted in the command: the command number is only
+/// substituted in env variables
+const COMMAND_PLACEHOLDERS: &[&str] = ENV_PLACEHOLDERS.split_last().unwrap().1;
+/// Exit code of the shell when the program to run does not exist
+const COMMAND_NOT_FOUND: i32 = 127;
+/// Longest wait for the aborted commands to stop before running new ones
+const ABORT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time given to an aborted or timed out command to exit after SIGTERM,
+/// before SIGKILL
+#[cfg(unix)]
+const KILL_GRACE: Duration = Duration::from_millis(500);
+/// First delay before running a failed command again with --until-success
+const RETRY_BACKOFF_MIN: Duration = Duration::from_millis(250);
+/// Longest delay before running a failed command again with --until-success
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(10);
 
 // Same module
 use crate::command::QueueMessage;
-use crate::command::execution_report::ExecOutput;
-use crate::command::execution_report::{ExecCode, ExecMessage, ExecStart};
+use crate::command::execution_report::{
+    ChangeKind, ExecCode, ExecError, ExecFile, ExecMessage, ExecOutput, ExecRunning, ExecStart,
+};
 use crate::command::exit_code;
+use crate::command::journal::{Journal, JournalEntry};
+use crate::command::limits::ResourceLimits;
+use crate::command::line_chunks::LineChunks;
+use crate::command::output_tracker::OutputTracker;
+use crate::command::queue_status::QueueStatus;
+use crate::command::snapshots::Snapshots;
 
-use crate::args::{Args, FILE_SUBSTITUTION, FILES_SUBSTITUTION};
+use crate::args::{
+    Args, BASENAME_SUBSTITUTION, COMMAND_NUMBER_SUBSTITUTION, DIR_SUBSTITUTION, EXT_SUBSTITUTION,
+    FILE_SUBSTITUTION, FILES_SUBSTITUTION, LABEL_SUBSTITUTION, Template, TimeoutSignal,
+    has_placeholder,
+};
 use crate::errors::{ArgumentError, ProgramError, RuntimeError, arg_error, runtime_error};
 use crate::event::Event;
+use crate::files::utils::{canonical_path, is_empty_file};
 
 use super::exit_code::ExitCode;
 
+/// Options applied to every spawned command
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RunOptions {
+    /// Do we pipe the command outputs into reports
+    pub pipe_output: bool,
+    /// Do we keep the \r of \r\n line endings in the outputs
+    pub keep_crlf: bool,
+    /// Longer output lines are split, in bytes. 0 does not limit them
+    pub max_line_length: usize,
+    /// Does the command use the terminal directly
+    pub interactive: bool,
+    /// Resource limits for the command
+    pub limits: ResourceLimits,
+    /// The command is stopped after this duration
+    pub timeout: Option<Duration>,
+    /// Signal stopping the command at the timeout
+    pub timeout_signal: TimeoutSignal,
+}
+
 macro_rules! send_msg_unchecked {
     ($tx:ident, $q_msg:expr) => {
         let _ = $tx.send(Event::Exec($q_msg));
     };
 }
 
+/// A file picked up from the queue for execution
+#[derive(Debug, Clone)]
+struct QueuedFile {
+    /// Updated file
+    path: PathBuf,
+    /// Top level watch the update was reported from
+    watch: PathBuf,
+    /// Kind of change
+    kind: ChangeKind,
+}
+
+/// A command running in a worker thread
+struct Worker {
+    /// Thread running the command, returns its exit code
+    handle: JoinHandle<ExitCode>,
+    /// Files the command was run for
+    batch: Vec<QueuedFile>,
+    /// Was the command told to stop
+    aborted: bool,
+    /// ID of the command being run
+    command_number: usize,
+    /// When the command was spawned
+    started: Instant,
+    /// Did we already warn that the command is running for long
+    warned: bool,
+    /// Disconnected once the worker thread is done with the command
+    done: Receiver<()>,
+}
+
+/// Environment variable set for the commands of a rerun. The rerun may be
+/// split into several chunks, they all get the variable
+struct RerunEnv {
+    /// Variable name and value
+    var: (String, String),
+    /// Files of the rerun not run yet
+    files: HashSet<PathBuf>,
+}
+
+/// Pending run of a command that failed (--until-success)
+struct Retry {
+    /// When to run the command again
+    at: Instant,
+    /// Files of the failed run(s)
+    files: Vec<QueuedFile>,
+}
+
+/// Long-lived command receiving updated files on its stdin (feed mode)
+struct FeedProcess {
+    /// stdin of the child, kept open between updates
+    stdin: ChildStdin,
+    /// Worker waiting for the child to exit
+    worker: JoinHandle<()>,
+}
+
 pub struct Queue {
     /// Prepared command to which we need to add the args / env variables
     command_base: Command,
+    /// Env variables with placeholders in their value, substituted for each
+    /// command
+    env_templates: Vec<(String, Template)>,
     /// Command to execute, to pass to the shell (i.e. sh -c "command to execute
     /// with args")
-    command: String,
+    command: Template,
     /// Files that have been updated - pending command execution
     /// First pathbuf is the file, second is the watched file/dir
-    files: HashSet<(PathBuf, PathBuf)>,
-    /// Do we keep the command outputs
-    pipe_command_output: bool,
+    files: HashMap<(PathBuf, PathBuf), ChangeKind>,
+    /// Watched files/dirs
+    watches: Vec<PathBuf>,
+    /// Labels of the watched files/dirs
+    labels: HashMap<PathBuf, String>,
+    /// Options applied to every spawned command
+    run_options: RunOptions,
     /// Do we configure a particular working dir for commands
     working_dir: Option<String>,
     /// Execution mode
     batch_exec: bool,
+    /// Do we substitute absolute paths for the files
+    abs_paths: bool,
+    /// Maximum number of files per command in batch mode
+    chunk_size: Option<usize>,
+    /// Do we collect files like in batch mode, then run one command per file,
+    /// one after the other
+    foreach: bool,
+    /// Chunks of a batch waiting for a free worker
+    pending_chunks: VecDeque<Vec<QueuedFile>>,
     /// Execute commands also if files are deleted
     deleted_files: bool,
     /// Handle to receive QueueMessages
@@ -59,21 +185,111 @@ pub struct Queue {
     report_tx: Sender<Event>,
     /// Timestamp of the last file update
     last_update: Option<std::time::Instant>,
-    /// Total command count.
-    command_count: usize,
+    /// Timestamp of the last file deletion, with --delete-debounce
+    last_delete: Option<Instant>,
+    /// Settle window for deleted files, instead of the normal one
+    delete_debounce: Option<Duration>,
+    /// Time without file updates before running the command
+    debounce: Duration,
+    /// Time to wait once updates settled, before running the command
+    delay: Duration,
+    /// End of the current --delay, once updates settled
+    delay_until: Option<Instant>,
+    /// Longest random delay before a command starts
+    jitter: Option<Duration>,
+    /// Run the pending files without waiting for more updates
+    run_now: bool,
+    /// Files waiting for a command, as last reported in the status
+    pending_files: usize,
+    /// Name of the --task the queue runs the command of
+    task: Option<String>,
     /// Do we abort previous commands?
     abort_previous: bool,
+    /// Do we keep running commands whose files are the same as the new batch
+    abort_on_new: bool,
     /// Abort signal for workers
     abort: Arc<AtomicBool>,
-    /// worker handles
-    workers: Vec<JoinHandle<()>>,
+    /// Running workers
+    workers: Vec<Worker>,
+    /// Maximum number of commands running at the same time
+    max_workers: usize,
+    /// Do we skip the files that are empty when the command runs
+    skip_empty: bool,
+    /// Do we keep a single command alive and feed files to its stdin
+    feed: bool,
+    /// Running command in feed mode
+    feed_process: Option<FeedProcess>,
+    /// Live state shared with the other threads
+    status: Arc<QueueStatus>,
+    /// Learns the outputs of the command to ignore them, if enabled
+    output_tracker: Option<OutputTracker>,
+    /// Do we run failed commands again until they succeed
+    until_success: bool,
+    /// Exit code of the last command that finished without being aborted,
+    /// passed to the next one in REX_PREV_EXIT
+    prev_exit_code: ExitCode,
+    /// Next run of a failed command
+    retry: Option<Retry>,
+    /// Delay before the next retry, grows while the command keeps failing
+    retry_backoff: Duration,
+    /// Running time after which we warn about a command (--warn-after)
+    warn_after: Option<Duration>,
+    /// Previous content of the updated files, to pass diffs to the command
+    snapshots: Option<Snapshots>,
+    /// Command to run instead when the command cannot start
+    fallback: Option<Template>,
+    /// Updates of the same file through different watches within this window
+    /// are reported only once
+    dedup_window: Duration,
+    /// Latest updated files, with the watch that reported them and when
+    recent_updates: HashMap<PathBuf, (PathBuf, Instant)>,
+    /// Files of the last started command
+    last_batch: Vec<QueuedFile>,
+    /// Journal of the file updates, with --journal
+    journal: Option<Journal>,
+    /// Environment variable of an 'e' rerun, until its files are all run
+    rerun_env: Option<RerunEnv>,
+}
+
+/// Handle to a started command queue
+pub struct QueueHandle {
+    /// Sends messages to the queue
+    pub tx: Sender<QueueMessage>,
+    /// Live state of the queue
+    pub status: Arc<QueueStatus>,
+    /// Thread running the queue
+    thread: JoinHandle<()>,
+}
+
+impl QueueHandle {
+    /// Tells if the queue thread is still running
+    pub fn is_alive(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    /// Stops the queue and waits for the running commands to be stopped, so
+    /// that none of them outlives rex
+    pub fn stop(&self) {
+        let _ = self.tx.send(QueueMessage::Abort);
+        let deadline = Instant::now() + ABORT_TIMEOUT + Duration::from_secs(1);
+        while self.is_alive() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
 }
 
 impl Queue {
-    pub fn start(
+    pub fn start(args: &Args, report_tx: Sender<Event>) -> Result<QueueHandle, ProgramError> {
+        Self::start_with_status(args, report_tx, Arc::new(QueueStatus::default()))
+    }
+
+    /// Starts a queue reporting in a status shared with other queues, so that
+    /// their commands are numbered together
+    pub fn start_with_status(
         args: &Args,
         report_tx: Sender<Event>,
-    ) -> Result<Sender<QueueMessage>, ProgramError> {
+        status: Arc<QueueStatus>,
+    ) -> Result<QueueHandle, ProgramError> {
         let (tx, rx) = crossbeam_channel::unbounded();
 
         // Parse the command and prep it
@@ -87,10 +303,10 @@ impl Queue {
             ));
         }
 
-        let shell_parts = shell_words::split(args.shell).map_err(|_| {
+        let shell_parts = shell_words::split(&args.shell).map_err(|_| {
             arg_error!(
                 CommandParseError,
-                args.shell.to_string(),
+                args.shell.clone(),
                 "Failed to parse shell command".to_string()
             )
         })?;
@@ -101,6 +317,7 @@ impl Queue {
         }
 
         // Env variables.
+        let mut env_templates = Vec::new();
         for env_var in &args.env {
             let mut parts = env_var.splitn(2, "=");
             let key = parts.next();
@@ -109,28 +326,103 @@ impl Queue {
             if key.is_none() {
                 return Err(arg_error!(InvalidEnvironmentVariable, env_var.to_owned()));
             }
-            command.env(key.unwrap(), value);
+            if ENV_PLACEHOLDERS.iter().any(|p| has_placeholder(value, p)) {
+                env_templates
+                    .push((key.unwrap().to_string(), Template::parse(value, &ENV_PLACEHOLDERS)));
+            } else {
+                command.env(key.unwrap(), value);
+            }
         }
 
+        // Labels are looked up by the canonical path reported with file updates
+        let labels = args
+            .watches
+            .iter()
+            .filter_map(|w| Some((canonical_path(&w.path).ok()?, w.label.clone()?)))
+            .collect();
+
+        let watches = args.watches.iter().filter_map(|w| canonical_path(&w.path).ok()).collect();
+
+        let (journal, unprocessed) = match &args.journal {
+            Some(path) => {
+                let (journal, unprocessed) = Journal::open(path)?;
+                (Some(journal), unprocessed)
+            }
+            None => (None, Vec::new()),
+        };
+
         let mut queue = Self {
             command_base: command,
-            command: args.command[0].clone(),
-            files: HashSet::new(),
-            pipe_command_output: !args.quiet,
+            env_templates,
+            command: Template::parse(&args.command[0], COMMAND_PLACEHOLDERS),
+            files: HashMap::new(),
+            watches,
+            labels,
+            run_options: RunOptions {
+                pipe_output: !args.quiet && !args.interactive,
+                keep_crlf: args.keep_crlf,
+                max_line_length: args.max_line_length,
+                interactive: args.interactive,
+                limits: ResourceLimits {
+                    memory: args.mem_limit.map(|mb| mb.saturating_mul(1024 * 1024)),
+                    cpu_time: args.cpu_time_limit,
+                    nice: args.nice,
+                },
+                timeout: args.timeout.map(Duration::from_millis),
+                timeout_signal: args.timeout_signal,
+            },
             working_dir: args.current_working_dir.clone(),
             batch_exec: args.batch_exec,
+            abs_paths: args.abs_paths,
+            chunk_size: args.chunk_size,
+            foreach: args.foreach,
+            pending_chunks: VecDeque::new(),
             deleted_files: args.deleted,
             rx,
             report_tx,
             last_update: None,
-            command_count: 0,
+            last_delete: None,
+            delete_debounce: args.delete_debounce.map(Duration::from_millis),
+            debounce: Duration::from_millis(args.debounce),
+            delay: Duration::from_millis(args.delay),
+            delay_until: None,
+            jitter: args.jitter.map(Duration::from_millis),
+            run_now: false,
+            pending_files: 0,
+            task: args.task_name.clone(),
             abort_previous: args.abort_previous,
+            abort_on_new: args.abort_on_new,
             abort: Arc::new(AtomicBool::new(false)),
-            workers: Vec::with_capacity(MAX_CONCURRENT_WORKERS),
+            workers: Vec::with_capacity(args.jobs),
+            max_workers: if args.interactive { 1 } else { args.jobs },
+            skip_empty: args.skip_empty,
+            feed: args.feed,
+            feed_process: None,
+            status,
+            output_tracker: args.auto_ignore_outputs.then(OutputTracker::default),
+            until_success: args.until_success,
+            prev_exit_code: None,
+            retry: None,
+            retry_backoff: RETRY_BACKOFF_MIN,
+            warn_after: args.warn_after.map(Duration::from_millis),
+            snapshots: args.diff.then(Snapshots::default),
+            fallback: args.fallback.as_deref().map(|f| Template::parse(f, COMMAND_PLACEHOLDERS)),
+            dedup_window: Duration::from_millis(args.dedup_window),
+            recent_updates: HashMap::new(),
+            last_batch: Vec::new(),
+            journal,
+            rerun_env: None,
         };
+        // Updates that no command ran for before rex stopped
+        let replayed = args.replay.unwrap_or(0).min(unprocessed.len());
+        for entry in unprocessed.into_iter().rev().take(replayed).rev() {
+            log::info!("Replaying the update of {:?} from the journal", entry.path);
+            queue.add_file(entry.path, entry.watch, entry.kind);
+        }
+        let status = queue.status.clone();
 
-        std::thread::spawn(move || queue.run());
-        Ok(tx)
+        let thread = spawn_named("rex-queue".into(), move || queue.run());
+        Ok(QueueHandle { tx, status, thread })
     }
 
     fn get_command(&self) -> Command {
@@ -142,7 +434,11 @@ impl Queue {
             }
         });
 
-        if self.pipe_command_output {
+        if self.run_options.interactive {
+            command.stdin(Stdio::inherit());
+            command.stdout(Stdio::inherit());
+            command.stderr(Stdio::inherit());
+        } else if self.run_options.pipe_output {
             command.stdout(Stdio::piped());
             command.stderr(Stdio::piped());
         } else {
@@ -159,6 +455,7 @@ impl Queue {
             match self.rx.recv_timeout(Duration::from_millis(100)) {
                 Ok(QueueMessage::Abort) => {
                     log::debug!("Command queue received abort");
+                    self.stop_commands();
                     break;
                 }
                 Ok(QueueMessage::RestartBackoff) => {
@@ -166,18 +463,58 @@ impl Queue {
                         self.last_update = Some(std::time::Instant::now());
                     }
                 }
-                Ok(QueueMessage::AddFile(p, watch)) => {
-                    let _ = self.files.insert((p, watch));
-                    self.last_update = Some(std::time::Instant::now());
+                Ok(QueueMessage::AddFile(p, watch, kind)) => {
+                    if self.is_duplicate(&p, &watch) {
+                        log::debug!("Ignoring {:?}: already reported by another watch", p);
+                    } else if let Some(tracker) = &mut self.output_tracker
+                        && tracker.is_output(&p, self.status.is_running(), Instant::now())
+                    {
+                        log::debug!("Ignoring {:?}: written by the command", p);
+                    } else {
+                        self.add_file(p, watch, ChangeKind::from(kind));
+                    }
                 }
                 Ok(QueueMessage::Clear) => {
                     self.abort_ongoing_commands_if_needed();
                     self.files.clear();
+                    self.pending_chunks.clear();
+                    self.retry = None;
+                    self.rerun_env = None;
+                    self.run_now = false;
                     self.last_update = None;
+                    self.last_delete = None;
+                    self.delay_until = None;
                 }
                 Ok(QueueMessage::AbortOngoingCommands) => {
                     self.abort_ongoing_commands();
                 }
+                Ok(QueueMessage::RunNow) => {
+                    if self.files.is_empty() {
+                        for w in &self.watches {
+                            self.files.insert((w.clone(), w.clone()), ChangeKind::Modified);
+                        }
+                    }
+                    if !self.files.is_empty() {
+                        self.run_now = true;
+                        self.last_update = Some(std::time::Instant::now());
+                    }
+                }
+                Ok(QueueMessage::Rerun) => {
+                    self.queue_last_batch();
+                }
+                Ok(QueueMessage::RerunWithEnv(key, value)) => {
+                    log::info!("Running the last command again with {key}={value}");
+                    let files = self.queue_last_batch();
+                    self.rerun_env = Some(RerunEnv { var: (key, value), files });
+                }
+                Ok(QueueMessage::DumpQueue(reply)) => {
+                    let _ = reply.send(self.pending_paths());
+                }
+                Ok(QueueMessage::Register(p, _)) => {
+                    if let Some(snapshots) = &mut self.snapshots {
+                        snapshots.record(&p);
+                    }
+                }
                 Err(RecvTimeoutError::Timeout) => {}
                 Err(e) => {
                     log::error!("Channel error: {e:?}");
@@ -185,12 +522,26 @@ impl Queue {
                 }
             }
             // remove finished workers
-            self.workers.retain(|w| !w.is_finished());
+            self.reap_workers();
+            if let Err(e) = self.warn_slow_workers() {
+                log::error!("Exec Tx Report Channel error: {e:?}");
+                return;
+            }
+            self.schedule_retry();
+            if let Err(e) = self.launch_pending_chunks() {
+                log::error!("Exec Tx Report Channel error: {e:?}");
+                return;
+            }
+            if let Some(tracker) = &mut self.output_tracker {
+                tracker.update(self.status.is_running(), Instant::now());
+            }
 
-            // See if we want to execute something
+            // See if we want to execute something. Updates already waiting in
+            // the channel are taken in first, so that they run together
             if let Some(t) = self.last_update
-                && t.elapsed() > std::time::Duration::from_millis(200)
-                && self.workers.len() < self.workers.capacity()
+                && self.rx.is_empty()
+                && (self.run_now || self.is_delay_over(t))
+                && (self.active_workers() < self.max_workers || self.aborts_previous())
             {
                 let tx_result = self.execute();
 
@@ -201,9 +552,209 @@ impl Queue {
 
                 if self.files.is_empty() {
                     self.last_update = None;
+                    self.last_delete = None;
+                    self.delay_until = None;
+                    self.run_now = false;
                 }
             }
+            let chunked: usize = self.pending_chunks.iter().map(Vec::len).sum();
+            let pending = self.files.len() + chunked;
+            self.status.replace_pending_files(self.pending_files, pending);
+            self.pending_files = pending;
+        }
+    }
+
+    /// Queues an updated file, to run the command once updates settle
+    fn add_file(&mut self, p: PathBuf, watch: PathBuf, kind: ChangeKind) {
+        if let Some(journal) = &mut self.journal {
+            journal.record_update(&JournalEntry { path: p.clone(), watch: watch.clone(), kind });
+        }
+        // A new change gets a fresh run, forget about the failed one
+        self.retry = None;
+        self.retry_backoff = RETRY_BACKOFF_MIN;
+        let key = (p, watch);
+        if kind == ChangeKind::Deleted && self.files.get(&key) == Some(&ChangeKind::Created) {
+            // Never seen by the command, e.g. an editor temp file
+            log::debug!("Dropping {:?}: created then deleted", key.0);
+            self.files.remove(&key);
+            if self.files.is_empty() {
+                self.last_update = None;
+                self.last_delete = None;
+                self.delay_until = None;
+                self.run_now = false;
+            }
+            return;
+        }
+        self.files.entry(key).and_modify(|k| *k = k.merge(kind)).or_insert(kind);
+        let now = Instant::now();
+        if kind == ChangeKind::Deleted && self.delete_debounce.is_some() {
+            // Deletes have their own settle window
+            self.last_delete = Some(now);
+            self.last_update.get_or_insert(now);
+        } else {
+            self.last_update = Some(now);
+        }
+    }
+
+    /// Variable of the pending rerun, if the batch is part of it. The
+    /// variable is dropped once all the files of the rerun are launched
+    fn rerun_var(&mut self, batch: &[QueuedFile]) -> Option<(String, String)> {
+        let rerun = self.rerun_env.as_mut()?;
+        let mut in_rerun = false;
+        for f in batch {
+            in_rerun |= rerun.files.remove(&f.path);
+        }
+        let var = in_rerun.then(|| rerun.var.clone());
+        if rerun.files.is_empty() {
+            self.rerun_env = None;
+        }
+        var
+    }
+
+    /// Queues the files of the last command to run now. The watched
+    /// files/dirs stand in if nothing ran yet. Returns the queued paths
+    fn queue_last_batch(&mut self) -> HashSet<PathBuf> {
+        let files = if self.last_batch.is_empty() {
+            self.watches
+                .iter()
+                .map(|w| QueuedFile {
+                    path: w.clone(),
+                    watch: w.clone(),
+                    kind: ChangeKind::Modified,
+                })
+                .collect()
+        } else {
+            self.last_batch.clone()
+        };
+        let mut paths = HashSet::new();
+        for f in files {
+            paths.insert(f.path.clone());
+            self.files
+                .entry((f.path, f.watch))
+                .and_modify(|k| *k = k.merge(f.kind))
+                .or_insert(f.kind);
+        }
+        self.run_now = true;
+        self.last_update = Some(Instant::now());
+        paths
+    }
+
+    /// Removes the finished workers, and keeps track of the failed runs if we
+    /// run commands until they succeed
+    fn reap_workers(&mut self) {
+        let finished: Vec<Worker> =
+            self.workers.extract_if(.., |w| w.handle.is_finished()).collect();
+
+        for worker in finished {
+            let exit_code = worker.handle.join().unwrap_or(None);
+            if worker.aborted {
+                continue;
+            }
+            self.prev_exit_code = exit_code;
+            if !self.until_success {
+                continue;
+            }
+            if exit_code == Some(0) {
+                log::debug!("Command succeeded, no more retries");
+                self.retry = None;
+                self.retry_backoff = RETRY_BACKOFF_MIN;
+            } else {
+                log::debug!("Command failed, running it again in {:?}", self.retry_backoff);
+                let retry = self.retry.get_or_insert_with(|| Retry {
+                    at: Instant::now() + self.retry_backoff,
+                    files: Vec::new(),
+                });
+                retry.files.extend(worker.batch);
+                self.retry_backoff = (self.retry_backoff * 2).min(RETRY_BACKOFF_MAX);
+            }
+        }
+    }
+
+    /// Checks if the updates settled and the --delay after them is over.
+    /// The delay starts the first time the updates are seen settled, later
+    /// updates do not push it back
+    fn is_delay_over(&mut self, last_update: Instant) -> bool {
+        if let Some(at) = self.delay_until {
+            return Instant::now() >= at;
+        }
+        if last_update.elapsed() < self.debounce || !self.deletes_settled() {
+            return false;
+        }
+        if self.delay.is_zero() {
+            return true;
+        }
+        self.delay_until = Some(Instant::now() + self.delay);
+        false
+    }
+
+    /// Checks if no file was deleted during the last --delete-debounce
+    fn deletes_settled(&self) -> bool {
+        match (self.last_delete, self.delete_debounce) {
+            (Some(t), Some(debounce)) => t.elapsed() > debounce,
+            _ => true,
+        }
+    }
+
+    /// Checks if a file update was just reported through another watch, e.g.
+    /// for overlapping watches. Remembers the update otherwise
+    fn is_duplicate(&mut self, file: &Path, watch: &Path) -> bool {
+        if self.dedup_window.is_zero() {
+            return false;
+        }
+        let now = Instant::now();
+        self.recent_updates
+            .retain(|_, (_, at)| now.duration_since(*at) < self.dedup_window);
+        match self.recent_updates.get(file) {
+            Some((first_watch, _)) if first_watch != watch => true,
+            _ => {
+                self.recent_updates.insert(file.to_path_buf(), (watch.to_path_buf(), now));
+                false
+            }
+        }
+    }
+
+    /// Files waiting for a run, including the chunks waiting for a worker
+    fn pending_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.files.keys().map(|(p, _)| p.clone()).collect();
+        paths.extend(self.pending_chunks.iter().flatten().map(|f| f.path.clone()));
+        paths.sort();
+        paths
+    }
+
+    /// Reports the commands running for longer than --warn-after, once each
+    fn warn_slow_workers(&mut self) -> Result<(), ProgramError> {
+        let Some(threshold) = self.warn_after else {
+            return Ok(());
+        };
+        for worker in self.workers.iter_mut().filter(|w| !w.warned) {
+            let elapsed = worker.started.elapsed();
+            if elapsed < threshold {
+                continue;
+            }
+            log::warn!("Command #{} still running after {elapsed:?}", worker.command_number + 1);
+            worker.warned = true;
+            self.report_tx
+                .send(Event::Exec(ExecMessage::Running(ExecRunning {
+                    command_number: worker.command_number,
+                    elapsed,
+                })))
+                .map_err(|e| runtime_error!(CommandExecutionError, e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Queues the files of the failed runs again once the backoff is over
+    fn schedule_retry(&mut self) {
+        let Some(retry) = self.retry.take_if(|r| r.at <= Instant::now()) else {
+            return;
+        };
+        for f in retry.files {
+            self.files
+                .entry((f.path, f.watch))
+                .and_modify(|k| *k = k.merge(f.kind))
+                .or_insert(f.kind);
         }
+        self.last_update = Some(Instant::now());
     }
 
     /// Aborts ongoing commands if the program is configured to do so
@@ -219,14 +770,57 @@ impl Queue {
     pub fn abort_ongoing_commands(&mut self) {
         // Abort previous commands if needed
         if !self.workers.is_empty() {
-            self.abort.store(true, Ordering::SeqCst);
-            // We could probably use a rendezvous channel or something like that to make
-            // sure the other threads have read the value.
-            std::thread::sleep(Duration::from_millis(100));
+            self.stop_workers();
         }
         self.abort.store(false, Ordering::SeqCst);
     }
 
+    /// Tells the running commands to stop, and waits for every worker to be
+    /// done with its command, so that the flag is not cleared before they
+    /// have seen it
+    fn stop_workers(&mut self) {
+        self.workers.iter_mut().for_each(|w| w.aborted = true);
+        self.abort.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + ABORT_TIMEOUT;
+        for worker in &self.workers {
+            if let Err(RecvTimeoutError::Timeout) = worker.done.recv_deadline(deadline) {
+                log::warn!("Command #{} did not stop in time", worker.command_number + 1);
+                break;
+            }
+        }
+    }
+
+    /// Stops all the commands when the queue stops, including the feed
+    /// command, so that they do not outlive rex
+    fn stop_commands(&mut self) {
+        self.stop_workers();
+        if let Some(feed) = self.feed_process.take() {
+            let deadline = Instant::now() + ABORT_TIMEOUT;
+            while !feed.worker.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    /// Tells if the pending files abort the running commands, instead of
+    /// waiting for a worker to be available
+    fn aborts_previous(&self) -> bool {
+        self.abort_previous && !(self.abort_on_new && self.is_running_same_files())
+    }
+
+    /// Number of workers running a command that was not aborted
+    fn active_workers(&self) -> usize {
+        self.workers.iter().filter(|w| !w.aborted).count()
+    }
+
+    /// Checks if a running command was started for exactly the pending files
+    fn is_running_same_files(&self) -> bool {
+        let pending: HashSet<&PathBuf> = self.files.keys().map(|(p, _)| p).collect();
+        self.workers.iter().filter(|w| !w.aborted).any(|w| {
+            w.batch.len() == pending.len() && w.batch.iter().all(|f| pending.contains(&f.path))
+        })
+    }
+
     /// Picks up the next file-batch and spawn a thread executing the
     /// command
     pub fn execute(&mut self) -> Result<(), ProgramError> {
@@ -239,97 +833,492 @@ impl Queue {
 
         // Remove deleted files unless we want them
         if !self.deleted_files {
-            self.files.retain(|(p, _)| p.exists());
+            self.files.retain(|(p, _), _| p.exists());
+        }
+        // Remove files that are still empty, deleted files are left alone
+        if self.skip_empty {
+            self.files.retain(|(p, _), _| !is_empty_file(p));
         }
 
         if self.files.is_empty() {
             return Ok(());
         }
 
-        self.abort_ongoing_commands_if_needed();
+        if self.aborts_previous() {
+            self.abort_ongoing_commands();
+            // Chunks that did not run yet are part of the new batch
+            for f in self.pending_chunks.drain(..).flatten() {
+                self.files
+                    .entry((f.path, f.watch))
+                    .and_modify(|k| *k = k.merge(f.kind))
+                    .or_insert(f.kind);
+            }
+        }
 
         // Choose arguments based on the placeholders
-        let p: Vec<PathBuf> = if !self.batch_exec {
-            let paths = self.files.iter().next().unwrap().clone();
-            self.files.remove(&paths);
-            vec![paths.0]
+        let batch: Vec<QueuedFile> = if !self.batch_exec && !self.foreach {
+            let paths = self.files.keys().next().unwrap().clone();
+            let kind = self.files.remove(&paths).unwrap();
+            vec![QueuedFile { path: paths.0, watch: paths.1, kind }]
         } else {
-            self.files.drain().map(|(p, _)| p).collect()
+            self.files
+                .drain()
+                .map(|((path, watch), kind)| QueuedFile { path, watch, kind })
+                .collect()
         };
-        assert!(!p.is_empty(), "p should not be empty. Files: {:?}, ", self.files);
+        assert!(!batch.is_empty(), "batch should not be empty. Files: {:?}, ", self.files);
+
+        // Whatever the events said, a file that is gone has been deleted
+        let batch: Vec<QueuedFile> = batch
+            .into_iter()
+            .map(|mut f| {
+                if !f.path.exists() {
+                    f.kind = ChangeKind::Deleted;
+                }
+                f
+            })
+            .collect();
+
+        // One file per chunk with --foreach
+        let chunk_size = if self.foreach { Some(1) } else { self.chunk_size };
+        if let Some(size) = chunk_size
+            && (self.batch_exec || self.foreach)
+        {
+            // Sorted so that chunks are predictable
+            let mut batch = batch;
+            batch.sort_by(|a, b| a.path.cmp(&b.path));
+            self.pending_chunks.extend(batch.chunks(size).map(<[QueuedFile]>::to_vec));
+            return self.launch_pending_chunks();
+        }
+
+        self.launch(batch)
+    }
+
+    /// Starts a command for each pending chunk, as long as workers are
+    /// available
+    fn launch_pending_chunks(&mut self) -> Result<(), ProgramError> {
+        // With --foreach, files run one after the other
+        let max_workers = if self.foreach { 1 } else { self.max_workers };
+        while self.active_workers() < max_workers
+            && let Some(chunk) = self.pending_chunks.pop_front()
+        {
+            self.launch(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Starts the command for a batch of files
+    fn launch(&mut self, batch: Vec<QueuedFile>) -> Result<(), ProgramError> {
+        let p: Vec<PathBuf> = batch.iter().map(|f| f.path.clone()).collect();
+        let label = self.batch_label(&batch);
+        if let Some(journal) = &mut self.journal {
+            for f in &batch {
+                journal.record_run(&f.path);
+            }
+        }
+
+        if self.feed {
+            return self.feed_files(&batch, label);
+        }
+
+        let command_number = self.status.next_command_number();
+        let mut command = self.command_for(&self.command, &p, label.as_deref(), command_number);
+        let mut fallback = self
+            .fallback
+            .as_ref()
+            .map(|f| self.command_for(f, &p, label.as_deref(), command_number));
+        self.set_file_env(&mut command, &batch);
+        if let Some(fallback) = &mut fallback {
+            self.set_file_env(fallback, &batch);
+        }
+        let prev_exit = self.prev_exit_code.map(|code| ("REX_PREV_EXIT".into(), code.to_string()));
+        for (key, value) in prev_exit.into_iter().chain(self.rerun_var(&batch)) {
+            command.env(&key, &value);
+            if let Some(fallback) = &mut fallback {
+                fallback.env(&key, &value);
+            }
+        }
+        self.last_batch = batch.clone();
+
+        // Count the worker as running before anyone hears about it
+        let active = self.status.track_worker();
+        self.report_start(&batch, label, command_number)?;
+
+        // Diffs of the files, written to the command stdin
+        let input = self.snapshots.as_mut().map(|snapshots| {
+            batch.iter().filter_map(|f| snapshots.diff(&f.path, f.kind)).collect::<String>()
+        });
+
+        let tx_clone = self.report_tx.clone();
+        let abort = self.abort.clone();
+        let options = self.run_options;
+        let delay = self.jitter.map(random_delay);
+        let (done_tx, done) = bounded::<()>(0);
+        let handle = spawn_named(format!("rex-cmd-{command_number}"), move || {
+            if let Some(delay) = delay
+                && !wait_unless_aborted(delay, &abort)
+            {
+                log::info!("Command #{} aborted before it started", command_number + 1);
+                let finish =
+                    ExecCode { command_number, exit_code: None, duration: None, stopped: true };
+                send_msg_unchecked!(tx_clone, ExecMessage::Finish(finish));
+                drop(active);
+                drop(done_tx);
+                return None;
+            }
+            let exit_code =
+                run_command(command_number, command, tx_clone, abort, options, input, fallback);
+            drop(active);
+            drop(done_tx);
+            exit_code
+        });
+        self.workers.push(Worker {
+            handle,
+            batch,
+            aborted: false,
+            command_number,
+            // The command only runs after the jitter
+            started: Instant::now() + delay.unwrap_or_default(),
+            warned: false,
+            done,
+        });
+        if let Some(tracker) = &mut self.output_tracker {
+            tracker.update(true, Instant::now());
+        }
+
+        Ok(())
+    }
 
-        // Start assembling the command
+    /// Assembles the command running a shell command line for some files,
+    /// with the placeholders replaced
+    fn command_for(
+        &self,
+        command_line: &Template,
+        p: &[PathBuf],
+        label: Option<&str>,
+        command_number: usize,
+    ) -> Command {
         let mut command = self.get_command();
 
         if let Some(cwd) = &self.working_dir {
             command.current_dir(cwd);
         }
+        if let Some(l) = label {
+            command.env("REX_LABEL", l);
+        }
 
-        // File the arguments, replace the placeholders
-        if self.command.contains(FILE_SUBSTITUTION) {
-            command.arg(self.command.replace(FILE_SUBSTITUTION, p[0].to_string_lossy().as_ref()));
-        } else if self.command.contains(FILES_SUBSTITUTION) {
-            command.arg(self.command.replace(
-                FILES_SUBSTITUTION,
-                p.iter().map(|pb| pb.to_string_lossy()).collect::<Vec<_>>().join(" ").as_str(),
-            ));
+        // Files from the watches are absolute already, as the watches are
+        // canonicalized. Others are relative to the directory of rex
+        let absolute_paths: Vec<PathBuf>;
+        let p = if self.abs_paths {
+            absolute_paths =
+                p.iter().map(|f| std::path::absolute(f).unwrap_or_else(|_| f.clone())).collect();
+            &absolute_paths
         } else {
-            command.arg(&self.command);
+            p
+        };
+
+        // File the arguments, replace the placeholders
+        let first = p.first().map(PathBuf::as_path).unwrap_or(Path::new(""));
+        let file = first.to_string_lossy();
+        let files = p.iter().map(|pb| pb.to_string_lossy()).collect::<Vec<_>>().join(" ");
+        let basename = first.file_stem().unwrap_or_default().to_string_lossy();
+        let dir = first.parent().unwrap_or(Path::new("")).to_string_lossy();
+        let ext = first.extension().unwrap_or_default().to_string_lossy();
+        // Number of the command about to start, as shown in the UI
+        let number = (command_number + 1).to_string();
+        // In the order of ENV_PLACEHOLDERS
+        let values = [files.as_str(), &file, &basename, &dir, &ext, label.unwrap_or(""), &number];
+        for (key, value) in &self.env_templates {
+            command.env(key, value.render(&values));
+        }
+        command.arg(command_line.render(&values));
+        command
+    }
+
+    /// Passes the files of a batch to the command in environment variables:
+    /// REX_FILE, REX_WATCH and REX_EVENT for the first file, REX_FILES for
+    /// all of them. Variables set with --env are left alone
+    fn set_file_env(&self, command: &mut Command, batch: &[QueuedFile]) {
+        let Some(first) = batch.first() else {
+            return;
+        };
+        let files = batch.iter().map(|f| f.path.to_string_lossy()).collect::<Vec<_>>().join(" ");
+        let vars = [
+            ("REX_FILE", first.path.as_os_str()),
+            ("REX_FILES", files.as_ref()),
+            ("REX_WATCH", first.watch.as_os_str()),
+            ("REX_EVENT", first.kind.name().as_ref()),
+        ];
+        for (key, value) in vars {
+            if !self.command_base.get_envs().any(|(k, _)| k == key)
+                && !self.env_templates.iter().any(|(k, _)| k == key)
+            {
+                command.env(key, value);
+            }
+        }
+    }
+
+    /// Returns the name of the task, or the labels of the watches involved in
+    /// a batch, comma separated
+    fn batch_label(&self, batch: &[QueuedFile]) -> Option<String> {
+        if self.task.is_some() {
+            return self.task.clone();
         }
+        let mut labels: Vec<&str> = batch
+            .iter()
+            .filter_map(|f| self.labels.get(&f.watch))
+            .map(|l| l.as_str())
+            .collect();
+        labels.sort_unstable();
+        labels.dedup();
+        if labels.is_empty() { None } else { Some(labels.join(",")) }
+    }
 
-        // Queue house keeping.
-        let command_number = self.command_count;
-        self.command_count += 1;
+    /// Reports the start of a command for the given files
+    fn report_start(
+        &mut self,
+        batch: &[QueuedFile],
+        label: Option<String>,
+        command_number: usize,
+    ) -> Result<(), ProgramError> {
         log::info!(
             "Executing command #{} for {} file(s): {:?}",
             command_number + 1,
-            p.len(),
-            p.iter()
-                .map(|pb| pb.file_name().unwrap().to_string_lossy().into_owned())
+            batch.len(),
+            batch
+                .iter()
+                .map(|f| f
+                    .path
+                    .file_name()
+                    .unwrap_or(f.path.as_os_str())
+                    .to_string_lossy()
+                    .into_owned())
                 .collect::<Vec<_>>()
         );
         self.report_tx
             .send(Event::Exec(ExecMessage::Start(ExecStart {
                 command_number,
-                files: p
+                files: batch
                     .iter()
-                    .map(|pb| pb.file_name().unwrap().to_string_lossy().into_owned())
+                    .map(|f| ExecFile {
+                        name: f
+                            .path
+                            .file_name()
+                            .unwrap_or(f.path.as_os_str())
+                            .to_string_lossy()
+                            .into_owned(),
+                        kind: f.kind,
+                    })
                     .collect(),
+                label,
             })))
             .map_err(|e| runtime_error!(CommandExecutionError, e.to_string()))?;
 
+        Ok(())
+    }
+
+    /// Writes the updated files to the stdin of the feed process, one per line.
+    /// (Re)starts the process first if it is not running.
+    fn feed_files(
+        &mut self,
+        batch: &[QueuedFile],
+        label: Option<String>,
+    ) -> Result<(), ProgramError> {
+        // Second attempt covers a child that exited before we noticed it
+        for _ in 0..2 {
+            if self.feed_process.as_ref().is_none_or(|f| f.worker.is_finished()) {
+                self.feed_process = None;
+                if let Err(e) = self.spawn_feed_process(batch, label.clone()) {
+                    log::error!("Could not start the feed command: {e}");
+                    return Ok(());
+                }
+            }
+            let Some(feed) = self.feed_process.as_mut() else {
+                return Ok(());
+            };
+
+            let written = batch
+                .iter()
+                .try_for_each(|f| writeln!(feed.stdin, "{}", f.path.display()))
+                .and_then(|_| feed.stdin.flush());
+            match written {
+                Ok(_) => {
+                    log::debug!("Fed {} file(s) to the running command", batch.len());
+                    return Ok(());
+                }
+                Err(e) => {
+                    log::warn!("Feed command is not accepting input ({e}), restarting it");
+                    self.feed_process = None;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Spawns the long-lived command used in feed mode
+    fn spawn_feed_process(
+        &mut self,
+        batch: &[QueuedFile],
+        label: Option<String>,
+    ) -> Result<(), ProgramError> {
+        let command_number = self.status.next_command_number();
+        let mut command = self.command_for(&self.command, &[], label.as_deref(), command_number);
+        command.stdin(Stdio::piped());
+
+        self.run_options.limits.apply(&mut command);
+        let mut child = command.spawn()?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| runtime_error!(InternalError, "Feed command has no stdin".into()))?;
+        self.report_start(batch, label, command_number)?;
+
         let tx_clone = self.report_tx.clone();
         let abort = self.abort.clone();
-        let pipe_output = self.pipe_command_output;
-        self.workers.push(std::thread::spawn(move || {
-            run_command(command_number, command, tx_clone, abort, pipe_output)
-        }));
+        let options = self.run_options;
+        let worker = spawn_named(format!("rex-feed-{command_number}"), move || {
+            wait_for_child(command_number, child, tx_clone, abort, options);
+        });
+        self.feed_process = Some(FeedProcess { stdin, worker });
 
         Ok(())
     }
 }
 
+/// Spawns a thread with a name, e.g. `rex-cmd-42`, so that debuggers and
+/// profilers can tell the threads apart
+fn spawn_named<F, T>(name: String, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    std::thread::Builder::new()
+        .name(name)
+        .spawn(f)
+        .expect("Could not spawn a thread")
+}
+
+/// Waits for the given duration, in short slices to notice the commands
+/// being aborted. Returns false if they were
+fn wait_unless_aborted(duration: Duration, abort: &AtomicBool) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if abort.load(Ordering::SeqCst) {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        std::thread::sleep(remaining.min(Duration::from_millis(40)));
+    }
+}
+
+/// Returns a random duration, shorter than the given one
+fn random_delay(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    // Randomly seeded hasher, to avoid a dependency for a bit of randomness
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_nanos(random % max.as_nanos().min(u64::MAX as u128) as u64)
+}
+
 pub fn run_command(
     command_number: usize,
     mut command: Command,
     report_tx: Sender<Event>,
     abort: Arc<AtomicBool>,
-    pipe_output: bool,
-) {
-    let mut child = command.spawn().expect("Command could not start");
+    options: RunOptions,
+    input: Option<String>,
+    mut fallback: Option<Command>,
+) -> ExitCode {
+    options.limits.apply(&mut command);
+    if input.is_some() {
+        command.stdin(Stdio::piped());
+    }
+    let spawned = command.spawn();
+    if let Err(e) = &spawned
+        && let Some(fallback) = fallback.take()
+    {
+        log::warn!("Command could not start ({e}), running the fallback");
+        return run_command(command_number, fallback, report_tx, abort, options, None, None);
+    }
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            log::error!("Command #{} could not start: {e}", command_number + 1);
+            let error = ExecError { command_number, error: e.to_string() };
+            send_msg_unchecked!(report_tx, ExecMessage::Error(error));
+            let finish =
+                ExecCode { command_number, exit_code: None, duration: None, stopped: false };
+            send_msg_unchecked!(report_tx, ExecMessage::Finish(finish));
+            return None;
+        }
+    };
+    if let Some(input) = input
+        && let Some(mut stdin) = child.stdin.take()
+    {
+        // Write from another thread, the command may not read its stdin
+        spawn_named(format!("rex-stdin-{command_number}"), move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+    let finish = wait_for_exit(command_number, child, report_tx.clone(), abort.clone(), options);
+    let exit_code = finish.exit_code;
+
+    // The shell reports a missing program with a dedicated exit code
+    if exit_code == Some(COMMAND_NOT_FOUND)
+        && let Some(fallback) = fallback
+    {
+        log::warn!("Command not found, running the fallback");
+        return run_command(command_number, fallback, report_tx, abort, options, None, None);
+    }
+    send_msg_unchecked!(report_tx, ExecMessage::Finish(finish));
+    exit_code
+}
+
+/// Pipes the output of a spawned child and reports its exit code once it
+/// terminates
+fn wait_for_child(
+    command_number: usize,
+    child: Child,
+    report_tx: Sender<Event>,
+    abort: Arc<AtomicBool>,
+    options: RunOptions,
+) -> ExitCode {
+    let finish = wait_for_exit(command_number, child, report_tx.clone(), abort, options);
+    let exit_code = finish.exit_code;
+    send_msg_unchecked!(report_tx, ExecMessage::Finish(finish));
+    exit_code
+}
+
+/// Pipes the output of a spawned child until it terminates, returns its exit
+/// code, how long it ran and whether it was aborted
+fn wait_for_exit(
+    command_number: usize,
+    mut child: Child,
+    report_tx: Sender<Event>,
+    abort: Arc<AtomicBool>,
+    options: RunOptions,
+) -> ExecCode {
     let start = std::time::Instant::now();
     let pid = child.id();
+    let mut terminated = None;
+    let mut stopped = false;
 
     // Send stdout updates to tx reports
-    if pipe_output {
+    let readers = options.pipe_output.then(|| {
         let tx_clone = report_tx.clone();
-        let _ = pipe_child_streams_to_events(&mut child, tx_clone, command_number);
-    }
+        pipe_child_streams_to_events(&mut child, tx_clone, command_number, options)
+    });
 
     // Block on child exit in a dedicated thread so that fast commands are
     // detected immediately rather than after a polling sleep.
     let (wait_tx, wait_rx) = bounded::<Option<ExitStatus>>(1);
-    std::thread::spawn(move || {
+    spawn_named(format!("rex-wait-{command_number}"), move || {
         let _ = wait_tx.send(child.wait().ok());
     });
 
@@ -339,10 +1328,15 @@ pub fn run_command(
             Ok(status) => break status,
             Err(RecvTimeoutError::Timeout) => {
                 if abort.load(Ordering::SeqCst) {
-                    #[cfg(unix)]
-                    unsafe {
-                        libc::kill(pid as libc::pid_t, libc::SIGKILL);
+                    stopped = true;
+                    stop_child(pid, TimeoutSignal::Sigterm, &mut terminated);
+                } else if let Some(timeout) = options.timeout
+                    && start.elapsed() >= timeout
+                {
+                    if terminated.is_none() {
+                        log::warn!("Command #{} timed out, stopping it", command_number + 1);
                     }
+                    stop_child(pid, options.timeout_signal, &mut terminated);
                 }
             }
             Err(RecvTimeoutError::Disconnected) => break None,
@@ -355,23 +1349,33 @@ pub fn run_command(
         None => None,
     };
 
-    send_msg_unchecked!(
-        report_tx,
-        ExecMessage::Finish(ExecCode { command_number, exit_code, duration: Some(elapsed) })
-    );
+    // Let the last output lines go out before the exit code. Background
+    // processes may keep the streams open, so do not wait for too long.
+    if let Some((stdout, stderr)) = readers {
+        let deadline = std::time::Instant::now() + Duration::from_millis(200);
+        while !(stdout.is_finished() && stderr.is_finished())
+            && std::time::Instant::now() < deadline
+        {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    ExecCode { command_number, exit_code, duration: Some(elapsed), stopped }
 }
 
 fn pipe_child_streams_to_events(
     child: &mut std::process::Child,
     report_tx: Sender<Event>,
     command_number: usize,
+    options: RunOptions,
 ) -> (JoinHandle<()>, JoinHandle<()>) {
+    let (keep_crlf, max_line_length) = (options.keep_crlf, options.max_line_length);
     // Send stdout updates to tx reports
     let stdout = BufReader::new(child.stdout.take().unwrap());
     let stdout_tx = report_tx.clone();
-    let stdout_handle = std::thread::spawn(move || {
-        for line in stdout.lines() {
-            let line = line.unwrap();
+    let stdout_handle = spawn_named(format!("rex-stdout-{command_number}"), move || {
+        for line in LineChunks::new(stdout, max_line_length).map_while(Result::ok) {
+            let line = normalize_line(line, keep_crlf);
             send_msg_unchecked!(
                 stdout_tx,
                 ExecMessage::Output(ExecOutput {
@@ -386,9 +1390,9 @@ fn pipe_child_streams_to_events(
     // Send stderr updates to tx reports
     let stderr = BufReader::new(child.stderr.take().unwrap());
     let stderr_tx = report_tx.clone();
-    let stderr_handle = std::thread::spawn(move || {
-        for line in stderr.lines() {
-            let line = line.unwrap();
+    let stderr_handle = spawn_named(format!("rex-stderr-{command_number}"), move || {
+        for line in LineChunks::new(stderr, max_line_length).map_while(Result::ok) {
+            let line = normalize_line(line, keep_crlf);
             send_msg_unchecked!(
                 stderr_tx,
                 ExecMessage::Output(ExecOutput {
@@ -402,3 +1406,1317 @@ fn pipe_child_streams_to_events(
 
     (stdout_handle, stderr_handle)
 }
+
+/// Asks a child to exit with the given signal, e.g. SIGTERM so that it can
+/// clean up, then kills it if it is still running after a grace period
+#[cfg(unix)]
+fn stop_child(pid: u32, signal: TimeoutSignal, terminated: &mut Option<Instant>) {
+    let signal = match terminated {
+        None => {
+            *terminated = Some(Instant::now());
+            signal.number()
+        }
+        Some(t) if t.elapsed() >= KILL_GRACE => libc::SIGKILL,
+        Some(_) => return,
+    };
+    unsafe {
+        libc::kill(pid as libc::pid_t, signal);
+    }
+}
+
+/// Kills a child and its own children. The child is owned by the thread
+/// waiting for it, so it is killed by its pid
+#[cfg(not(unix))]
+fn stop_child(pid: u32, _signal: TimeoutSignal, terminated: &mut Option<Instant>) {
+    if terminated.replace(Instant::now()).is_some() {
+        return;
+    }
+    let killed = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    if let Err(e) = killed {
+        log::error!("Could not kill the command (pid {pid}): {e}");
+    }
+}
+
+/// Removes the \r left at the end of lines terminated with \r\n, unless we
+/// keep them
+fn normalize_line(mut line: String, keep_crlf: bool) -> String {
+    if !keep_crlf && line.ends_with('\r') {
+        line.pop();
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crossbeam_channel::unbounded;
+    use notify::event::{CreateKind, EventKind, ModifyKind, RemoveKind};
+    use std::fs;
+    use tempfile::tempdir;
+
+    const CREATE: EventKind = EventKind::Create(CreateKind::File);
+    const MODIFY: EventKind = EventKind::Modify(ModifyKind::Any);
+
+    /// Starts a queue from command line arguments
+    fn start_queue(cli: &[&str]) -> (Sender<QueueMessage>, Receiver<Event>) {
+        let mut args = Args::try_parse_from(cli).expect("Invalid test arguments");
+        args.validate().expect("Test arguments do not validate");
+        let (tx, rx) = unbounded();
+        (Queue::start(&args, tx).expect("Could not start the queue").tx, rx)
+    }
+
+    /// Returns the next execution message, fails after a timeout
+    fn next_exec(rx: &Receiver<Event>) -> ExecMessage {
+        match rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(Event::Exec(message)) => message,
+            other => panic!("Expected an execution message, got {other:?}"),
+        }
+    }
+
+    /// Waits for a stdout line, returns the messages received before it
+    fn wait_for_stdout(rx: &Receiver<Event>, expected: &str) -> Vec<ExecMessage> {
+        let mut before = Vec::new();
+        loop {
+            match next_exec(rx) {
+                ExecMessage::Output(ExecOutput { stdout: Some(line), .. }) if line == expected => {
+                    return before;
+                }
+                message => before.push(message),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_feed_keeps_command_alive() {
+        let dir = tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+
+        let (tx, rx) = start_queue(&["rex", "--feed", "while read f; do echo \"got $f\"; done"]);
+
+        tx.send(QueueMessage::AddFile(a.clone(), dir.path().to_path_buf(), CREATE))
+            .unwrap();
+        let before = wait_for_stdout(&rx, &format!("got {}", a.display()));
+        assert!(matches!(before[..], [ExecMessage::Start(ExecStart { command_number: 0, .. })]));
+
+        tx.send(QueueMessage::AddFile(b.clone(), dir.path().to_path_buf(), CREATE))
+            .unwrap();
+        let before = wait_for_stdout(&rx, &format!("got {}", b.display()));
+        assert!(before.is_empty(), "Command was restarted: {before:?}");
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_feed_restarts_exited_command() {
+        let dir = tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+
+        let (tx, rx) = start_queue(&["rex", "--feed", "read f; echo \"got $f\""]);
+
+        tx.send(QueueMessage::AddFile(a.clone(), dir.path().to_path_buf(), CREATE))
+            .unwrap();
+        wait_for_stdout(&rx, &format!("got {}", a.display()));
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(ExecCode { command_number: 0, .. })));
+
+        tx.send(QueueMessage::AddFile(b.clone(), dir.path().to_path_buf(), CREATE))
+            .unwrap();
+        let before = wait_for_stdout(&rx, &format!("got {}", b.display()));
+        assert!(matches!(before[..], [ExecMessage::Start(ExecStart { command_number: 1, .. })]));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cpu_time_limit_terminates_command() {
+        let (tx, rx) = unbounded();
+        let mut command = Command::new("sh");
+        command.args(["-c", "while :; do :; done"]);
+        let options = RunOptions {
+            limits: ResourceLimits { cpu_time: Some(1), ..Default::default() },
+            ..Default::default()
+        };
+
+        run_command(0, command, tx, Arc::new(AtomicBool::new(false)), options, None, None);
+
+        match next_exec(&rx) {
+            ExecMessage::Finish(ExecCode { exit_code, .. }) => {
+                assert_eq!(exit_code, Some(128 + libc::SIGXCPU));
+                assert_eq!(exit_code::get_exit_reason(exit_code), Some("CPU time limit exceeded"));
+            }
+            message => panic!("Unexpected message {message:?}"),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout_escalates_to_sigkill() {
+        let (tx, rx) = unbounded();
+        let mut command = Command::new("sh");
+        command.args(["-c", "trap '' TERM; echo started; while :; do sleep 0.05; done"]);
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let options = RunOptions {
+            pipe_output: true,
+            timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+
+        let abort = Arc::new(AtomicBool::new(false));
+        let start = Instant::now();
+        let exit_code = run_command(0, command, tx, abort, options, None, None);
+        // SIGTERM is ignored, SIGKILL comes after the grace period
+        assert_eq!(exit_code, Some(128 + libc::SIGKILL));
+        assert!(start.elapsed() >= Duration::from_millis(200) + KILL_GRACE);
+        wait_for_stdout(&rx, "started");
+        // Timing out is a failure, the command was not aborted
+        match next_exec(&rx) {
+            ExecMessage::Finish(finish) => assert!(!finish.stopped),
+            message => panic!("Unexpected message {message:?}"),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fallback_when_spawn_fails() {
+        let (tx, rx) = unbounded();
+        let command = Command::new("/nonexistent/rex-test-program");
+        let mut fallback = Command::new("sh");
+        fallback.args(["-c", "echo fallback"]);
+        fallback.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let options = RunOptions { pipe_output: true, ..Default::default() };
+
+        let abort = Arc::new(AtomicBool::new(false));
+        let exit_code = run_command(0, command, tx, abort, options, None, Some(fallback));
+        assert_eq!(exit_code, Some(0));
+        wait_for_stdout(&rx, "fallback");
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(ExecCode { exit_code: Some(0), .. })));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fallback_on_exit_code_127() {
+        let (tx, rx) = unbounded();
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo started; exit 127"]);
+        let mut fallback = Command::new("sh");
+        fallback.args(["-c", "echo fallback"]);
+        for c in [&mut command, &mut fallback] {
+            c.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        let options = RunOptions { pipe_output: true, ..Default::default() };
+
+        let abort = Arc::new(AtomicBool::new(false));
+        let exit_code = run_command(0, command, tx, abort, options, None, Some(fallback));
+        assert_eq!(exit_code, Some(0));
+        // The command ran and printed before the fallback replaced it
+        let before = wait_for_stdout(&rx, "fallback");
+        assert!(before.iter().any(|m| matches!(m,
+            ExecMessage::Output(ExecOutput { stdout: Some(line), .. }) if line == "started")));
+        assert!(!before.iter().any(|m| matches!(m, ExecMessage::Finish(_))));
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(ExecCode { exit_code: Some(0), .. })));
+    }
+
+    #[test]
+    fn test_spawn_failure_reported() {
+        let (tx, rx) = unbounded();
+        let command = Command::new("/nonexistent/rex-test-shell");
+        let abort = Arc::new(AtomicBool::new(false));
+        let exit_code = run_command(0, command, tx, abort, RunOptions::default(), None, None);
+
+        assert_eq!(exit_code, None);
+        match next_exec(&rx) {
+            ExecMessage::Error(error) => assert!(error.error.contains("No such file")),
+            other => panic!("Expected the spawn error, got {other:?}"),
+        }
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(ExecCode { exit_code: None, .. })));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fallback_when_program_missing() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        let (tx, rx) =
+            start_queue(&["rex", "--fallback", "echo fallback {file}", "rex-missing-program"]);
+        tx.send(QueueMessage::AddFile(file.clone(), dir.path().to_path_buf(), CREATE))
+            .unwrap();
+
+        wait_for_stdout(&rx, &format!("fallback {}", file.display()));
+        loop {
+            if let ExecMessage::Finish(code) = next_exec(&rx) {
+                assert_eq!(code.exit_code, Some(0));
+                break;
+            }
+        }
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_chunk_size() {
+        let dir = tempdir().unwrap();
+        let (tx, rx) = start_queue(&["rex", "-q", "--chunk-size", "2", "echo {files}"]);
+        for name in ["a", "b", "c", "d", "e"] {
+            let file = dir.path().join(name);
+            fs::write(&file, name).unwrap();
+            tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+        }
+
+        let mut chunks = Vec::new();
+        while chunks.len() < 3 {
+            if let ExecMessage::Start(start) = next_exec(&rx) {
+                let names: Vec<String> = start.files.into_iter().map(|f| f.name).collect();
+                chunks.push((start.command_number, names));
+            }
+        }
+        chunks.sort();
+        let chunks: Vec<Vec<String>> = chunks.into_iter().map(|(_, names)| names).collect();
+        assert_eq!(chunks, [vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_foreach() {
+        let dir = tempdir().unwrap();
+        let (tx, rx) = start_queue(&["rex", "--foreach", "sleep 0.1; echo {file}"]);
+        let (reply_tx, reply_rx) = bounded(0);
+        tx.send(QueueMessage::DumpQueue(reply_tx)).unwrap();
+        for name in ["a", "b", "c"] {
+            let file = dir.path().join(name);
+            fs::write(&file, name).unwrap();
+            tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+        }
+        reply_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // One command per file, each finishing before the next one starts
+        let mut runs = Vec::new();
+        while runs.len() < 6 {
+            match next_exec(&rx) {
+                ExecMessage::Start(start) => {
+                    let names: Vec<String> = start.files.into_iter().map(|f| f.name).collect();
+                    runs.push(format!("start {}", names.join(" ")));
+                }
+                ExecMessage::Finish(_) => runs.push("finish".to_string()),
+                _ => {}
+            }
+        }
+        assert_eq!(runs, ["start a", "finish", "start b", "finish", "start c", "finish"]);
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_crlf_removed_from_output() {
+        let (tx, rx) = unbounded();
+        let mut command = Command::new("sh");
+        command.args(["-c", "printf 'one\\r\\ntwo\\r\\n'"]);
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let options = RunOptions { pipe_output: true, ..Default::default() };
+
+        run_command(0, command, tx, Arc::new(AtomicBool::new(false)), options, None, None);
+
+        let mut lines = Vec::new();
+        loop {
+            match next_exec(&rx) {
+                ExecMessage::Output(ExecOutput { stdout: Some(line), .. }) => lines.push(line),
+                ExecMessage::Finish(_) => break,
+                _ => {}
+            }
+        }
+        assert_eq!(lines, ["one", "two"]);
+        assert_eq!(normalize_line("one\r".into(), true), "one\r");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid_utf8_output() {
+        let (tx, rx) = start_queue(&["rex", "printf 'bin\\377ary\\n'; echo after"]);
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        tx.send(QueueMessage::AddFile(file.clone(), dir.path().to_path_buf(), CREATE))
+            .unwrap();
+        let before = wait_for_stdout(&rx, "after");
+        assert!(before.iter().any(|m| matches!(m,
+            ExecMessage::Output(ExecOutput { stdout: Some(line), .. }) if line == "bin\u{fffd}ary")));
+
+        // The output of the next commands is still read
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), MODIFY)).unwrap();
+        wait_for_stdout(&rx, "after");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_interactive_inherits_stdio() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        let fds = dir.path().join("fds");
+        fs::write(&file, "a").unwrap();
+
+        let command =
+            format!("for fd in 0 1 2; do readlink /proc/$$/fd/$fd; done > {}", fds.display());
+        let (tx, rx) = start_queue(&["rex", "--interactive", &command]);
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(_)));
+
+        // The child has the same stdin/stderr as we do, stdout goes to the file
+        let child_fds = fs::read_to_string(&fds).unwrap();
+        let child_fds: Vec<&str> = child_fds.lines().collect();
+        let own_fd = |fd: u8| fs::read_link(format!("/proc/self/fd/{fd}")).unwrap();
+        assert_eq!(PathBuf::from(child_fds[0]), own_fd(0));
+        assert_eq!(PathBuf::from(child_fds[2]), own_fd(2));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_nice_command() {
+        let (tx, rx) = start_queue(&["rex", "--nice", "7", "ps -o nice= -p $$"]);
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+
+        loop {
+            if let ExecMessage::Output(ExecOutput { stdout: Some(line), .. }) = next_exec(&rx) {
+                assert_eq!(line.trim(), "7");
+                break;
+            }
+        }
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_watch_label() {
+        let dir = tempdir().unwrap();
+        let watch = dir.path().canonicalize().unwrap();
+        let file = watch.join("index.html");
+        fs::write(&file, "<html>").unwrap();
+
+        let target = format!("web={}", watch.display());
+        let (tx, rx) = start_queue(&["rex", "-f", &target, "echo {label} $REX_LABEL"]);
+
+        tx.send(QueueMessage::AddFile(file, watch, CREATE)).unwrap();
+        let before = wait_for_stdout(&rx, "web web");
+        match &before[..] {
+            [ExecMessage::Start(start)] => assert_eq!(start.label.as_deref(), Some("web")),
+            other => panic!("Unexpected messages {other:?}"),
+        }
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_auto_ignore_outputs() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("main.c");
+        let output = dir.path().join("main.o");
+        fs::write(&source, "int main;").unwrap();
+        let watch = dir.path().to_path_buf();
+
+        let command = format!("touch {}", output.display());
+        let (tx, rx) = start_queue(&["rex", "--auto-ignore-outputs", &command]);
+
+        tx.send(QueueMessage::AddFile(source, watch.clone(), CREATE)).unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(_)));
+
+        // The watcher reports the file written by the command
+        tx.send(QueueMessage::AddFile(output, watch, CREATE)).unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(800)).is_err());
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_until_success() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        let counter = dir.path().join("count");
+
+        // Fails on the first two runs
+        let command = format!(
+            "n=$(cat {0} 2>/dev/null || echo 0); n=$((n+1)); echo $n > {0}; [ $n -ge 3 ]",
+            counter.display()
+        );
+        let (tx, rx) = start_queue(&["rex", "-q", "--until-success", &command]);
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+
+        let mut exit_codes = Vec::new();
+        for _ in 0..3 {
+            assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+            match next_exec(&rx) {
+                ExecMessage::Finish(code) => exit_codes.push(code.exit_code),
+                other => panic!("Unexpected message {other:?}"),
+            }
+        }
+        assert_eq!(exit_codes, vec![Some(1), Some(1), Some(0)]);
+
+        // No more runs after the success
+        assert!(rx.recv_timeout(Duration::from_millis(1500)).is_err());
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[test]
+    fn test_queue_liveness() {
+        let mut args = Args::try_parse_from(["rex", "true"]).unwrap();
+        args.validate().unwrap();
+        let (tx, _rx) = unbounded();
+        let queue = Queue::start(&args, tx).unwrap();
+        assert!(queue.is_alive());
+
+        queue.tx.send(QueueMessage::Abort).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while queue.is_alive() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!queue.is_alive());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restart_replaces_command() {
+        let dir = tempdir().unwrap();
+        let command = "trap 'echo stopping; exit 0' TERM; echo up; while :; do sleep 0.05; done";
+        let (tx, rx) = start_queue(&["rex", "--restart", command]);
+        let watch = dir.path().to_path_buf();
+
+        tx.send(QueueMessage::AddFile(dir.path().join("a"), watch.clone(), MODIFY))
+            .unwrap();
+        wait_for_stdout(&rx, "up");
+        tx.send(QueueMessage::AddFile(dir.path().join("b"), watch, MODIFY)).unwrap();
+        // The running instance is stopped before the new one starts
+        let before = wait_for_stdout(&rx, "up");
+        let stopping = before.iter().position(|m| {
+            matches!(m, ExecMessage::Output(ExecOutput { stdout: Some(l), .. }) if l == "stopping")
+        });
+        let started = before.iter().position(|m| matches!(m, ExecMessage::Start(_)));
+        assert!(stopping.unwrap() < started.unwrap());
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stop_terminates_commands() {
+        let dir = tempdir().unwrap();
+        let command =
+            "trap 'echo cleanup; exit 1' TERM; echo started; while :; do sleep 0.05; done";
+        let mut args = Args::try_parse_from(["rex", command]).unwrap();
+        args.validate().unwrap();
+        let (tx, rx) = unbounded();
+        let queue = Queue::start(&args, tx).unwrap();
+        queue
+            .tx
+            .send(QueueMessage::AddFile(dir.path().join("a"), dir.path().to_path_buf(), CREATE))
+            .unwrap();
+        wait_for_stdout(&rx, "started");
+
+        // The command gets SIGTERM and can clean up before the queue stops
+        queue.stop();
+        assert!(!queue.is_alive());
+        wait_for_stdout(&rx, "cleanup");
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(_)));
+    }
+
+    /// Starts a command that reports SIGTERM, then updates another file: the
+    /// command must be stopped and run again even if no worker is free
+    #[cfg(unix)]
+    fn assert_update_replaces_command(options: &[&str]) {
+        let dir = tempdir().unwrap();
+        let command =
+            "trap 'echo stopped; exit 1' TERM; echo started; while :; do sleep 0.05; done";
+        let mut cli = vec!["rex"];
+        cli.extend(options);
+        cli.push(command);
+        let (tx, rx) = start_queue(&cli);
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        fs::write(&a, "").unwrap();
+        fs::write(&b, "").unwrap();
+        tx.send(QueueMessage::AddFile(a, dir.path().to_path_buf(), MODIFY)).unwrap();
+        wait_for_stdout(&rx, "started");
+
+        tx.send(QueueMessage::AddFile(b, dir.path().to_path_buf(), MODIFY)).unwrap();
+        let before = wait_for_stdout(&rx, "started");
+        assert!(before.iter().any(|m| matches!(m,
+            ExecMessage::Output(ExecOutput { stdout: Some(line), .. }) if line == "stopped")));
+        assert!(before.iter().any(|m| matches!(m, ExecMessage::Start(_))));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_abort_previous_with_one_job() {
+        assert_update_replaces_command(&["-j", "1", "--abort-previous"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restart_with_one_job() {
+        assert_update_replaces_command(&["--restart", "-j", "1"]);
+    }
+
+    #[test]
+    fn test_dump_queue() {
+        let dir = tempdir().unwrap();
+        let (tx, _rx) = start_queue(&["rex", "-q", "true"]);
+        for name in ["b.txt", "a.txt", "b.txt"] {
+            let file = dir.path().join(name);
+            tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+        }
+
+        let (reply_tx, reply_rx) = bounded(1);
+        tx.send(QueueMessage::DumpQueue(reply_tx)).unwrap();
+        let files = reply_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(files, vec![dir.path().join("a.txt"), dir.path().join("b.txt")]);
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_diff_on_stdin() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "one\ntwo\n").unwrap();
+        let (tx, rx) = start_queue(&["rex", "--diff", "grep '^[+-][^+-]' || true"]);
+
+        // The first update only records the content
+        tx.send(QueueMessage::AddFile(file.clone(), dir.path().to_path_buf(), MODIFY))
+            .unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(_)));
+
+        fs::write(&file, "one\n2\n").unwrap();
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), MODIFY)).unwrap();
+        wait_for_stdout(&rx, "-two");
+        assert!(matches!(
+            next_exec(&rx),
+            ExecMessage::Output(ExecOutput { stdout: Some(ref line), .. }) if line == "+2"
+        ));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_registered_file_does_not_run() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "one\ntwo\n").unwrap();
+        let cli = ["rex", "--diff", "--no-run-on-startup-scan", "grep '^[+-][^+-]' || true"];
+        let (tx, rx) = start_queue(&cli);
+
+        tx.send(QueueMessage::Register(file.clone(), dir.path().to_path_buf())).unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+
+        // The first update is already diffed against the scanned content
+        fs::write(&file, "one\n2\n").unwrap();
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), MODIFY)).unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+        wait_for_stdout(&rx, "-two");
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_overlapping_watches_run_once() {
+        let dir = tempdir().unwrap();
+        let sub = dir.path().join("src");
+        fs::create_dir(&sub).unwrap();
+        let file = sub.join("main.rs");
+        fs::write(&file, "").unwrap();
+        let (tx, rx) = start_queue(&["rex", "echo {file}"]);
+
+        for watch in [dir.path(), sub.as_path()] {
+            tx.send(QueueMessage::AddFile(file.clone(), watch.to_path_buf(), MODIFY))
+                .unwrap();
+        }
+        let before = wait_for_stdout(&rx, file.to_str().unwrap());
+        assert!(matches!(before[..], [ExecMessage::Start(ExecStart { command_number: 0, .. })]));
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(_)));
+        assert!(rx.recv_timeout(Duration::from_millis(800)).is_err());
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[test]
+    fn test_debounce() {
+        let dir = tempdir().unwrap();
+        let watch = dir.path().to_path_buf();
+
+        // With no debounce, updates waiting together still run once
+        let (tx, rx) = start_queue(&["rex", "-q", "--debounce", "0", "true"]);
+        // The queue is held until the reply is read
+        let (reply_tx, reply_rx) = bounded(0);
+        tx.send(QueueMessage::DumpQueue(reply_tx)).unwrap();
+        for name in ["a", "b", "c"] {
+            let file = dir.path().join(name);
+            fs::write(&file, "").unwrap();
+            tx.send(QueueMessage::AddFile(file, watch.clone(), CREATE)).unwrap();
+        }
+        reply_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        match next_exec(&rx) {
+            ExecMessage::Start(start) => assert_eq!(start.files.len(), 3),
+            other => panic!("Unexpected message {other:?}"),
+        }
+        tx.send(QueueMessage::Abort).unwrap();
+
+        let (tx, rx) = start_queue(&["rex", "-q", "--debounce", "500", "true"]);
+        let sent = Instant::now();
+        tx.send(QueueMessage::AddFile(dir.path().join("a"), watch, MODIFY)).unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+        assert!(sent.elapsed() >= Duration::from_millis(500));
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[test]
+    fn test_delay_merges_updates() {
+        let dir = tempdir().unwrap();
+        let watch = dir.path().to_path_buf();
+        let (tx, rx) =
+            start_queue(&["rex", "-q", "--debounce", "0", "--delay", "800", "echo {files}"]);
+        let sent = Instant::now();
+        for name in ["a", "b"] {
+            let file = dir.path().join(name);
+            fs::write(&file, "").unwrap();
+            tx.send(QueueMessage::AddFile(file, watch.clone(), CREATE)).unwrap();
+            // Past the settle window, within the delay
+            std::thread::sleep(Duration::from_millis(300));
+        }
+        match next_exec(&rx) {
+            ExecMessage::Start(start) => assert_eq!(start.files.len(), 2),
+            other => panic!("Unexpected message {other:?}"),
+        }
+        assert!(sent.elapsed() >= Duration::from_millis(800));
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[test]
+    fn test_random_delay() {
+        assert_eq!(random_delay(Duration::ZERO), Duration::ZERO);
+        let max = Duration::from_millis(10);
+        assert!((0..100).map(|_| random_delay(max)).all(|d| d < max));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_abort_during_jitter() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a");
+        fs::write(&file, "").unwrap();
+        let (tx, rx) = start_queue(&["rex", "--jitter", "600000", "echo run"]);
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+
+        // The command never runs, and its end is reported right away
+        let aborted = Instant::now();
+        tx.send(QueueMessage::AbortOngoingCommands).unwrap();
+        match next_exec(&rx) {
+            ExecMessage::Finish(finish) => {
+                assert_eq!((finish.exit_code, finish.stopped), (None, true))
+            }
+            message => panic!("Unexpected message {message:?}"),
+        }
+        assert!(aborted.elapsed() < ABORT_TIMEOUT);
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_aborted_command_is_reported_stopped() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a");
+        fs::write(&file, "").unwrap();
+        let (tx, rx) = start_queue(&["rex", "echo started; sleep 60"]);
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+        wait_for_stdout(&rx, "started");
+
+        tx.send(QueueMessage::AbortOngoingCommands).unwrap();
+        loop {
+            if let ExecMessage::Finish(finish) = next_exec(&rx) {
+                assert!(finish.stopped);
+                break;
+            }
+        }
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_jitter_spreads_starts() {
+        let dir = tempdir().unwrap();
+        // One file per chunk: the chunks start together without jitter
+        let cli = ["rex", "--jitter", "1000", "--chunk-size", "1", "echo {files}"];
+        let (tx, rx) = start_queue(&cli);
+        // The queue is held until the reply is read, so that all files are
+        // picked up together
+        let (reply_tx, reply_rx) = bounded(0);
+        tx.send(QueueMessage::DumpQueue(reply_tx)).unwrap();
+        for name in ["a", "b", "c"] {
+            let file = dir.path().join(name);
+            fs::write(&file, "").unwrap();
+            tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+        }
+        reply_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        let sent = Instant::now();
+
+        let mut outputs = Vec::new();
+        while outputs.len() < 3 {
+            if let ExecMessage::Output(_) = next_exec(&rx) {
+                outputs.push(sent.elapsed());
+            }
+        }
+        let (first, last) = (outputs[0], outputs[2]);
+        assert!(last - first > Duration::from_millis(10), "{outputs:?}");
+        assert!(last < Duration::from_secs(2), "{outputs:?}");
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_jobs_limit() {
+        let dir = tempdir().unwrap();
+        let cli = ["rex", "--jobs", "1", "--chunk-size", "1", "sleep 0.2; echo {files}"];
+        let (tx, rx) = start_queue(&cli);
+        let (reply_tx, reply_rx) = bounded(0);
+        tx.send(QueueMessage::DumpQueue(reply_tx)).unwrap();
+        for name in ["a", "b", "c"] {
+            let file = dir.path().join(name);
+            fs::write(&file, "").unwrap();
+            tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+        }
+        reply_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // Each command finishes before the next one starts
+        let mut sequence = Vec::new();
+        while sequence.len() < 6 {
+            match next_exec(&rx) {
+                ExecMessage::Start(_) => sequence.push("start"),
+                ExecMessage::Finish(_) => sequence.push("finish"),
+                _ => {}
+            }
+        }
+        assert_eq!(sequence, ["start", "finish", "start", "finish", "start", "finish"]);
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_created_then_deleted_file_dropped() {
+        let dir = tempdir().unwrap();
+        let (tx, rx) = start_queue(&["rex", "-d", "echo {files}"]);
+        let (reply_tx, reply_rx) = bounded(0);
+        tx.send(QueueMessage::DumpQueue(reply_tx)).unwrap();
+        // Like the file created by vim to test if a directory is writable
+        let temp = dir.path().join("4913");
+        let source = dir.path().join("main.rs");
+        fs::write(&source, "").unwrap();
+        let watch = dir.path().to_path_buf();
+        tx.send(QueueMessage::AddFile(temp.clone(), watch.clone(), CREATE)).unwrap();
+        tx.send(QueueMessage::AddFile(source.clone(), watch.clone(), CREATE)).unwrap();
+        let delete = EventKind::Remove(RemoveKind::File);
+        tx.send(QueueMessage::AddFile(temp, watch, delete)).unwrap();
+        reply_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        match next_exec(&rx) {
+            ExecMessage::Start(start) => {
+                let names: Vec<String> = start.files.into_iter().map(|f| f.name).collect();
+                assert_eq!(names, ["main.rs"]);
+            }
+            other => panic!("Expected the command start, got {other:?}"),
+        }
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_journal_replayed_after_restart() {
+        let dir = tempdir().unwrap();
+        let journal = dir.path().join("journal");
+        let journal = journal.to_str().unwrap();
+        let file = dir.path().join("main.rs");
+        fs::write(&file, "").unwrap();
+
+        // Stopped before the update settles: no command runs for it
+        let (tx, _rx) = start_queue(&["rex", "--journal", journal, "--debounce", "60000", "true"]);
+        tx.send(QueueMessage::AddFile(file.clone(), dir.path().into(), MODIFY)).unwrap();
+        let (reply_tx, reply_rx) = bounded(1);
+        tx.send(QueueMessage::DumpQueue(reply_tx)).unwrap();
+        assert_eq!(
+            reply_rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            std::slice::from_ref(&file)
+        );
+        tx.send(QueueMessage::Abort).unwrap();
+
+        let cli = ["rex", "--journal", journal, "--replay", "5", "echo replayed {file}"];
+        let (tx, rx) = start_queue(&cli);
+        wait_for_stdout(&rx, &format!("replayed {}", file.display()));
+        tx.send(QueueMessage::Abort).unwrap();
+
+        // Processed now, nothing left to replay
+        let mut args = Args::try_parse_from(cli).unwrap();
+        args.validate().unwrap();
+        let (_, unprocessed) = Journal::open(args.journal.as_ref().unwrap()).unwrap();
+        assert!(unprocessed.is_empty());
+    }
+
+    #[test]
+    fn test_delete_debounce() {
+        let dir = tempdir().unwrap();
+        let (tx, rx) =
+            start_queue(&["rex", "-q", "--deleted", "--delete-debounce", "1000", "true"]);
+        let delete = EventKind::Remove(RemoveKind::File);
+
+        // Modifies use the normal settle window
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        let sent = Instant::now();
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), MODIFY)).unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+        assert!(sent.elapsed() < Duration::from_millis(900));
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(_)));
+
+        // A burst of deletes waits for the delete settle window
+        let sent = Instant::now();
+        for name in ["b", "c", "d"] {
+            let file = dir.path().join(name);
+            tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), delete)).unwrap();
+            std::thread::sleep(Duration::from_millis(300));
+        }
+        match next_exec(&rx) {
+            ExecMessage::Start(start) => assert_eq!(start.files.len(), 3),
+            other => panic!("Unexpected message {other:?}"),
+        }
+        // Last delete sent after ~600 ms, then 1000 ms of settle window
+        assert!(sent.elapsed() >= Duration::from_millis(1600));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_skip_empty() {
+        let dir = tempdir().unwrap();
+        let (empty, full) = (dir.path().join("empty.txt"), dir.path().join("full.txt"));
+        fs::write(&empty, "").unwrap();
+        fs::write(&full, "content").unwrap();
+        let (tx, rx) = start_queue(&["rex", "--skip-empty", "echo {file}"]);
+
+        tx.send(QueueMessage::AddFile(empty, dir.path().to_path_buf(), CREATE)).unwrap();
+        tx.send(QueueMessage::AddFile(full.clone(), dir.path().to_path_buf(), CREATE))
+            .unwrap();
+        let before = wait_for_stdout(&rx, full.to_str().unwrap());
+        assert!(matches!(before[..], [ExecMessage::Start(ExecStart { command_number: 0, .. })]));
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(_)));
+        assert!(rx.recv_timeout(Duration::from_millis(800)).is_err());
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_escaped_placeholder_is_literal() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        let (tx, rx) = start_queue(&["rex", "echo '{{file}}' {file}"]);
+        tx.send(QueueMessage::AddFile(file.clone(), dir.path().to_path_buf(), CREATE))
+            .unwrap();
+
+        wait_for_stdout(&rx, &format!("{{file}} {}", file.display()));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_abort_waits_for_workers() {
+        let dir = tempdir().unwrap();
+        let (tx, rx) = start_queue(&["rex", "-q", "-a", "sleep 5"]);
+        let watch = dir.path().to_path_buf();
+        tx.send(QueueMessage::AddFile(dir.path().join("a"), watch.clone(), CREATE))
+            .unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+
+        let sent = Instant::now();
+        tx.send(QueueMessage::AddFile(dir.path().join("b"), watch, CREATE)).unwrap();
+        // The aborted command is gone before the next one starts
+        match next_exec(&rx) {
+            ExecMessage::Finish(code) => assert_eq!(code.command_number, 0),
+            other => panic!("Unexpected message {other:?}"),
+        }
+        match next_exec(&rx) {
+            ExecMessage::Start(start) => assert_eq!(start.command_number, 1),
+            other => panic!("Unexpected message {other:?}"),
+        }
+        assert!(sent.elapsed() < Duration::from_secs(2));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_abort_on_new() {
+        let dir = tempdir().unwrap();
+        let (tx, rx) = start_queue(&["rex", "-q", "--abort-on-new", "sleep 1"]);
+        let watch = dir.path().to_path_buf();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        tx.send(QueueMessage::AddFile(a.clone(), watch.clone(), CREATE)).unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+
+        // The same file again: the running command goes on
+        tx.send(QueueMessage::AddFile(a.clone(), watch.clone(), MODIFY)).unwrap();
+        let mut finished = Vec::new();
+        while finished.len() < 2 {
+            if let ExecMessage::Finish(code) = next_exec(&rx) {
+                finished.push((code.command_number, code.exit_code));
+            }
+        }
+        assert_eq!(finished, [(0, Some(0)), (1, Some(0))]);
+
+        // Another file: the running command is aborted
+        tx.send(QueueMessage::AddFile(a, watch.clone(), MODIFY)).unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+        let sent = Instant::now();
+        tx.send(QueueMessage::AddFile(b, watch, CREATE)).unwrap();
+        match next_exec(&rx) {
+            ExecMessage::Finish(code) => {
+                assert_eq!(code.command_number, 2);
+                assert_ne!(code.exit_code, Some(0));
+            }
+            other => panic!("Unexpected message {other:?}"),
+        }
+        assert!(sent.elapsed() < Duration::from_millis(900));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_warn_after() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        let (tx, rx) = start_queue(&["rex", "-q", "--warn-after", "300", "sleep 1"]);
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE)).unwrap();
+
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+        match next_exec(&rx) {
+            ExecMessage::Running(running) => {
+                assert_eq!(running.command_number, 0);
+                assert!(running.elapsed >= Duration::from_millis(300));
+                assert!(running.elapsed < Duration::from_secs(1));
+            }
+            other => panic!("Unexpected message {other:?}"),
+        }
+        // Warned only once
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(_)));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rerun_with_env() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        let (tx, rx) = start_queue(&["rex", "echo {file} ${REX_TEST:-unset}"]);
+        let watch = dir.path().to_path_buf();
+        let name = file.to_str().unwrap();
+
+        tx.send(QueueMessage::AddFile(file.clone(), watch.clone(), MODIFY)).unwrap();
+        wait_for_stdout(&rx, &format!("{name} unset"));
+
+        tx.send(QueueMessage::RerunWithEnv("REX_TEST".into(), "set".into())).unwrap();
+        wait_for_stdout(&rx, &format!("{name} set"));
+
+        // Only for one run
+        tx.send(QueueMessage::AddFile(file.clone(), watch, MODIFY)).unwrap();
+        wait_for_stdout(&rx, &format!("{name} unset"));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rerun_with_env_in_chunks() {
+        let dir = tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+        let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+        let cli = ["rex", "-f", a, "-f", b, "--chunk-size", "1", "echo {files} ${REX_TEST:-unset}"];
+        let (tx, rx) = start_queue(&cli);
+
+        // Nothing ran yet: the rerun is for both watched files, one chunk each
+        tx.send(QueueMessage::RerunWithEnv("REX_TEST".into(), "set".into())).unwrap();
+        let mut lines = Vec::new();
+        while lines.len() < 2 {
+            if let ExecMessage::Output(ExecOutput { stdout: Some(line), .. }) = next_exec(&rx) {
+                lines.push(line);
+            }
+        }
+        lines.sort();
+        assert_eq!(lines, [format!("{a} set"), format!("{b} set")]);
+
+        // The variable is gone once the rerun is done
+        tx.send(QueueMessage::RunNow).unwrap();
+        let mut lines = Vec::new();
+        while lines.len() < 2 {
+            if let ExecMessage::Output(ExecOutput { stdout: Some(line), .. }) = next_exec(&rx) {
+                lines.push(line);
+            }
+        }
+        lines.sort();
+        assert_eq!(lines, [format!("{a} unset"), format!("{b} unset")]);
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_thread_names() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "").unwrap();
+        // Threads of the process running the command, i.e. of the tests
+        let cli = ["rex", "grep -qx rex-cmd-0 /proc/$PPID/task/*/comm && echo named"];
+        let (tx, rx) = start_queue(&cli);
+
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), MODIFY)).unwrap();
+        wait_for_stdout(&rx, "named");
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_env() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "").unwrap();
+        let cli =
+            ["rex", "-E", "REX_WATCH=mine", "echo $REX_EVENT $REX_FILE $REX_FILES $REX_WATCH"];
+        let (tx, rx) = start_queue(&cli);
+
+        tx.send(QueueMessage::AddFile(file.clone(), dir.path().to_path_buf(), CREATE))
+            .unwrap();
+        let name = file.display();
+        wait_for_stdout(&rx, &format!("created {name} {name} mine"));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_custom_shell() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "").unwrap();
+        let cli = ["rex", "--shell", "env REX_SHELL='custom shell' sh -c", "echo $REX_SHELL"];
+        let (tx, rx) = start_queue(&cli);
+
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), MODIFY)).unwrap();
+        wait_for_stdout(&rx, "custom shell");
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_previous_exit_code() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        let (tx, rx) = start_queue(&["rex", "echo prev=${REX_PREV_EXIT:-unset}; exit 3"]);
+        let watch = dir.path().to_path_buf();
+
+        tx.send(QueueMessage::AddFile(file.clone(), watch.clone(), MODIFY)).unwrap();
+        wait_for_stdout(&rx, "prev=unset");
+        while !matches!(next_exec(&rx), ExecMessage::Finish(_)) {}
+
+        tx.send(QueueMessage::AddFile(file, watch, MODIFY)).unwrap();
+        wait_for_stdout(&rx, "prev=3");
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_env_placeholders() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "").unwrap();
+        let cli = ["rex", "-E", "LOGFILE=build-{n}.log", "-E", "FIXED=x", "echo $LOGFILE $FIXED"];
+        let (tx, rx) = start_queue(&cli);
+        let watch = dir.path().to_path_buf();
+
+        tx.send(QueueMessage::AddFile(file.clone(), watch.clone(), MODIFY)).unwrap();
+        wait_for_stdout(&rx, "build-1.log x");
+        tx.send(QueueMessage::AddFile(file, watch, MODIFY)).unwrap();
+        wait_for_stdout(&rx, "build-2.log x");
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_path_placeholders() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("photo.jpg");
+        fs::write(&file, "").unwrap();
+        let (tx, rx) = start_queue(&["rex", "echo {dir}/out/{basename}.png {ext}"]);
+
+        tx.send(QueueMessage::AddFile(file, dir.path().to_path_buf(), MODIFY)).unwrap();
+        wait_for_stdout(&rx, &format!("{}/out/photo.png jpg", dir.path().display()));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_absolute_paths() {
+        let (file, watch) = (PathBuf::from("Cargo.toml"), PathBuf::from("."));
+        let (tx, rx) = start_queue(&["rex", "echo {file}"]);
+        tx.send(QueueMessage::AddFile(file.clone(), watch.clone(), MODIFY)).unwrap();
+        wait_for_stdout(&rx, "Cargo.toml");
+        tx.send(QueueMessage::Abort).unwrap();
+
+        let (tx, rx) = start_queue(&["rex", "--abs-paths", "echo {dir} {file}"]);
+        tx.send(QueueMessage::AddFile(file, watch, MODIFY)).unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        wait_for_stdout(&rx, &format!("{} {}", cwd.display(), cwd.join("Cargo.toml").display()));
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rerun() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "first").unwrap();
+        let (tx, rx) = start_queue(&["rex", "-f", dir.path().to_str().unwrap(), "cat {files}"]);
+
+        tx.send(QueueMessage::AddFile(file.clone(), dir.path().to_path_buf(), MODIFY))
+            .unwrap();
+        wait_for_stdout(&rx, "first");
+
+        // The same file, not the watched directory
+        fs::write(&file, "again").unwrap();
+        tx.send(QueueMessage::Rerun).unwrap();
+        wait_for_stdout(&rx, "again");
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_now_without_updates() {
+        let dir = tempdir().unwrap();
+        let watch = dir.path().to_str().unwrap();
+        let (tx, rx) = start_queue(&["rex", "-f", watch, "echo {file}"]);
+
+        tx.send(QueueMessage::RunNow).unwrap();
+        let expected = dir.path().canonicalize().unwrap();
+        let before = wait_for_stdout(&rx, expected.to_str().unwrap());
+        assert!(matches!(&before[..], [ExecMessage::Start(_)]));
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_now_for_root_watch() {
+        // The root has no file name to show
+        let (tx, rx) = start_queue(&["rex", "-f", "/", "echo {file}"]);
+
+        tx.send(QueueMessage::Rerun).unwrap();
+        match wait_for_stdout(&rx, "/").first() {
+            Some(ExecMessage::Start(start)) => assert_eq!(start.files[0].name, "/"),
+            other => panic!("Unexpected message {other:?}"),
+        }
+        tx.send(QueueMessage::RunNow).unwrap();
+        wait_for_stdout(&rx, "/");
+
+        tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[test]
+    fn test_status_reports_pending_files() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+
+        let mut args = Args::try_parse_from(["rex", "-q", "echo"]).unwrap();
+        args.validate().unwrap();
+        let (report_tx, _rx) = unbounded();
+        let queue = Queue::start(&args, report_tx).unwrap();
+        assert_eq!(queue.status.pending_files(), 0);
+
+        queue
+            .tx
+            .send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE))
+            .unwrap();
+        // Picked up before the command runs
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(queue.status.pending_files(), 1);
+
+        queue.tx.send(QueueMessage::Abort).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_status_reports_running_commands() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+
+        let mut args = Args::try_parse_from(["rex", "sleep 0.5"]).unwrap();
+        args.validate().unwrap();
+        let (report_tx, rx) = unbounded();
+        let queue = Queue::start(&args, report_tx).unwrap();
+        assert!(!queue.status.is_running());
+
+        queue
+            .tx
+            .send(QueueMessage::AddFile(file, dir.path().to_path_buf(), CREATE))
+            .unwrap();
+        assert!(matches!(next_exec(&rx), ExecMessage::Start(_)));
+        assert!(queue.status.is_running());
+
+        assert!(matches!(next_exec(&rx), ExecMessage::Finish(_)));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!queue.status.is_running());
+
+        queue.tx.send(QueueMessage::Abort).unwrap();
+    }
+}
@@ -1,6 +1,9 @@
+use crossbeam_channel::Sender;
+use notify::EventKind;
 use std::path::PathBuf;
 
 /// Messages issued to the command queue
+#[derive(Clone)]
 pub enum QueueMessage {
     /// Tell the queue to stop.
     Abort,
@@ -8,9 +11,24 @@ pub enum QueueMessage {
     RestartBackoff,
     /// Insert an update of a file.
     /// First PathBuf is the updated file / Second is the top level watch
-    AddFile(PathBuf, PathBuf),
+    /// EventKind is the kind of update reported by the watcher
+    AddFile(PathBuf, PathBuf, EventKind),
     /// Clears the queue
     Clear,
     /// Signal running commands to stop
     AbortOngoingCommands,
+    /// Run the command now for the pending files, or for the watched
+    /// files/dirs if nothing is pending
+    RunNow,
+    /// Reply with the files waiting for a run
+    DumpQueue(Sender<Vec<PathBuf>>),
+    /// Run the last command again, for the same files, or for the watched
+    /// files/dirs if nothing ran yet
+    Rerun,
+    /// Run the last command again, once, with an additional environment
+    /// variable (key, value)
+    RerunWithEnv(String, String),
+    /// Record the current state of a file found by the startup scan of a
+    /// watch (file, watch), without running the command
+    Register(PathBuf, PathBuf),
 }
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Live state of the command queue, readable from other threads. The queues
+/// of the --task blocks share one
+#[derive(Debug, Default)]
+pub struct QueueStatus {
+    /// Number of commands currently running
+    active_workers: AtomicUsize,
+    /// Number of files waiting for a command
+    pending_files: AtomicUsize,
+    /// Number of commands started so far
+    commands: AtomicUsize,
+}
+
+impl QueueStatus {
+    /// Number of commands currently running
+    pub fn active_workers(&self) -> usize {
+        self.active_workers.load(Ordering::SeqCst)
+    }
+
+    /// Checks if at least one command is running
+    pub fn is_running(&self) -> bool {
+        self.active_workers() > 0
+    }
+
+    /// Number of files waiting for a command
+    pub fn pending_files(&self) -> usize {
+        self.pending_files.load(Ordering::SeqCst)
+    }
+
+    /// Checks that no command is running nor waiting for one
+    pub fn is_idle(&self) -> bool {
+        !self.is_running() && self.pending_files() == 0
+    }
+
+    /// Updates the count of a queue from `old` to `new` files waiting
+    pub(crate) fn replace_pending_files(&self, old: usize, new: usize) {
+        self.pending_files.fetch_add(new, Ordering::SeqCst);
+        self.pending_files.fetch_sub(old, Ordering::SeqCst);
+    }
+
+    /// Allocates the number of the next command, unique across the queues
+    pub(crate) fn next_command_number(&self) -> usize {
+        self.commands.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Counts a worker as running until the returned guard is dropped
+    pub fn track_worker(self: &Arc<Self>) -> ActiveWorker {
+        self.active_workers.fetch_add(1, Ordering::SeqCst);
+        ActiveWorker(self.clone())
+    }
+}
+
+/// Guard keeping a worker counted as running
+pub struct ActiveWorker(Arc<QueueStatus>);
+
+impl Drop for ActiveWorker {
+    fn drop(&mut self) {
+        self.0.active_workers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::args::Args;
+use crate::command::queue_status::QueueStatus;
+use crate::command::{Queue, QueueHandle, QueueMessage};
+use crate::errors::ProgramError;
+use crate::event::Event;
+use crate::files::utils::{canonical_path, ignore_reason};
+use crossbeam_channel::{RecvTimeoutError, SendError, Sender, bounded};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Command queues of rex: a single one, or one per --task. They share their
+/// status, so that the commands of all the tasks are numbered together
+pub struct Queues {
+    /// Queues, with the files they run the command for
+    queues: Vec<(QueueHandle, Route)>,
+    /// Live state of all the queues
+    pub status: Arc<QueueStatus>,
+}
+
+/// Files a queue runs the command for
+struct Route {
+    /// Watched files/dirs of the queue, any watch if empty
+    watches: Vec<PathBuf>,
+    /// Arguments of the task, filtering the files with its extensions and
+    /// ignore options. None for the queue of the command, as the files
+    /// were filtered already
+    filters: Option<Args>,
+}
+
+impl Route {
+    /// Tells if a file reported by a watch goes to the queue
+    fn accepts(&self, path: &PathBuf, watch: &PathBuf) -> bool {
+        let watched = self.watches.is_empty() || self.watches.contains(watch);
+        watched
+            && self.filters.as_ref().is_none_or(|args| match ignore_reason(path, args, watch) {
+                Some(reason) => {
+                    log::debug!("Not running the task for {:?}: {}", path, reason);
+                    false
+                }
+                None => true,
+            })
+    }
+}
+
+impl Queues {
+    /// Starts the queues. `parsed` are the arguments before validation, for
+    /// the tasks to validate their own
+    pub fn start(
+        parsed: &Args,
+        args: &Args,
+        report_tx: Sender<Event>,
+    ) -> Result<Self, ProgramError> {
+        let status = Arc::new(QueueStatus::default());
+        let mut queues = Vec::new();
+        if args.tasks.is_empty() {
+            let queue = Queue::start_with_status(args, report_tx.clone(), status.clone())?;
+            queues.push((queue, Route { watches: Vec::new(), filters: None }));
+        }
+        for task in &args.tasks {
+            let task_args = parsed.for_task(task)?;
+            let queue = Queue::start_with_status(&task_args, report_tx.clone(), status.clone())?;
+            // Paths as reported with the file updates
+            let watches =
+                task_args.watches.iter().filter_map(|w| canonical_path(&w.path).ok()).collect();
+            queues.push((queue, Route { watches, filters: Some(task_args) }));
+        }
+        Ok(Self { queues, status })
+    }
+
+    /// Sends a message to the queues. Files only go to the queues watching
+    /// them and not ignoring them
+    pub fn send(&self, message: QueueMessage) -> Result<(), SendError<QueueMessage>> {
+        for (queue, route) in &self.queues {
+            let accepted = match &message {
+                QueueMessage::AddFile(p, watch, _) | QueueMessage::Register(p, watch) => {
+                    route.accepts(p, watch)
+                }
+                _ => true,
+            };
+            if !accepted {
+                continue;
+            }
+            queue.tx.send(message.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Lists the files waiting for a run in all the queues
+    pub fn pending_paths(&self, timeout: Duration) -> Result<Vec<PathBuf>, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut paths = Vec::new();
+        for (queue, _) in &self.queues {
+            let (reply_tx, reply_rx) = bounded(1);
+            if queue.tx.send(QueueMessage::DumpQueue(reply_tx)).is_err() {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            paths.extend(reply_rx.recv_deadline(deadline)?);
+        }
+        Ok(paths)
+    }
+
+    /// Tells if the threads of all the queues are still running
+    pub fn is_alive(&self) -> bool {
+        self.queues.iter().all(|(queue, _)| queue.is_alive())
+    }
+
+    /// Stops the queues together, and waits for their commands to be stopped
+    pub fn stop(&self) {
+        for (queue, _) in &self.queues {
+            let _ = queue.tx.send(QueueMessage::Abort);
+        }
+        for (queue, _) in &self.queues {
+            queue.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::execution_report::{ExecMessage, ExecOutput};
+    use clap::Parser;
+    use crossbeam_channel::{Receiver, unbounded};
+    use notify::EventKind;
+    use notify::event::ModifyKind;
+
+    /// Returns the label of the next command started, and its output line
+    fn next_run(rx: &Receiver<Event>) -> (Option<String>, String) {
+        let mut label = None;
+        loop {
+            match rx.recv_timeout(Duration::from_secs(5)) {
+                Ok(Event::Exec(ExecMessage::Start(start))) => label = start.label,
+                Ok(Event::Exec(ExecMessage::Output(ExecOutput { stdout: Some(line), .. }))) => {
+                    return (label, line);
+                }
+                Ok(_) => {}
+                Err(e) => panic!("No command output: {e}"),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tasks_run_independently() {
+        let dir = tempfile::tempdir().unwrap();
+        let (shared, front, back) =
+            (dir.path().join("shared"), dir.path().join("front"), dir.path().join("back"));
+        for d in [&shared, &front, &back] {
+            std::fs::create_dir(d).unwrap();
+        }
+        let front_task = format!("front@{}=:echo front {{file}}", front.display());
+        let back_task = format!("back@{}=rs:echo back", back.display());
+        let cli = [
+            "rex",
+            "-f",
+            shared.to_str().unwrap(),
+            "-R",
+            "generated",
+            "--task",
+            "rust=rs:echo build {file}",
+            "--task",
+            "style=css:echo lint",
+            "--task",
+            &front_task,
+            "--task",
+            &back_task,
+        ];
+        let parsed = Args::try_parse_from(cli).unwrap();
+        let mut args = parsed.clone();
+        args.validate().unwrap();
+        let watched: Vec<_> = args.watches.iter().map(|w| w.path.as_str()).collect();
+        assert_eq!(
+            watched,
+            [shared.to_str().unwrap(), front.to_str().unwrap(), back.to_str().unwrap()]
+        );
+        let (tx, rx) = unbounded();
+        let queues = Queues::start(&parsed, &args, tx).unwrap();
+        let modify = EventKind::Modify(ModifyKind::Any);
+        let send = |file: &PathBuf, watch: &PathBuf| {
+            std::fs::write(file, "").unwrap();
+            let (file, watch) = (file.canonicalize().unwrap(), watch.canonicalize().unwrap());
+            queues.send(QueueMessage::AddFile(file.clone(), watch, modify)).unwrap();
+            file
+        };
+
+        let source = send(&shared.join("main.rs"), &shared);
+        let expected = format!("build {}", source.display());
+        assert_eq!(next_run(&rx), (Some("rust".into()), expected));
+
+        send(&shared.join("a.css"), &shared);
+        assert_eq!(next_run(&rx), (Some("style".into()), "lint".into()));
+
+        // The tasks with their own dirs only get the files of these dirs,
+        // whatever the extension for a task without extensions
+        let page = send(&front.join("page.html"), &front);
+        let expected = format!("front {}", page.display());
+        assert_eq!(next_run(&rx), (Some("front".into()), expected));
+
+        send(&back.join("lib.rs"), &back);
+        assert_eq!(next_run(&rx), (Some("back".into()), "back".into()));
+
+        // Files of no task run nothing, the tasks apply the filter options
+        send(&shared.join("a.txt"), &shared);
+        send(&shared.join("generated.rs"), &shared);
+        send(&front.join("generated.html"), &front);
+        send(&back.join("notes.txt"), &back);
+        assert!(queues.pending_paths(Duration::from_secs(1)).unwrap().is_empty());
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(!rx.try_iter().any(|e| matches!(e, Event::Exec(ExecMessage::Start(_)))));
+        queues.stop();
+        assert!(!queues.is_alive());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::command::execution_report::ChangeKind;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Files larger than this are not snapshotted, to bound the memory usage
+const MAX_SNAPSHOT_SIZE: u64 = 1024 * 1024;
+/// Number of unchanged lines shown around a change
+const CONTEXT_LINES: usize = 3;
+/// Above this number of compared line pairs, changed blocks are shown as
+/// fully removed then added instead of being diffed line by line
+const MAX_DIFF_CELLS: usize = 1_000_000;
+
+/// Last seen content of the updated text files, used for --diff
+#[derive(Default)]
+pub struct Snapshots {
+    contents: HashMap<PathBuf, String>,
+}
+
+impl Snapshots {
+    /// Records the current content of a file, e.g. before its first update
+    pub fn record(&mut self, path: &Path) {
+        if let Some(content) = read_text(path) {
+            self.contents.insert(path.to_path_buf(), content);
+        }
+    }
+
+    /// Records the current content of a file and returns a unified diff
+    /// against its previous content.
+    /// Returns None if the file has no known previous content (the first
+    /// update of a modified file), is not text, is too large or is unchanged
+    pub fn diff(&mut self, path: &Path, kind: ChangeKind) -> Option<String> {
+        let new = read_text(path);
+        let old = match &new {
+            Some(content) => self.contents.insert(path.to_path_buf(), content.clone()),
+            None => self.contents.remove(path),
+        };
+        let old = match old {
+            Some(content) => content,
+            None if kind == ChangeKind::Created => String::new(),
+            None => return None,
+        };
+        let new = match new {
+            Some(content) => content,
+            None if !path.exists() => String::new(),
+            None => return None,
+        };
+        unified_diff(path, &old, &new)
+    }
+}
+
+/// Reads a file if it is a text file small enough to be snapshotted
+fn read_text(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_SNAPSHOT_SIZE {
+        return None;
+    }
+    String::from_utf8(std::fs::read(path).ok()?).ok()
+}
+
+/// Formats the changes between two contents as a unified diff with a single
+/// hunk, from the first to the last changed line
+fn unified_diff(path: &Path, old: &str, new: &str) -> Option<String> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    if old == new {
+        return None;
+    }
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+
+    let start = prefix.saturating_sub(CONTEXT_LINES);
+    let trailing = suffix.min(CONTEXT_LINES);
+    let mut lines: Vec<(char, &str)> = old[start..prefix].iter().map(|l| (' ', *l)).collect();
+    lines.extend(diff_lines(&old[prefix..old_end], &new[prefix..new_end]));
+    lines.extend(old[old_end..old_end + trailing].iter().map(|l| (' ', *l)));
+
+    let old_count = lines.iter().filter(|(op, _)| *op != '+').count();
+    let new_count = lines.iter().filter(|(op, _)| *op != '-').count();
+    // An empty range starts at the line before it
+    let first_line = |count: usize| if count == 0 { start } else { start + 1 };
+
+    let mut diff = format!("--- {0}\n+++ {0}\n", path.display());
+    diff.push_str(&format!(
+        "@@ -{},{} +{},{} @@\n",
+        first_line(old_count),
+        old_count,
+        first_line(new_count),
+        new_count
+    ));
+    for (op, line) in lines {
+        diff.push(op);
+        diff.push_str(line);
+        diff.push('\n');
+    }
+    Some(diff)
+}
+
+/// Line by line diff of two blocks, based on their longest common subsequence
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(char, &'a str)> {
+    if old.len() * new.len() > MAX_DIFF_CELLS {
+        let removed = old.iter().map(|l| ('-', *l));
+        return removed.chain(new.iter().map(|l| ('+', *l))).collect();
+    }
+
+    // lcs[i][j]: length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::with_capacity(old.len() + new.len());
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|l| ('-', *l)));
+    lines.extend(new[j..].iter().map(|l| ('+', *l)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_line_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.txt");
+        std::fs::write(&file, "1\n2\n3\n4\n5\n6\n7\n8\n9\n").unwrap();
+
+        let mut snapshots = Snapshots::default();
+        assert_eq!(snapshots.diff(&file, ChangeKind::Modified), None);
+
+        std::fs::write(&file, "1\n2\n3\n4\nfive\n6\n7\n8\n9\n").unwrap();
+        let expected = format!(
+            "--- {0}\n+++ {0}\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n",
+            file.display()
+        );
+        assert_eq!(snapshots.diff(&file, ChangeKind::Modified), Some(expected));
+        assert_eq!(snapshots.diff(&file, ChangeKind::Modified), None);
+    }
+
+    #[test]
+    fn test_created_and_deleted_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("new.txt");
+        std::fs::write(&file, "a\n").unwrap();
+
+        let mut snapshots = Snapshots::default();
+        let diff = snapshots.diff(&file, ChangeKind::Created).unwrap();
+        assert!(diff.ends_with("@@ -0,0 +1,1 @@\n+a\n"));
+
+        std::fs::remove_file(&file).unwrap();
+        let diff = snapshots.diff(&file, ChangeKind::Deleted).unwrap();
+        assert!(diff.ends_with("@@ -1,1 +0,0 @@\n-a\n"));
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let ops = diff_lines(&["a", "b", "c"], &["a", "c", "d"]);
+        assert_eq!(ops, vec![(' ', "a"), ('-', "b"), (' ', "c"), ('+', "d")]);
+    }
+}
@@ -42,6 +42,12 @@ pub enum RuntimeError {
     #[error("File error: {0} {1}")]
     FileError(String, String),
 
+    #[error("Failed to watch {0}: {1}")]
+    WatchRegistrationError(String, String),
+
+    #[error("Cannot watch {0}: it is a {1}, only regular files and directories can be watched")]
+    UnsupportedFileType(String, String),
+
     #[error("Failed to execute command: {0}")]
     CommandExecutionError(String),
 
@@ -50,6 +56,12 @@ pub enum RuntimeError {
 
     #[error("Channel Error: {0}")]
     ChannelReceiveError(String),
+
+    #[error("{0} stopped unexpectedly")]
+    ThreadStopped(String),
+
+    #[error("git failed in {0}: {1}")]
+    GitError(String, String),
 }
 
 #[derive(Error, Debug)]
@@ -66,9 +78,15 @@ pub enum ArgumentError {
     #[error("Invalid current working directory: {0}")]
     InvalidCurrentWorkingDirectory(String),
 
-    #[error("Invalid regular expression: {0} {1}")]
-    InvalidRegex(String, String),
+    #[error("Invalid regular expression for {0}: {1} {2}")]
+    InvalidRegex(&'static str, String, String),
 
     #[error("Command to execute is empty")]
     EmptyCommand,
+
+    #[error("Invalid value for {0}: {1}ms is too short, the minimum is {2}ms")]
+    PollIntervalTooShort(&'static str, u64, u64),
+
+    #[error("{0} is not supported on this platform")]
+    UnsupportedOnPlatform(String),
 }
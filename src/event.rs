@@ -14,4 +14,12 @@ pub enum Event {
     TogglePause,
     // User wishes to abort ongoing commands
     AbortOngoingCommands,
+    // Run the command now, without waiting for a file update
+    RunNow,
+    // Run the last command again with an additional environment variable
+    RerunWithEnv(String, String),
+    // Read the git configuration and the files tracked by git again
+    Reload,
+    // User wishes to see the full command in the title, or truncate it again
+    ToggleTitle,
 }
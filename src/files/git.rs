@@ -1,51 +1,201 @@
 use same_file;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf, absolute};
-
-pub fn is_git_ignored(filename: &PathBuf, watch: &PathBuf) -> bool {
+use std::process::Command;
+use std::sync::{Mutex, PoisonError};
+
+/// Ignore files read in each directory, from the highest precedence.
+/// .gitignore files are skipped with --no-gitignore, the others are still read
+const IGNORE_FILES: [&str; 3] = [".rexignore", ".ignore", ".gitignore"];
+/// Global excludes file of git
+static GLOBAL_EXCLUDES: CachedExcludes = CachedExcludes::new();
+
+/// Checks if a file is ignored by the ignore files between it and the watch.
+/// .gitignore files are only read if `gitignore` is true
+pub fn is_git_ignored(filename: &PathBuf, watch: &PathBuf, gitignore: bool) -> bool {
     let abs_path = absolute(filename).unwrap_or(filename.clone());
-    let all_rules = GitIgnoreRules::from_dir(&abs_path, watch);
-
-    // Check if a negative rule matches, if yes, it is not ignored, no matter
-    // the other matches
-    for ignore_rules in &all_rules {
-        let ignore_path = &ignore_rules.rule_path;
-        for rule in &ignore_rules.rules {
-            if !rule.is_negated {
-                continue;
-            }
-            if rule.file_matches(&abs_path, &ignore_path) {
-                log::debug!(
-                    "gitignore: {:?} re-included by negated rule '{}' in {:?}",
-                    filename,
-                    rule.raw,
-                    ignore_path
-                );
-                return false;
-            }
+    let all_rules = all_rules(&abs_path, watch, gitignore, global_excludes_file().as_deref());
+
+    match matching_rule(&abs_path, &all_rules) {
+        Some((ignore_rules, rule)) if rule.is_negated => {
+            log::debug!(
+                "gitignore: {:?} re-included by negated rule '{}' in {:?}",
+                filename,
+                rule.raw,
+                ignore_rules.file
+            );
+            false
         }
+        Some((ignore_rules, rule)) => {
+            log::debug!(
+                "gitignore: {:?} ignored by rule '{}' in {:?}",
+                filename,
+                rule.raw,
+                ignore_rules.file
+            );
+            true
+        }
+        None => false,
     }
+}
 
-    // Second pass, non-negated rules
+/// Describes the ignore files consulted for a file and the rule that
+/// decides if it is ignored, for --explain-ignore
+pub fn explain_ignore(filename: &Path, watch: &Path, gitignore: bool) -> String {
+    let abs_path = absolute(filename).unwrap_or(filename.to_path_buf());
+    let all_rules =
+        all_rules(&abs_path, &watch.to_path_buf(), gitignore, global_excludes_file().as_deref());
+
+    let mut explanation = format!("{} (watch: {})\n", abs_path.display(), watch.display());
+    if all_rules.is_empty() {
+        explanation.push_str("No ignore file consulted\n");
+    }
     for ignore_rules in &all_rules {
-        let ignore_path = &ignore_rules.rule_path;
-        for rule in &ignore_rules.rules {
-            if rule.is_negated {
-                continue;
-            }
-            if rule.file_matches(&abs_path, &ignore_path) {
-                log::debug!(
-                    "gitignore: {:?} ignored by rule '{}' in {:?}",
-                    filename,
-                    rule.raw,
-                    ignore_path
-                );
-                return true;
+        let count = ignore_rules.rules.len();
+        explanation.push_str(&format!(
+            "Consulted {} ({count} rule{})\n",
+            ignore_rules.file.display(),
+            if count == 1 { "" } else { "s" }
+        ));
+    }
+    let decision = match matching_rule(&abs_path, &all_rules) {
+        Some((ignore_rules, rule)) => format!(
+            "{} by rule '{}' at {}:{}",
+            if rule.is_negated { "Not ignored: re-included" } else { "Ignored" },
+            rule.raw,
+            ignore_rules.file.display(),
+            rule.line
+        ),
+        None => "Not ignored: no rule matches".to_string(),
+    };
+    explanation.push_str(&decision);
+    explanation.push('\n');
+    explanation
+}
+
+/// Collects the rules of the ignore files between a file and the watch, then
+/// the global excludes file of git, with the lowest precedence
+fn all_rules(
+    abs_path: &Path,
+    watch: &PathBuf,
+    gitignore: bool,
+    global: Option<&Path>,
+) -> Vec<GitIgnoreRules> {
+    let mut rules = GitIgnoreRules::from_dir(abs_path, watch, gitignore);
+    if let Some(global) = global.filter(|g| gitignore && g.is_file()) {
+        // Global patterns are relative to the watched directory
+        let dir = if watch.is_dir() { watch } else { watch.parent().unwrap_or(watch) };
+        let global_rules = GitIgnoreRules::from_ignore_file(global);
+        rules.push(GitIgnoreRules { rule_path: dir.to_path_buf(), ..global_rules });
+    }
+    rules
+}
+
+/// Excludes file resolved once, until it is reset. `None` until it is
+/// resolved
+struct CachedExcludes(Mutex<Option<Option<PathBuf>>>);
+
+impl CachedExcludes {
+    const fn new() -> Self {
+        Self(Mutex::new(None))
+    }
+
+    /// Returns the cached file, resolving it if needed
+    fn get(&self, resolve: impl FnOnce() -> Option<PathBuf>) -> Option<PathBuf> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert_with(resolve)
+            .clone()
+    }
+
+    /// Forgets the file, so that it is resolved again on the next use
+    fn reset(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// Global excludes file of git, resolved once until the git configuration is
+/// reloaded
+fn global_excludes_file() -> Option<PathBuf> {
+    GLOBAL_EXCLUDES.get(|| {
+        let configured = Command::new("git")
+            .args(["config", "--path", "--get", "core.excludesFile"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+        let file = resolve_global_excludes(
+            configured,
+            std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+            std::env::var_os("HOME").map(PathBuf::from),
+        );
+        log::debug!("Global excludes file: {:?}", file);
+        file
+    })
+}
+
+/// Forgets the global excludes file, so that the git configuration is read
+/// again on the next file update
+pub fn reload_global_excludes() {
+    GLOBAL_EXCLUDES.reset();
+}
+
+/// Picks the global excludes file: core.excludesFile if configured, else
+/// $XDG_CONFIG_HOME/git/ignore (defaulting to ~/.config/git/ignore), else
+/// ~/.gitignore. The fallbacks are only used if they exist
+fn resolve_global_excludes(
+    configured: Option<String>,
+    xdg_config_home: Option<PathBuf>,
+    home: Option<PathBuf>,
+) -> Option<PathBuf> {
+    if let Some(configured) = configured.filter(|c| !c.is_empty()) {
+        return Some(PathBuf::from(configured));
+    }
+    let xdg_config_home = xdg_config_home
+        .filter(|p| !p.as_os_str().is_empty())
+        .or_else(|| home.as_ref().map(|h| h.join(".config")));
+    let candidates =
+        [xdg_config_home.map(|p| p.join("git/ignore")), home.map(|h| h.join(".gitignore"))];
+    candidates.into_iter().flatten().find(|p| p.is_file())
+}
+
+/// Finds the rule deciding if a file is ignored, with the rules it belongs to
+fn matching_rule<'a>(
+    abs_path: &Path,
+    all_rules: &'a [GitIgnoreRules],
+) -> Option<(&'a GitIgnoreRules, &'a GitIgnoreRule)> {
+    // Check if a negative rule matches, if yes, it is not ignored, no matter
+    // the other matches. Second pass, non-negated rules
+    for negated in [true, false] {
+        for ignore_rules in all_rules {
+            let ignore_path = &ignore_rules.rule_path;
+            for rule in &ignore_rules.rules {
+                if rule.is_negated == negated && rule.file_matches(abs_path, ignore_path) {
+                    return Some((ignore_rules, rule));
+                }
             }
         }
     }
+    None
+}
+
+/// Checks if a file matches a gitignore-style pattern, relative to the watch
+pub fn matches_pattern(pattern: &str, filename: &Path, watch: &Path) -> bool {
+    GitIgnoreRule::from_str(pattern).is_some_and(|rule| rule_matches(&rule, filename, watch))
+}
 
-    false
+/// Same as matches_pattern, but the pattern is taken verbatim: its trailing
+/// spaces are part of it
+pub fn matches_raw_pattern(pattern: &str, filename: &Path, watch: &Path) -> bool {
+    GitIgnoreRule::parse(pattern, false).is_some_and(|rule| rule_matches(&rule, filename, watch))
+}
+
+/// Checks if a file matches a rule, relative to the watch
+fn rule_matches(rule: &GitIgnoreRule, filename: &Path, watch: &Path) -> bool {
+    let abs_path = absolute(filename).unwrap_or(filename.to_path_buf());
+    let dir = if watch.is_dir() { watch } else { watch.parent().unwrap_or(watch) };
+    rule.file_matches(&abs_path, &dir)
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -115,6 +265,8 @@ enum GitIgnoreRuleElements {
 struct GitIgnoreRule {
     /// Original pattern string
     raw: String,
+    /// Line of the pattern in its ignore file, 0 if not read from a file
+    line: usize,
     /// Pattern
     pattern: Vec<GitIgnoreRuleElements>,
     /// Is the pattern negated
@@ -129,6 +281,12 @@ struct GitIgnoreRule {
 impl GitIgnoreRule {
     /// Creates a GitIgnoreRule from a line
     fn from_str<S: AsRef<str>>(line: S) -> Option<Self> {
+        Self::parse(line, true)
+    }
+
+    /// Creates a GitIgnoreRule from a line, optionally keeping its unescaped
+    /// trailing spaces
+    fn parse<S: AsRef<str>>(line: S, trim_trailing_spaces: bool) -> Option<Self> {
         let mut pattern = Vec::new();
         let raw = line.as_ref().to_string();
         let line: &str = line.as_ref();
@@ -138,10 +296,13 @@ impl GitIgnoreRule {
         }
 
         let is_negated = line.starts_with("!");
-        let match_all_levels = line[..line.len() - 1].chars().filter(|c| *c == '/').count() == 0;
         let dirs_only = line.ends_with("/");
 
         let line = if is_negated { &line[1..] } else { line };
+        // Without a separator before the end, or with a leading **/, the
+        // pattern matches at any level below the .gitignore
+        let match_all_levels =
+            line.starts_with("**/") || !line.strip_suffix('/').unwrap_or(line).contains('/');
 
         // Trim whitespaces at the end if they are not preceeded with a backslash
         let mut spaces_to_trim = 0;
@@ -153,6 +314,9 @@ impl GitIgnoreRule {
                 spaces_to_trim += 1;
             }
         }
+        if !trim_trailing_spaces {
+            spaces_to_trim = 0;
+        }
         let line = &line[..line.len() - spaces_to_trim];
 
         let mut chars = line.chars().peekable();
@@ -187,14 +351,37 @@ impl GitIgnoreRule {
                         chars.next();
                     }
 
+                    // A ']' right after '[' or '[!' is a literal, as in fnmatch
+                    let mut first = true;
                     while let Some(start_char) = chars.next() {
-                        if start_char == ']' {
+                        if start_char == ']' && !first {
                             break;
                         }
+                        first = false;
+                        // Escaped characters are literals, e.g. [a\-z]
+                        let start_char = match start_char {
+                            '\\' => match chars.next() {
+                                Some(escaped) => escaped,
+                                None => break,
+                            },
+                            c => c,
+                        };
                         if chars.peek() == Some(&'-') {
                             chars.next(); // Consume '-'
-                            if let Some(end_char) = chars.next() {
-                                range.push((start_char, end_char));
+                            match chars.next() {
+                                // A '-' closing the class is a literal, e.g. [a-]
+                                Some(']') => {
+                                    range.push((start_char, start_char));
+                                    range.push(('-', '-'));
+                                    break;
+                                }
+                                Some('\\') => {
+                                    if let Some(end_char) = chars.next() {
+                                        range.push((start_char, end_char));
+                                    }
+                                }
+                                Some(end_char) => range.push((start_char, end_char)),
+                                None => {}
                             }
                         } else {
                             range.push((start_char, start_char));
@@ -218,7 +405,7 @@ impl GitIgnoreRule {
             }
         }
 
-        Some(GitIgnoreRule { raw, pattern, is_negated, match_all_levels, dirs_only })
+        Some(GitIgnoreRule { raw, line: 0, pattern, is_negated, match_all_levels, dirs_only })
     }
 
     /// Checks if the current git ignore rule matches a file within a dir
@@ -419,6 +606,8 @@ struct GitIgnoreRules {
     pub rules: Vec<GitIgnoreRule>,
     /// Directory where the rule file is located
     pub rule_path: PathBuf,
+    /// Ignore file the rules were read from
+    pub file: PathBuf,
 }
 
 impl GitIgnoreRules {
@@ -427,27 +616,36 @@ impl GitIgnoreRules {
         let mut rules = Vec::new();
 
         if let Ok(file) = std::fs::File::open(path) {
-            for line in BufReader::new(file).lines().map_while(Result::ok) {
+            for (index, line) in BufReader::new(file).lines().map_while(Result::ok).enumerate() {
                 let rule = GitIgnoreRule::from_str(line);
                 if let Some(r) = rule {
-                    rules.push(r);
+                    rules.push(GitIgnoreRule { line: index + 1, ..r });
                 }
             }
         } else {
             log::warn!("Error reading contents of {path:?}");
         }
 
-        Self { rules, rule_path: path.parent().unwrap_or(path).to_path_buf() }
+        Self {
+            rules,
+            rule_path: path.parent().unwrap_or(path).to_path_buf(),
+            file: path.to_path_buf(),
+        }
     }
 
     /// Starts collecting GitIgnoreRules from the path, going up to the watch
-    /// directory
-    fn from_dir(path: &Path, watch: &PathBuf) -> Vec<Self> {
+    /// directory. In each directory, the files are read in the order of
+    /// IGNORE_FILES. Negated rules win over the others wherever they are, so
+    /// the order only decides which rule explains an ignored file
+    fn from_dir(path: &Path, watch: &PathBuf, gitignore: bool) -> Vec<Self> {
         let mut rules: Vec<Self> = Vec::new();
         let mut current_path = if path.is_dir() { Some(path) } else { path.parent() };
 
         while let Some(dir) = current_path {
-            for ignore_file_name in &[".gitignore"] {
+            for ignore_file_name in IGNORE_FILES {
+                if !gitignore && ignore_file_name == ".gitignore" {
+                    continue;
+                }
                 let ignore_path = dir.join(ignore_file_name);
                 if !ignore_path.exists() {
                     continue;
@@ -501,6 +699,33 @@ mod tests {
             ]
         );
 
+        // Test a literal ']' at the start of a range
+        let rule = GitIgnoreRule::from_str("[]x]").unwrap();
+        assert_eq!(
+            rule.pattern,
+            vec![GitIgnoreRuleElements::CharRange((false, vec![(']', ']'), ('x', 'x')]))]
+        );
+        let rule = GitIgnoreRule::from_str("[!]x]").unwrap();
+        assert_eq!(
+            rule.pattern,
+            vec![GitIgnoreRuleElements::CharRange((true, vec![(']', ']'), ('x', 'x')]))]
+        );
+
+        // Test escaped characters and literal hyphens in a range
+        let rule = GitIgnoreRule::from_str("[a\\-z]").unwrap();
+        assert_eq!(
+            rule.pattern,
+            vec![GitIgnoreRuleElements::CharRange((
+                false,
+                vec![('a', 'a'), ('-', '-'), ('z', 'z')]
+            ))]
+        );
+        let rule = GitIgnoreRule::from_str("[a-]").unwrap();
+        assert_eq!(
+            rule.pattern,
+            vec![GitIgnoreRuleElements::CharRange((false, vec![('a', 'a'), ('-', '-')]))]
+        );
+
         // Test comments
         let rule = GitIgnoreRule::from_str("#foo[bar].txt");
         assert!(rule.is_none());
@@ -534,6 +759,16 @@ mod tests {
         let rule = GitIgnoreRule::from_str("a/**/b").unwrap();
         assert!(rule.file_matches(dir.join("a/x/y/b").as_path(), &dir));
         assert!(!rule.file_matches(dir.join("a/x/y/c").as_path(), &dir));
+
+        let rule = GitIgnoreRule::from_str("[]x]").unwrap();
+        assert!(rule.file_matches(dir.join("]").as_path(), &dir));
+        assert!(rule.file_matches(dir.join("x").as_path(), &dir));
+        assert!(!rule.file_matches(dir.join("y").as_path(), &dir));
+
+        let rule = GitIgnoreRule::from_str("[a\\-z]").unwrap();
+        assert!(rule.file_matches(dir.join("-").as_path(), &dir));
+        assert!(rule.file_matches(dir.join("z").as_path(), &dir));
+        assert!(!rule.file_matches(dir.join("b").as_path(), &dir));
     }
 
     #[test]
@@ -580,7 +815,7 @@ mod tests {
         let mut file = File::create(&sub_ignore).unwrap();
         writeln!(file, "!important.log").unwrap();
 
-        let rules = GitIgnoreRules::from_dir(&subdir, &dir.path().to_path_buf());
+        let rules = GitIgnoreRules::from_dir(&subdir, &dir.path().to_path_buf(), true);
         assert_eq!(rules.len(), 2);
 
         // Check root .gitignore
@@ -600,6 +835,139 @@ mod tests {
         assert!(rules[0].rules[0].is_negated);
     }
 
+    #[test]
+    fn test_raw_pattern_keeps_trailing_spaces() {
+        let dir = tempdir().unwrap();
+        let spaced = dir.path().join("notes ");
+        let plain = dir.path().join("notes");
+
+        assert!(matches_raw_pattern("notes ", &spaced, dir.path()));
+        assert!(!matches_raw_pattern("notes ", &plain, dir.path()));
+        // Gitignore patterns lose their trailing spaces
+        assert!(matches_pattern("notes ", &plain, dir.path()));
+        assert!(!matches_pattern("notes ", &spaced, dir.path()));
+    }
+
+    #[test]
+    fn test_explain_ignore() {
+        let dir = tempdir().unwrap();
+        let subdir = dir.path().join("subdir");
+        fs::create_dir(&subdir).unwrap();
+        fs::write(dir.path().join(".gitignore"), "# logs\n*.log\n*.tmp\n").unwrap();
+        fs::write(subdir.join(".gitignore"), "!important.log\n").unwrap();
+
+        let explanation = explain_ignore(&subdir.join("debug.tmp"), dir.path(), true);
+        let root_ignore = dir.path().join(".gitignore");
+        assert!(explanation.contains(&format!("Consulted {} (2 rules)", root_ignore.display())));
+        assert!(
+            explanation
+                .contains(&format!("Consulted {} (1 rule)", subdir.join(".gitignore").display()))
+        );
+        assert!(
+            explanation
+                .ends_with(&format!("Ignored by rule '*.tmp' at {}:3\n", root_ignore.display()))
+        );
+
+        let explanation = explain_ignore(&subdir.join("important.log"), dir.path(), true);
+        assert!(explanation.contains("Not ignored: re-included by rule '!important.log'"));
+
+        let explanation = explain_ignore(&subdir.join("main.rs"), dir.path(), true);
+        assert!(explanation.ends_with("Not ignored: no rule matches\n"));
+    }
+
+    #[test]
+    fn test_global_excludes() {
+        let dir = tempdir().unwrap();
+        let watch = dir.path().join("project");
+        fs::create_dir(&watch).unwrap();
+        fs::write(watch.join(".gitignore"), "!keep.orig\n").unwrap();
+        let global = dir.path().join("global_ignore");
+        fs::write(&global, "*.orig\n").unwrap();
+
+        let rules = all_rules(&watch.join("main.rs.orig"), &watch, true, Some(&global));
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[1].file, global);
+        let (ignore_rules, rule) = matching_rule(&watch.join("main.rs.orig"), &rules).unwrap();
+        assert!(!rule.is_negated);
+        assert_eq!(ignore_rules.file, global);
+        // Local rules come first
+        let (_, rule) = matching_rule(&watch.join("keep.orig"), &rules).unwrap();
+        assert!(rule.is_negated);
+        // Skipped with --no-gitignore
+        assert!(all_rules(&watch.join("main.rs.orig"), &watch, false, Some(&global)).is_empty());
+    }
+
+    #[test]
+    fn test_reload_global_excludes() {
+        let dir = tempdir().unwrap();
+        let watch = dir.path().join("project");
+        fs::create_dir(&watch).unwrap();
+        let (old, new) = (dir.path().join("old_ignore"), dir.path().join("new_ignore"));
+        fs::write(&old, "*.orig\n").unwrap();
+        fs::write(&new, "*.bak\n").unwrap();
+        let is_ignored = |file: &str, global: Option<PathBuf>| {
+            let file = watch.join(file);
+            matching_rule(&file, &all_rules(&file, &watch, true, global.as_deref())).is_some()
+        };
+
+        let cache = CachedExcludes::new();
+        let global = cache.get(|| Some(old.clone()));
+        assert!(is_ignored("main.rs.orig", global));
+        // The configuration changed, the cached file is still used
+        let global = cache.get(|| Some(new.clone()));
+        assert!(!is_ignored("main.rs.bak", global));
+
+        cache.reset();
+        let global = cache.get(|| Some(new.clone()));
+        assert!(is_ignored("main.rs.bak", global.clone()));
+        assert!(!is_ignored("main.rs.orig", global));
+    }
+
+    #[test]
+    fn test_resolve_global_excludes() {
+        let home = tempdir().unwrap();
+        let home_path = Some(home.path().to_path_buf());
+        assert_eq!(resolve_global_excludes(None, None, home_path.clone()), None);
+
+        let configured = Some("/etc/gitignore".to_string());
+        let resolved = resolve_global_excludes(configured, None, home_path.clone());
+        assert_eq!(resolved, Some(PathBuf::from("/etc/gitignore")));
+
+        fs::write(home.path().join(".gitignore"), "").unwrap();
+        let resolved = resolve_global_excludes(None, None, home_path.clone());
+        assert_eq!(resolved, Some(home.path().join(".gitignore")));
+
+        fs::create_dir_all(home.path().join(".config/git")).unwrap();
+        fs::write(home.path().join(".config/git/ignore"), "").unwrap();
+        let resolved = resolve_global_excludes(None, None, home_path);
+        assert_eq!(resolved, Some(home.path().join(".config/git/ignore")));
+    }
+
+    #[test]
+    fn test_other_ignore_files() {
+        let dir = tempdir().unwrap();
+        let watch = dir.path().to_path_buf();
+        fs::write(dir.path().join(".gitignore"), "*.log\n!keep.orig\n").unwrap();
+        fs::write(dir.path().join(".ignore"), "*.tmp\n").unwrap();
+        fs::write(dir.path().join(".rexignore"), "*.orig\n").unwrap();
+
+        assert!(is_git_ignored(&watch.join("debug.log"), &watch, true));
+        assert!(is_git_ignored(&watch.join("scratch.tmp"), &watch, true));
+        assert!(is_git_ignored(&watch.join("main.rs.orig"), &watch, true));
+        // A negation in a .gitignore re-includes a file of the .rexignore
+        assert!(!is_git_ignored(&watch.join("keep.orig"), &watch, true));
+
+        // Without .gitignore, the other files still apply
+        assert!(!is_git_ignored(&watch.join("debug.log"), &watch, false));
+        assert!(is_git_ignored(&watch.join("scratch.tmp"), &watch, false));
+        assert!(is_git_ignored(&watch.join("keep.orig"), &watch, false));
+
+        let explanation = explain_ignore(&watch.join("main.rs.orig"), &watch, true);
+        let rexignore = dir.path().join(".rexignore");
+        assert!(explanation.contains(&format!("Consulted {} (1 rule)", rexignore.display())));
+        assert!(explanation.ends_with(&format!("by rule '*.orig' at {}:1\n", rexignore.display())));
+    }
+
     #[test]
     fn test_complex_patterns() {
         let dir = tempdir().unwrap();
@@ -768,4 +1136,29 @@ mod tests {
         assert!(rule.file_matches(dir.join("sub/error.log").as_path(), &dir));
         assert!(!rule.file_matches(dir.join("error.txt").as_path(), &dir));
     }
+
+    #[test]
+    fn test_leading_double_asterisk() {
+        let dir = tempdir().unwrap();
+        let dir = dir.path();
+
+        // **/foo is the same as foo
+        for pattern in ["**/foo", "foo", "!**/foo"] {
+            let rule = GitIgnoreRule::from_str(pattern).unwrap();
+            assert!(rule.match_all_levels, "{pattern}");
+            assert!(rule.file_matches(dir.join("foo").as_path(), &dir), "{pattern}");
+            assert!(rule.file_matches(dir.join("a/b/foo").as_path(), &dir), "{pattern}");
+            assert!(rule.file_matches(dir.join("a/foo/bar").as_path(), &dir), "{pattern}");
+            assert!(!rule.file_matches(dir.join("a/b/foobar").as_path(), &dir), "{pattern}");
+        }
+
+        // **/foo/bar: bar directly under a foo directory, anywhere
+        let rule = GitIgnoreRule::from_str("**/foo/bar").unwrap();
+        assert!(rule.file_matches(dir.join("foo/bar").as_path(), &dir));
+        assert!(rule.file_matches(dir.join("a/foo/bar").as_path(), &dir));
+        assert!(!rule.file_matches(dir.join("a/foo/x/bar").as_path(), &dir));
+
+        // A trailing multi-byte character does not break the classification
+        assert!(GitIgnoreRule::from_str("caf\u{e9}").unwrap().match_all_levels);
+    }
 }
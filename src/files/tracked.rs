@@ -0,0 +1,128 @@
+use crate::errors::{ProgramError, RuntimeError, runtime_error};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+/// Files tracked by git under each watch, for --tracked-only.
+/// The list of a watch is read again when its git index changes
+pub struct TrackedFiles {
+    repos: HashMap<PathBuf, TrackedRepo>,
+}
+
+struct TrackedRepo {
+    /// Directory where git is run
+    dir: PathBuf,
+    /// Index of the repository, listing the tracked files
+    index: PathBuf,
+    /// Modification time and size of the index when the files were listed
+    index_state: Option<(SystemTime, u64)>,
+    files: HashSet<PathBuf>,
+}
+
+impl TrackedFiles {
+    /// Lists the tracked files under each watch.
+    /// Fails if a watch is not in a git repository
+    pub fn new(watches: &[PathBuf]) -> Result<Self, ProgramError> {
+        let mut repos = HashMap::new();
+        for watch in watches {
+            let dir = if watch.is_dir() { watch.clone() } else { watch.parent().unwrap().into() };
+            let index = dir.join(git(&dir, &["rev-parse", "--git-path", "index"])?.trim_end());
+            let mut repo = TrackedRepo { dir, index, index_state: None, files: HashSet::new() };
+            repo.refresh()?;
+            repos.insert(watch.clone(), repo);
+        }
+        Ok(Self { repos })
+    }
+
+    /// Checks if a file of a watch is tracked by git
+    pub fn is_tracked(&mut self, filename: &Path, watch: &Path) -> bool {
+        let Some(repo) = self.repos.get_mut(watch) else {
+            return false;
+        };
+        if repo.index_state != index_state(&repo.index)
+            && let Err(e) = repo.refresh()
+        {
+            log::warn!("Could not list the files tracked by git: {e}");
+        }
+        repo.files.contains(filename)
+    }
+
+    /// Checks if a file was tracked when the files were last listed
+    #[cfg(test)]
+    pub fn is_listed(&self, filename: &Path, watch: &Path) -> bool {
+        self.repos.get(watch).is_some_and(|repo| repo.files.contains(filename))
+    }
+
+    /// Lists the tracked files again, even if the git indexes did not change
+    pub fn reload(&mut self) {
+        for repo in self.repos.values_mut() {
+            if let Err(e) = repo.refresh() {
+                log::warn!("Could not list the files tracked by git: {e}");
+            }
+        }
+    }
+}
+
+impl TrackedRepo {
+    /// Lists the tracked files again
+    fn refresh(&mut self) -> Result<(), ProgramError> {
+        self.index_state = index_state(&self.index);
+        let listed = git(&self.dir, &["ls-files", "-z"])?;
+        self.files =
+            listed.split('\0').filter(|f| !f.is_empty()).map(|f| self.dir.join(f)).collect();
+        log::debug!("{} files tracked by git in {:?}", self.files.len(), self.dir);
+        Ok(())
+    }
+}
+
+/// Modification time and size of the git index, to notice its updates
+fn index_state(index: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(index).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Runs git in a directory and returns its output
+fn git(dir: &Path, args: &[&str]) -> Result<String, ProgramError> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| runtime_error!(GitError, dir.display().to_string(), e.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(runtime_error!(GitError, dir.display().to_string(), stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracked_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        git(&root, &["init", "-q"]).unwrap();
+        let (tracked, untracked) = (root.join("tracked.rs"), root.join("untracked.rs"));
+        std::fs::write(&tracked, "").unwrap();
+        std::fs::write(&untracked, "").unwrap();
+        git(&root, &["add", "tracked.rs"]).unwrap();
+
+        let mut files = TrackedFiles::new(std::slice::from_ref(&root)).unwrap();
+        assert!(files.is_tracked(&tracked, &root));
+        assert!(!files.is_tracked(&untracked, &root));
+
+        // Adding the file updates the index
+        git(&root, &["add", "untracked.rs"]).unwrap();
+        assert!(files.is_tracked(&untracked, &root));
+    }
+
+    #[test]
+    fn test_not_a_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(TrackedFiles::new(&[dir.path().to_path_buf()]).is_err());
+    }
+}
@@ -1,8 +1,9 @@
 use crate::Args;
-use crate::files::git::is_git_ignored;
+use crate::errors::{ProgramError, RuntimeError, runtime_error};
+use crate::files::git::{is_git_ignored, matches_pattern, matches_raw_pattern};
 
 use regex::Regex;
-use std::path::{Path, PathBuf};
+use std::path::{Path, PathBuf, absolute};
 
 macro_rules! is_some_or_return {
     ($opt:expr, $ret:expr) => {
@@ -20,34 +21,191 @@ macro_rules! is_ok_or_return {
     };
 }
 
+/// Returns the absolute path of a file or directory, with symlinks resolved
+pub fn canonical_path(file: &str) -> Result<PathBuf, ProgramError> {
+    absolute(file)
+        .map_err(|e| runtime_error!(FileError, file.to_string(), e.to_string()))?
+        .canonicalize()
+        .map_err(|e| runtime_error!(FileError, file.to_string(), e.to_string()))
+}
+
+/// Lists the files under a watched file/dir that would trigger the command.
+/// Ignored or hidden directories are not walked into, symlinks are skipped
+pub fn scan_watch(watch: &PathBuf, args: &Args) -> Vec<PathBuf> {
+    if !watch.is_dir() {
+        return if should_be_ignored(watch, args, watch) { vec![] } else { vec![watch.clone()] };
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![watch.clone()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            log::warn!("Could not scan {:?}", dir);
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                let ignored = is_git_ignored(&path, watch, !args.no_gitignore)
+                    || (!args.hidden && is_hidden(&path, watch));
+                if !ignored {
+                    dirs.push(path);
+                }
+            } else if file_type.is_file() && !should_be_ignored(&path, args, watch) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Checks if a path is an existing regular file of zero bytes
+pub fn is_empty_file(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.len() == 0)
+}
+
+/// Describes the type of a file that is neither a regular file, a directory
+/// nor a symlink (FIFO, device, socket).
+/// Returns None for supported or missing files
+pub fn special_file_type(path: &Path) -> Option<&'static str> {
+    let file_type = std::fs::metadata(path).ok()?.file_type();
+    if file_type.is_file() || file_type.is_dir() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            return Some("FIFO");
+        }
+        if file_type.is_char_device() || file_type.is_block_device() {
+            return Some("device");
+        }
+        if file_type.is_socket() {
+            return Some("socket");
+        }
+    }
+    Some("special file")
+}
+
+/// Checks if a path is on a network mount (NFS, SMB/CIFS, FUSE), where
+/// native file events are unreliable
+#[cfg(target_os = "linux")]
+pub fn is_network_mount(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    const NFS_SUPER_MAGIC: u32 = 0x6969;
+    const SMB_SUPER_MAGIC: u32 = 0x517b;
+    const SMB2_SUPER_MAGIC: u32 = 0xfe53_4d42;
+    const CIFS_SUPER_MAGIC: u32 = 0xff53_4d42;
+    const FUSE_SUPER_MAGIC: u32 = 0x6573_5546;
+
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return false;
+    }
+    matches!(
+        stats.f_type as u32,
+        NFS_SUPER_MAGIC | SMB_SUPER_MAGIC | SMB2_SUPER_MAGIC | CIFS_SUPER_MAGIC | FUSE_SUPER_MAGIC
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_network_mount(_path: &Path) -> bool {
+    false
+}
+
+/// Why a file update is ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IgnoreReason {
+    Extension,
+    Name,
+    /// Deeper than --max-depth below the watch
+    TooDeep,
+    Deleted,
+    RegexMismatch,
+    IgnoredRegex,
+    IgnoreRaw,
+    GitIgnored,
+    Hidden,
+    /// Not tracked by git, with --tracked-only
+    NotTracked,
+}
+
+impl std::fmt::Display for IgnoreReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Self::Extension => "extension not in allow list",
+            Self::Name => "name not in allow list",
+            Self::TooDeep => "deeper than --max-depth",
+            Self::Deleted => "file deleted",
+            Self::RegexMismatch => "does not match required regex",
+            Self::IgnoredRegex => "matches ignored regex",
+            Self::IgnoreRaw => "matches --ignore-raw pattern",
+            Self::GitIgnored => "matched gitignore rule",
+            Self::Hidden => "hidden file",
+            Self::NotTracked => "not tracked by git",
+        };
+        f.write_str(reason)
+    }
+}
+
 /// Checks if a file update should be ignored
 pub fn should_be_ignored(filename: &PathBuf, args: &Args, watch: &PathBuf) -> bool {
+    match ignore_reason(filename, args, watch) {
+        Some(reason) => {
+            log::debug!("Ignoring {:?}: {}", filename, reason);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns why a file update should be ignored, None if it is accepted
+pub fn ignore_reason(filename: &PathBuf, args: &Args, watch: &PathBuf) -> Option<IgnoreReason> {
+    if args.force.iter().any(|pattern| matches_pattern(pattern, filename, watch)) {
+        log::debug!("Accepting {:?}: forced by --force", filename);
+        return None;
+    }
     if !extension_matches(filename, args.extensions.as_slice()) {
-        log::debug!("Ignoring {:?}: extension not in allow list", filename);
-        return true;
+        return Some(IgnoreReason::Extension);
+    }
+    if !name_matches(filename, &args.names) {
+        return Some(IgnoreReason::Name);
+    }
+    if args.max_depth.is_some_and(|max| depth_in_watch(filename, watch) > max) {
+        return Some(IgnoreReason::TooDeep);
     }
     if !args.deleted && !filename.exists() {
-        log::debug!("Ignoring {:?}: file deleted", filename);
-        return true;
+        return Some(IgnoreReason::Deleted);
     }
     if !has_all_regex_match(&args.regexps, filename, watch) {
-        log::debug!("Ignoring {:?}: does not match required regex", filename);
-        return true;
+        return Some(IgnoreReason::RegexMismatch);
     }
     if has_any_regex_match(&args.ignored_regexps, filename, watch) {
-        log::debug!("Ignoring {:?}: matches ignored regex", filename);
-        return true;
+        return Some(IgnoreReason::IgnoredRegex);
     }
-    if !args.no_gitignore && is_git_ignored(filename, watch) {
-        log::debug!("Ignoring {:?}: matched gitignore rule", filename);
-        return true;
+    if args
+        .ignore_raw
+        .iter()
+        .any(|pattern| matches_raw_pattern(pattern, filename, watch))
+    {
+        return Some(IgnoreReason::IgnoreRaw);
+    }
+    if is_git_ignored(filename, watch, !args.no_gitignore) {
+        return Some(IgnoreReason::GitIgnored);
     }
     if !args.hidden && is_hidden(filename, watch) {
-        log::debug!("Ignoring {:?}: hidden file", filename);
-        return true;
+        return Some(IgnoreReason::Hidden);
     }
 
-    false
+    None
 }
 
 /// Checks if the filename extensions is part of our allow-list
@@ -70,6 +228,24 @@ pub fn extension_matches(filename: &Path, allowed_extensions: &[String]) -> bool
     allowed_extensions.contains(&ext.to_lowercase())
 }
 
+/// Checks if the file name is exactly one of the allowed names
+/// Returns true if the allow-list is empty
+pub fn name_matches(filename: &Path, allowed_names: &[String]) -> bool {
+    if allowed_names.is_empty() {
+        return true;
+    }
+    filename
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| allowed_names.iter().any(|n| n == name))
+}
+
+/// Number of levels of a file below the watch, 1 for a file directly in it.
+/// A watched file is at level 0
+pub fn depth_in_watch(filename: &Path, watch: &Path) -> usize {
+    filename.strip_prefix(watch).map_or(0, |p| p.components().count())
+}
+
 /// Checks if the file or any parent directory is hidden
 /// up to the watch directory level.
 pub fn is_hidden(filename: &Path, watch: &PathBuf) -> bool {
@@ -232,6 +408,119 @@ mod tests {
         assert!(extension_matches(&filename, &[String::from("jpeg")]));
     }
 
+    #[test]
+    fn test_name_matches() {
+        let names = [String::from("Cargo.toml")];
+        assert!(name_matches(Path::new("/project/crates/a/Cargo.toml"), &names));
+        assert!(!name_matches(Path::new("/project/Cargo.lock"), &names));
+        assert!(!name_matches(Path::new("/project/cargo.toml"), &names));
+        assert!(!name_matches(Path::new("/project/Cargo.toml/main.rs"), &names));
+        assert!(name_matches(Path::new("/project/Cargo.lock"), &[]));
+    }
+
+    #[test]
+    fn test_only_named_files_trigger() {
+        use clap::Parser;
+
+        let dir = tempfile::tempdir().unwrap();
+        let watch = dir.path().to_path_buf();
+        let manifest = watch.join("Cargo.toml");
+        let source = watch.join("main.rs");
+        std::fs::write(&manifest, "[package]").unwrap();
+        std::fs::write(&source, "fn main() {}").unwrap();
+
+        let mut args =
+            Args::try_parse_from(["rex", "--name", "Cargo.toml", "cargo build"]).unwrap();
+        args.validate().unwrap();
+        assert!(!should_be_ignored(&manifest, &args, &watch));
+        assert!(should_be_ignored(&source, &args, &watch));
+    }
+
+    #[test]
+    fn test_forced_file_triggers() {
+        use clap::Parser;
+
+        let dir = tempfile::tempdir().unwrap();
+        let watch = dir.path().to_path_buf();
+        std::fs::write(watch.join(".gitignore"), "dist/\n").unwrap();
+        std::fs::create_dir(watch.join("dist")).unwrap();
+        let app = watch.join("dist/app.js");
+        let vendor = watch.join("dist/vendor.js");
+        std::fs::write(&app, "").unwrap();
+        std::fs::write(&vendor, "").unwrap();
+
+        let mut args = Args::try_parse_from(["rex", "make"]).unwrap();
+        args.validate().unwrap();
+        assert!(should_be_ignored(&app, &args, &watch));
+
+        let mut args = Args::try_parse_from(["rex", "--force", "dist/app.js", "make"]).unwrap();
+        args.validate().unwrap();
+        assert!(!should_be_ignored(&app, &args, &watch));
+        assert!(should_be_ignored(&vendor, &args, &watch));
+    }
+
+    #[test]
+    fn test_ignored_regex_applied() {
+        use clap::Parser;
+
+        let dir = tempfile::tempdir().unwrap();
+        let watch = dir.path().to_path_buf();
+        std::fs::create_dir(watch.join("target")).unwrap();
+        let artifact = watch.join("target/app.o");
+        let source = watch.join("app.c");
+        std::fs::write(&artifact, "").unwrap();
+        std::fs::write(&source, "").unwrap();
+
+        let mut args = Args::try_parse_from(["rex", "-R", "target/", "make"]).unwrap();
+        args.validate().unwrap();
+        assert!(should_be_ignored(&artifact, &args, &watch));
+        assert!(!should_be_ignored(&source, &args, &watch));
+
+        // -r is evaluated against the same relative path
+        let mut args = Args::try_parse_from(["rex", "-r", "^target/", "make"]).unwrap();
+        args.validate().unwrap();
+        assert!(!should_be_ignored(&artifact, &args, &watch));
+        assert!(should_be_ignored(&source, &args, &watch));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        use clap::Parser;
+
+        let dir = tempfile::tempdir().unwrap();
+        let watch = dir.path().to_path_buf();
+        std::fs::create_dir_all(watch.join("a/b")).unwrap();
+        let (shallow, deep) = (watch.join("a/main.rs"), watch.join("a/b/main.rs"));
+        std::fs::write(&shallow, "").unwrap();
+        std::fs::write(&deep, "").unwrap();
+        assert_eq!(depth_in_watch(&deep, &watch), 3);
+        assert_eq!(depth_in_watch(&deep, &deep), 0);
+
+        let mut args = Args::try_parse_from(["rex", "--max-depth", "2", "make"]).unwrap();
+        args.validate().unwrap();
+        assert!(!should_be_ignored(&shallow, &args, &watch));
+        assert_eq!(ignore_reason(&deep, &args, &watch), Some(IgnoreReason::TooDeep));
+        // A watched file is never too deep
+        assert_ne!(ignore_reason(&deep, &args, &deep), Some(IgnoreReason::TooDeep));
+    }
+
+    #[test]
+    fn test_scan_watch() {
+        use clap::Parser;
+
+        let dir = tempfile::tempdir().unwrap();
+        let watch = dir.path().canonicalize().unwrap();
+        std::fs::write(watch.join(".gitignore"), "target/\n").unwrap();
+        for sub in ["src", "target", ".git"] {
+            std::fs::create_dir(watch.join(sub)).unwrap();
+            std::fs::write(watch.join(sub).join("a.rs"), "").unwrap();
+        }
+
+        let mut args = Args::try_parse_from(["rex", "-e", "rs", "make"]).unwrap();
+        args.validate().unwrap();
+        assert_eq!(scan_watch(&watch, &args), vec![watch.join("src/a.rs")]);
+    }
+
     #[test]
     fn test_is_hidden() {
         let filename = PathBuf::from_str("/a/path/.with/hidden_dir/file.jPeG").expect("test error");
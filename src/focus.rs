@@ -0,0 +1,72 @@
+use crate::command::QueueMessage;
+use notify::EventKind;
+use std::path::PathBuf;
+
+/// Focus mode: file updates are dropped instead of triggering the command,
+/// while remembering them so that they can run once when focus mode ends.
+#[derive(Debug, Default)]
+pub struct FocusMode {
+    /// Updates received during focus mode: file, top level watch and kind
+    updates: Vec<(PathBuf, PathBuf, EventKind)>,
+}
+
+impl FocusMode {
+    /// Drops a file update, remembering it for the end of focus mode
+    pub fn drop_update(&mut self, file: PathBuf, watch: PathBuf, kind: EventKind) {
+        log::debug!("Dropping {:?}: focus mode", file);
+        self.updates.push((file, watch, kind));
+    }
+
+    /// Ends focus mode. Returns the messages queueing the updates received
+    /// meanwhile if we run the command for them.
+    pub fn end(self, run: bool) -> Vec<QueueMessage> {
+        if !run {
+            return Vec::new();
+        }
+        self.updates
+            .into_iter()
+            .map(|(file, watch, kind)| QueueMessage::AddFile(file, watch, kind))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, ModifyKind};
+
+    fn focus_with_updates() -> FocusMode {
+        let mut focus = FocusMode::default();
+        let watch = PathBuf::from("/project");
+        focus.drop_update(
+            "/project/a.rs".into(),
+            watch.clone(),
+            EventKind::Create(CreateKind::File),
+        );
+        focus.drop_update(
+            "/project/a.rs".into(),
+            watch.clone(),
+            EventKind::Modify(ModifyKind::Any),
+        );
+        focus.drop_update("/project/b.rs".into(), watch, EventKind::Modify(ModifyKind::Any));
+        focus
+    }
+
+    #[test]
+    fn test_updates_dropped_in_focus_mode() {
+        assert!(focus_with_updates().end(false).is_empty());
+    }
+
+    #[test]
+    fn test_focus_mode_runs_accumulated_updates() {
+        let messages = focus_with_updates().end(true);
+        let files: Vec<PathBuf> = messages
+            .into_iter()
+            .filter_map(|m| match m {
+                QueueMessage::AddFile(file, ..) => Some(file),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(files, ["/project/a.rs", "/project/a.rs", "/project/b.rs"].map(PathBuf::from));
+    }
+}
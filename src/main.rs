@@ -1,10 +1,14 @@
 use anyhow::Result;
 use colored::Colorize;
-use crossbeam_channel::{Receiver, Select, Sender, tick, unbounded};
+use crossbeam_channel::{Receiver, RecvError, RecvTimeoutError, Select, Sender, tick, unbounded};
+use crossterm::event::{KeyCode, KeyModifiers};
 use notify::*;
-use std::path::{PathBuf, absolute};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf, absolute};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
-use term_events::TermEvents;
+use term_events::{KeyAction, KeyChord, Keymap, TermEvents};
 
 pub mod event;
 use event::Event;
@@ -16,17 +20,33 @@ pub mod errors;
 use errors::{ProgramError, RuntimeError, runtime_error};
 
 pub mod files;
-use files::utils::should_be_ignored;
+pub mod focus;
+use files::git;
+use files::tracked::TrackedFiles;
+use files::utils::{
+    IgnoreReason, canonical_path, ignore_reason, is_network_mount, scan_watch, special_file_type,
+};
+use focus::FocusMode;
 
 pub mod command;
-use command::Queue;
 use command::QueueMessage;
+use command::Queues;
+use command::execution_report::ExecMessage;
+use command::queue_status::QueueStatus;
 
 pub mod logging;
+#[cfg(unix)]
+pub mod signals;
 pub mod term_events;
 pub mod tui;
 use tui::Output;
 use tui::RawModeGuard;
+use tui::theme::Theme;
+
+/// Poll intervals below this one are allowed, but costly
+const LOW_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Interval between the checks of the threads feeding the event loop
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
 
 fn main() {
     let _raw_mode = RawModeGuard::new().expect("Could not enable raw mode");
@@ -34,7 +54,8 @@ fn main() {
     drop(_raw_mode);
 
     match result {
-        Ok(_) => {}
+        Ok(0) => {}
+        Ok(code) => std::process::exit(code),
         Err(e) => {
             eprintln!("{}: {} {:?}", tui::PROGRAM_NAME.bold(), "error".red(), e);
             std::process::exit(1);
@@ -42,36 +63,101 @@ fn main() {
     }
 }
 
-fn run() -> Result<()> {
-    let mut args = Args::try_parse()?;
+/// Runs rex, returns its exit code: the one of the last finished command
+fn run() -> Result<i32> {
+    let parsed = Args::try_parse()?;
+    let mut args = parsed.clone();
     args.validate()?;
+    setup_colors(&mut args);
     let args = args;
 
     logging::setup(args.log_file.as_deref());
     log::info!("Starting {} v{}", tui::PROGRAM_NAME, env!("CARGO_PKG_VERSION"));
     log::debug!("Parsed arguments: {:?}", args);
 
+    if let Some(path) = &args.explain_ignore {
+        let path = path.canonicalize().or_else(|_| absolute(path))?;
+        print!(
+            "{}",
+            git::explain_ignore(&path, &watch_containing(&path, &args), !args.no_gitignore)
+        );
+        return Ok(0);
+    }
+
+    let (event_tx, event_rx) = unbounded::<Event>();
+    // Let scripts control rex with signals. Signals and keys are handled
+    // from the start, to quit while waiting for the watched files/dirs
+    #[cfg(unix)]
+    signals::monitor_signals(event_tx.clone())?;
+    // Start listening on keys
+    let keys_suspended = Arc::new(AtomicBool::new(false));
+    let suspended = keys_suspended.clone();
+    // Lines piped to rex run the command. On a terminal, Enter does it
+    let stdin_is_terminal = std::io::stdin().is_terminal();
+    if args.trigger_stdin && !stdin_is_terminal {
+        let tx = event_tx.clone();
+        std::thread::spawn(move || term_events::monitor_input_lines(std::io::stdin().lock(), tx));
+    }
+    let mut keymap = Keymap::default();
+    if args.trigger_stdin && stdin_is_terminal {
+        keymap.bind(KeyChord::new(KeyCode::Enter, KeyModifiers::NONE), KeyAction::RunNow);
+    }
+    for (chord, action) in &args.bind {
+        keymap.bind(*chord, *action);
+    }
+    let tx = event_tx.clone();
+    std::thread::spawn(move || term_events::monitor_key_inputs(tx, suspended, keymap));
+
     let mut file_watchers: Vec<Box<dyn Watcher>> = Vec::new();
     let mut rx_with_path: Vec<(Receiver<Event>, PathBuf)> = Vec::new();
+    let mut pending_events = Vec::new();
 
-    for f in &args.files {
+    for w in &args.watches {
         let (tx, rx) = unbounded::<Event>();
-        let mut watcher = get_watcher(tx, &args);
-        let p = register_watch_for_file(&mut watcher, f)?;
+        if let Some(secs) = args.wait_for_targets {
+            let timeout = Duration::from_secs(secs);
+            if wait_for_target(Path::new(&w.path), timeout, &event_rx, &mut pending_events)
+                .is_none()
+            {
+                log::info!("Quit while waiting for {:?}", w.path);
+                return Ok(0);
+            }
+        }
+        let remote = w.remote || canonical_path(&w.path).is_ok_and(|p| is_network_mount(&p));
+        let mut watcher = get_watcher(tx, &args, remote)?;
+        let p = register_watch_for_file(&mut watcher, &w.path)?;
         file_watchers.push(watcher);
         rx_with_path.push((rx, p));
     }
 
-    let (event_tx, event_rx) = unbounded::<Event>();
+    let watches: Vec<PathBuf> = rx_with_path.iter().map(|(_, p)| p.clone()).collect();
+    let mut tracked = if args.tracked_only { Some(TrackedFiles::new(&watches)?) } else { None };
 
-    // Start the command queue
+    // Start the command queues, one per task
     let tx_clone = event_tx.clone();
-    let command_queue_tx = Queue::start(&args, tx_clone)?;
-    // Start listening on keys
-    std::thread::spawn(move || term_events::monitor_key_inputs(event_tx));
+    let queue = Queues::start(&parsed, &args, tx_clone)?;
+    // Record the current state of the watched files, without running anything
+    if args.no_run_on_startup_scan {
+        for (_, watch) in &rx_with_path {
+            for p in scan_watch(watch, &args) {
+                queue.send(QueueMessage::Register(p, watch.clone()))?;
+            }
+        }
+    }
+    if args.run_at_start {
+        queue.send(QueueMessage::RunNow)?;
+    }
+    // Handle the events received while waiting for the watched files/dirs
+    for event in pending_events {
+        event_tx.send(event)?;
+    }
+    drop(event_tx);
 
     // Printout / output
     let mut output = Output::new(&args);
+    if args.footer {
+        output.show_footer(queue.status.clone());
+    }
 
     let mut select = Select::new();
     let mut rxs = Vec::new();
@@ -88,8 +174,22 @@ fn run() -> Result<()> {
     select.recv(&flush_tick);
     let flush_tick_index = rxs.len(); // index of the tick receiver in the select
 
+    // Ticker checking that the threads feeding the event loop are still alive
+    let heartbeat_tick = tick(HEARTBEAT_INTERVAL);
+    select.recv(&heartbeat_tick);
+    let heartbeat_tick_index = flush_tick_index + 1;
+
     let rxs = rxs;
     let mut paused = false;
+    let mut focus: Option<FocusMode> = None;
+
+    // Scope of the updates to drop while a command is running
+    let ignore_during_run: Option<Option<PathBuf>> = args.ignore_during_run.as_ref().map(|scope| {
+        scope.as_deref().map(|p| canonical_path(p).or(absolute(p)).unwrap_or(p.into()))
+    });
+
+    // Did a command finish, with --once
+    let mut ran_once = false;
 
     // Event loop
     loop {
@@ -100,78 +200,308 @@ fn run() -> Result<()> {
         if index == flush_tick_index {
             let _ = operation.recv(&flush_tick);
             output.tick_spinners();
+            output.refresh_footer();
             output.flush_output();
+            // With --once, quit when everything queued so far is done
+            if ran_once && queue.status.is_idle() {
+                log::info!("The command ran once, shutting down");
+                return Ok(shut_down(&mut output, &queue, &event_rx));
+            }
+            continue;
+        }
+        if index == heartbeat_tick_index {
+            let _ = operation.recv(&heartbeat_tick);
+            check_threads(&queue)?;
             continue;
         }
 
         let rx = rxs[index];
 
-        match operation.recv(rx) {
-            Ok(Event::FileWatch(file_watch)) => {
-                // if the program is paused, ignore file updates
-                if paused {
-                    continue;
-                }
-                match file_watch {
-                    Ok(event) => match event.kind {
-                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
-                            let (_, watch) = &rx_with_path[index];
-                            for p in &event.paths {
-                                if should_be_ignored(p, &args, watch) {
-                                    continue;
-                                }
+        // Handle the other events already waiting on the same channel, in order
+        let batch = recv_batch(rx, operation.recv(rx), args.event_batch_size);
+        for received in batch {
+            if args.dry_run_ignore
+                && matches!(
+                    received,
+                    Ok(Event::RunNow | Event::RerunWithEnv(..) | Event::Term(TermEvents::Rerun))
+                )
+            {
+                log::info!("Dry run: the command is not run");
+                continue;
+            }
+            match received {
+                Ok(Event::FileWatch(file_watch)) => {
+                    // if the program is paused, ignore file updates
+                    if paused {
+                        continue;
+                    }
+                    match file_watch {
+                        Ok(event) => match event.kind {
+                            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                                let (_, watch) = &rx_with_path[index];
+                                for p in &event.paths {
+                                    if args.show_source {
+                                        output.print_event_source(p, event.kind, watch, index);
+                                    }
+                                    if let Some(scope) = &ignore_during_run
+                                        && is_dropped_during_run(p, scope.as_deref(), &queue.status)
+                                    {
+                                        log::debug!("Dropping {:?}: a command is running", p);
+                                        continue;
+                                    }
+                                    if !accept_update(
+                                        p,
+                                        watch,
+                                        &args,
+                                        tracked.as_mut(),
+                                        &mut output,
+                                    ) {
+                                        continue;
+                                    }
+                                    if let Some(focus) = &mut focus {
+                                        focus.drop_update(p.clone(), watch.clone(), event.kind);
+                                        continue;
+                                    }
 
-                                log::debug!("File change accepted: {:?} ({:?})", p, event.kind);
-                                command_queue_tx
-                                    .send(QueueMessage::AddFile(p.clone(), watch.clone()))?;
+                                    log::debug!("File change accepted: {:?} ({:?})", p, event.kind);
+                                    queue.send(QueueMessage::AddFile(
+                                        p.clone(),
+                                        watch.clone(),
+                                        event.kind,
+                                    ))?;
+                                }
                             }
+                            _ => {}
+                        },
+                        Err(error) => {
+                            log::error!("File watch error: {}", error);
+                            return Err(runtime_error!(FileWatchError, error.to_string()).into());
                         }
-                        _ => {}
-                    },
-                    Err(error) => {
-                        log::error!("File watch error: {}", error);
-                        return Err(runtime_error!(FileWatchError, error.to_string()).into());
                     }
                 }
+                Ok(Event::Exec(update)) => {
+                    let (started, finished) = (
+                        matches!(update, ExecMessage::Start(_)),
+                        matches!(update, ExecMessage::Finish(_)),
+                    );
+                    output.update(update);
+                    ran_once |= args.once && finished;
+                    // Interactive commands get the terminal while they run
+                    if args.interactive && started {
+                        output.step_back();
+                        keys_suspended.store(true, Ordering::SeqCst);
+                        RawModeGuard::suspend();
+                    } else if args.interactive && finished {
+                        RawModeGuard::resume();
+                        keys_suspended.store(false, Ordering::SeqCst);
+                        output.redraw();
+                    }
+                }
+                Ok(Event::Term(TermEvents::Quit)) => {
+                    log::info!("Quit signal received, shutting down");
+                    return Ok(shut_down(&mut output, &queue, &event_rx));
+                }
+                Ok(Event::Term(TermEvents::Resize(_, rows))) => {
+                    output.resize(rows);
+                }
+                Ok(Event::Term(TermEvents::ClearScreen)) => {
+                    output.clear_output();
+                }
+                Ok(Event::Term(TermEvents::ToggleFocus { run })) => {
+                    match focus.take() {
+                        Some(f) => {
+                            for message in f.end(run) {
+                                queue.send(message)?;
+                            }
+                        }
+                        None => focus = Some(FocusMode::default()),
+                    }
+                    output.set_focus(focus.is_some());
+                }
+                Ok(Event::Term(TermEvents::ListQueue)) => {
+                    match queue.pending_paths(Duration::from_secs(1)) {
+                        Ok(files) => output.print_queue(&files),
+                        Err(e) => log::warn!("Could not list the queued files: {e}"),
+                    }
+                }
+                Ok(Event::TogglePause) => {
+                    paused = !paused;
+                    output.set_pause(paused);
+                }
+                Ok(Event::Term(TermEvents::EnvPrompt(input))) => {
+                    output.set_prompt(input);
+                }
+                Ok(Event::Term(TermEvents::Rerun)) => {
+                    log::info!("Running the last command again on request");
+                    queue.send(QueueMessage::Rerun)?;
+                }
+                Ok(Event::RerunWithEnv(key, value)) => {
+                    queue.send(QueueMessage::RerunWithEnv(key, value))?;
+                }
+                Ok(Event::RunNow) => {
+                    log::info!("Running the command on request");
+                    queue.send(QueueMessage::RunNow)?;
+                }
+                Ok(Event::Reload) => {
+                    reload(tracked.as_mut());
+                    output.redraw();
+                }
+                Ok(Event::ToggleTitle) => {
+                    output.toggle_full_title();
+                }
+                Ok(Event::AbortOngoingCommands) => {
+                    log::debug!("Request to abort command received");
+                    queue.send(QueueMessage::AbortOngoingCommands)?;
+                }
+                Err(e) => return Err(closed_channel_error(index, &rx_with_path, e).into()),
             }
-            Ok(Event::Exec(update)) => output.update(update),
-            Ok(Event::Term(TermEvents::Quit)) => {
-                log::info!("Quit signal received, shutting down");
-                let _ = command_queue_tx.send(QueueMessage::Abort);
-                output.finish();
-                return Ok(());
-            }
-            Ok(Event::Term(TermEvents::Resize(..))) => {
-                output.redraw();
-            }
-            Ok(Event::Term(TermEvents::ClearScreen)) => {
-                output.clear_output();
-            }
-            Ok(Event::TogglePause) => {
-                paused = !paused;
-                output.set_pause(paused);
-            }
-            Ok(Event::AbortOngoingCommands) => {
-                log::debug!("Request to abort command received");
-                command_queue_tx.send(QueueMessage::AbortOngoingCommands)?;
-            }
-            Err(e) => {
-                return Err(runtime_error!(ChannelReceiveError, e.to_string()).into());
-            }
         }
     }
 }
 
+/// Returns the received event along with the events already pending on the
+/// same channel, up to `size` events in total
+fn recv_batch(
+    rx: &Receiver<Event>,
+    first: Result<Event, RecvError>,
+    size: usize,
+) -> Vec<Result<Event, RecvError>> {
+    let mut batch = vec![first];
+    if batch[0].is_ok() {
+        batch.extend(rx.try_iter().take(size - 1).map(Ok));
+    }
+    batch
+}
+
+/// Turns the colors on or off for the whole output, following --color and
+/// NO_COLOR. Without colors, the UI uses the mono theme
+fn setup_colors(args: &mut Args) {
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let colors = args.color.enabled(no_color_env, std::io::stdout().is_terminal());
+    colored::control::set_override(colors);
+    if !colors {
+        args.theme = Theme::mono();
+    }
+}
+
+/// Stops the commands and gives the terminal back. Returns the exit code of
+/// rex
+fn shut_down(output: &mut Output, queue: &Queues, event_rx: &Receiver<Event>) -> i32 {
+    queue.stop();
+    output.drain_reports(event_rx);
+    output.finish();
+    output.exit_code()
+}
+
+/// Checks that the threads feeding the event loop are still alive. File
+/// watchers are not checked here: the select reports their closed channels
+fn check_threads(queue: &Queues) -> Result<(), ProgramError> {
+    if !queue.is_alive() {
+        log::error!("The command queue thread is gone");
+        return Err(runtime_error!(ThreadStopped, "Command queue".into()));
+    }
+    Ok(())
+}
+
+/// Describes the failure behind a closed event channel, naming the file
+/// watcher that stopped if it is one
+fn closed_channel_error(
+    index: usize,
+    rx_with_path: &[(Receiver<Event>, PathBuf)],
+    error: RecvError,
+) -> ProgramError {
+    match rx_with_path.get(index) {
+        Some((_, path)) => {
+            log::error!("The file watcher of {:?} is gone", path);
+            runtime_error!(ThreadStopped, format!("File watcher of {}", path.display()))
+        }
+        None => runtime_error!(ChannelReceiveError, error.to_string()),
+    }
+}
+
+/// Checks if a file update goes on to the command queue. With
+/// --dry-run-ignore, whether it would is printed instead, and it never does
+fn accept_update(
+    p: &PathBuf,
+    watch: &PathBuf,
+    args: &Args,
+    tracked: Option<&mut TrackedFiles>,
+    output: &mut Output,
+) -> bool {
+    let reason = ignore_reason(p, args, watch).or_else(|| {
+        tracked
+            .is_some_and(|t| !t.is_tracked(p, watch))
+            .then_some(IgnoreReason::NotTracked)
+    });
+    if args.dry_run_ignore {
+        output.print_ignore_preview(p, reason);
+        return false;
+    }
+    match reason {
+        Some(reason) => {
+            log::debug!("Ignoring {:?}: {}", p, reason);
+            false
+        }
+        None => true,
+    }
+}
+
+/// Checks if a file update has to be dropped because a command is running.
+/// If a scope is given, only updates under it are dropped.
+fn is_dropped_during_run(file: &Path, scope: Option<&Path>, status: &QueueStatus) -> bool {
+    status.is_running() && scope.is_none_or(|s| file.starts_with(s))
+}
+
+/// Waits until a file/dir exists, or the timeout elapses.
+/// Returns true if it exists, None if the user quit meanwhile. The other
+/// events received while waiting are kept in `pending`
+fn wait_for_target(
+    path: &Path,
+    timeout: Duration,
+    events: &Receiver<Event>,
+    pending: &mut Vec<Event>,
+) -> Option<bool> {
+    let deadline = std::time::Instant::now() + timeout;
+    while !path.exists() {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            log::warn!("{:?} still does not exist after {:?}", path, timeout);
+            return Some(false);
+        }
+        log::debug!("Waiting for {:?} to exist", path);
+        let slice = remaining.min(Duration::from_millis(100));
+        match events.recv_timeout(slice) {
+            Ok(Event::Term(TermEvents::Quit)) => return None,
+            Ok(event) => pending.push(event),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(slice),
+        }
+    }
+    Some(true)
+}
+
+/// Finds the watched directory containing a path, defaults to the directory
+/// of the path
+fn watch_containing(path: &Path, args: &Args) -> PathBuf {
+    args.watches
+        .iter()
+        .filter_map(|w| canonical_path(&w.path).ok())
+        .find(|w| path.starts_with(w))
+        .unwrap_or_else(|| path.parent().unwrap_or(path).to_path_buf())
+}
+
 /// Updates the watcher to watch the file pointed by &str, if it exists
+/// Symlinks are resolved, so a symlinked file is watched in the directory of
+/// its target.
 /// Returns a Result with the PathBuf
 fn register_watch_for_file(
     watcher: &mut Box<dyn Watcher>,
     file: &str,
 ) -> Result<PathBuf, ProgramError> {
-    let p = absolute(file)
-        .map_err(|e| runtime_error!(FileError, file.to_string(), e.to_string()))?
-        .canonicalize()
-        .map_err(|e| runtime_error!(FileError, file.to_string(), e.to_string()))?;
+    let p = canonical_path(file)?;
+    if let Some(file_type) = special_file_type(&p) {
+        return Err(runtime_error!(UnsupportedFileType, file.to_string(), file_type.to_string()));
+    }
 
     let watch_mode =
         if p.is_dir() { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
@@ -191,38 +521,348 @@ fn register_watch_for_file(
     };
 
     log::info!("Watching {:?} ({:?})", watch_target.display(), watch_mode);
-    watcher
-        .watch(watch_target.as_path(), watch_mode)
-        .map_err(|e| runtime_error!(FileWatchError, e.to_string()))?;
+    watcher.watch(watch_target.as_path(), watch_mode).map_err(|e| {
+        runtime_error!(WatchRegistrationError, watch_target.display().to_string(), e.to_string())
+    })?;
 
     Ok(p)
 }
 
-/// Gets the recommended watcher using the Sender
-fn get_watcher(tx: Sender<Event>, args: &Args) -> Box<dyn Watcher> {
-    if args.force_poll || RecommendedWatcher::kind() == WatcherKind::PollWatcher {
-        log::debug!("Using PollWatcher (interval: {}ms)", args.poll_interval);
-        let config =
-            Config::default().with_poll_interval(Duration::from_millis(args.poll_interval));
+/// Reads the git configuration and the files tracked by git again. Ignore
+/// files are read again for every file update, only these are cached
+fn reload(tracked: Option<&mut TrackedFiles>) {
+    log::info!("Reloading the git configuration and the tracked files");
+    git::reload_global_excludes();
+    if let Some(tracked) = tracked {
+        tracked.reload();
+    }
+}
+
+/// Returns the poll interval if a watch target has to be polled, None if the
+/// recommended watcher can be used
+fn poll_interval(args: &Args, remote: bool) -> Option<Duration> {
+    if remote {
+        Some(Duration::from_millis(args.remote_poll_interval))
+    } else if args.force_poll || RecommendedWatcher::kind() == WatcherKind::PollWatcher {
+        Some(Duration::from_millis(args.poll_interval))
+    } else {
+        None
+    }
+}
+
+/// Gets the recommended watcher using the Sender, or a PollWatcher for targets
+/// on network mounts
+fn get_watcher(
+    tx: Sender<Event>,
+    args: &Args,
+    remote: bool,
+) -> Result<Box<dyn Watcher>, ProgramError> {
+    let handler = move |res| {
+        tx.send(Event::FileWatch(res)).expect("Could not send watch event to channel");
+    };
+    let watcher: Box<dyn Watcher> = if let Some(interval) = poll_interval(args, remote) {
+        log::debug!("Using PollWatcher (interval: {}ms, remote: {})", interval.as_millis(), remote);
+        if interval < LOW_POLL_INTERVAL {
+            log::warn!("Polling every {}ms may use a lot of CPU", interval.as_millis());
+        }
+        let config = Config::default().with_poll_interval(interval);
         Box::new(
-            PollWatcher::new(
-                move |res| {
-                    tx.send(Event::FileWatch(res)).expect("Could not send watch event to channel");
-                },
-                config,
-            )
-            .unwrap(),
+            PollWatcher::new(handler, config)
+                .map_err(|e| runtime_error!(FileWatchError, e.to_string()))?,
         )
     } else {
         log::debug!("Using RecommendedWatcher ({:?})", RecommendedWatcher::kind());
         Box::new(
-            RecommendedWatcher::new(
-                move |res| {
-                    tx.send(Event::FileWatch(res)).expect("Could not send watch event to channel");
-                },
-                Config::default(),
-            )
-            .unwrap(),
+            RecommendedWatcher::new(handler, Config::default())
+                .map_err(|e| runtime_error!(FileWatchError, e.to_string()))?,
         )
+    };
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[test]
+    fn test_remote_target_uses_poller() {
+        let mut args = Args::try_parse_from(["rex", "--remote", ".", "make"]).unwrap();
+        args.validate().unwrap();
+        assert_eq!(args.watches.len(), 1);
+        assert!(args.watches[0].remote);
+        assert_eq!(poll_interval(&args, args.watches[0].remote), Some(Duration::from_secs(2)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fifo_cannot_be_watched() {
+        let dir = tempfile::tempdir().unwrap();
+        let fifo = dir.path().join("pipe");
+        let c_path = std::ffi::CString::new(fifo.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) }, 0);
+
+        let mut args = Args::try_parse_from(["rex", "make"]).unwrap();
+        args.validate().unwrap();
+        let (tx, _rx) = unbounded();
+        let mut watcher = get_watcher(tx, &args, false).unwrap();
+
+        let error = register_watch_for_file(&mut watcher, fifo.to_str().unwrap()).unwrap_err();
+        assert!(matches!(
+            error,
+            ProgramError::RuntimeError(RuntimeError::UnsupportedFileType(_, ref t)) if t == "FIFO"
+        ));
+        assert!(error.to_string().contains("only regular files and directories"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_file_watches_target() {
+        let real_dir = tempfile::tempdir().unwrap();
+        let link_dir = tempfile::tempdir().unwrap();
+        let target = real_dir.path().join("config.toml");
+        std::fs::write(&target, "a").unwrap();
+        let link = link_dir.path().join("config.toml");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let mut args = Args::try_parse_from(["rex", "make"]).unwrap();
+        args.validate().unwrap();
+        let (tx, rx) = unbounded();
+        let mut watcher = get_watcher(tx, &args, false).unwrap();
+        let watched = register_watch_for_file(&mut watcher, link.to_str().unwrap()).unwrap();
+        let target = target.canonicalize().unwrap();
+        assert_eq!(watched, target);
+
+        std::fs::write(&target, "b").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(timeout).expect("No event for the symlink target") {
+                Event::FileWatch(Ok(event)) if event.paths.contains(&target) => break,
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_dropped_watcher_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut args = Args::try_parse_from(["rex", "make"]).unwrap();
+        args.validate().unwrap();
+        let (tx, rx) = unbounded();
+        let mut watcher = get_watcher(tx, &args, false).unwrap();
+        let watched = register_watch_for_file(&mut watcher, dir.path().to_str().unwrap()).unwrap();
+
+        drop(watcher);
+        let error = match rx.recv_timeout(Duration::from_secs(5)) {
+            Err(RecvTimeoutError::Disconnected) => RecvError,
+            other => panic!("Watcher channel still open: {other:?}"),
+        };
+        let rx_with_path = vec![(rx, watched.clone())];
+        let message = closed_channel_error(0, &rx_with_path, error).to_string();
+        assert!(message.contains(&format!("File watcher of {}", watched.display())));
+        assert!(message.contains("stopped unexpectedly"));
+        // Other channels keep the generic error
+        let message = closed_channel_error(1, &rx_with_path, RecvError).to_string();
+        assert!(!message.contains("File watcher"));
+    }
+
+    #[test]
+    fn test_event_source() {
+        let dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let mut args = Args::try_parse_from(["rex", "--show-source", "make"]).unwrap();
+        args.validate().unwrap();
+        let mut watchers = Vec::new();
+        let mut rx_with_path = Vec::new();
+        for dir in &dirs {
+            let (tx, rx) = unbounded();
+            let mut watcher = get_watcher(tx, &args, false).unwrap();
+            let p = register_watch_for_file(&mut watcher, dir.path().to_str().unwrap()).unwrap();
+            watchers.push(watcher);
+            rx_with_path.push((rx, p));
+        }
+        let mut select = Select::new();
+        for (rx, _) in &rx_with_path {
+            select.recv(rx);
+        }
+
+        let file = rx_with_path[1].1.join("second.txt");
+        std::fs::write(&file, "").unwrap();
+        let operation = select.select_timeout(Duration::from_secs(5)).expect("No file event");
+        let index = operation.index();
+        let (rx, watch) = &rx_with_path[index];
+        let Ok(Event::FileWatch(Ok(event))) = operation.recv(rx) else {
+            panic!("Unexpected event");
+        };
+        let mut output = Output::headless(&args);
+        output.print_event_source(&event.paths[0], event.kind, watch, index);
+
+        let line = output.printed_lines().back().unwrap();
+        assert_eq!(index, 1);
+        assert!(line.contains(&format!("<- {} (receiver 1)", watch.display())), "{line}");
+        assert!(line.contains("second.txt"), "{line}");
+    }
+
+    #[test]
+    fn test_recv_batch() {
+        let (tx, rx) = unbounded();
+        for _ in 0..10 {
+            tx.send(Event::RunNow).unwrap();
+        }
+        tx.send(Event::Reload).unwrap();
+
+        let mut iterations = 0;
+        let mut received = Vec::new();
+        while received.len() < 11 {
+            received.extend(recv_batch(&rx, rx.recv(), 4));
+            iterations += 1;
+        }
+        assert_eq!(iterations, 3);
+        assert!(received[..10].iter().all(|e| matches!(e, Ok(Event::RunNow))));
+        assert!(matches!(received[10], Ok(Event::Reload)));
+        assert!(rx.try_recv().is_err());
+
+        drop(tx);
+        assert_eq!(recv_batch(&rx, rx.recv(), 4).len(), 1);
+    }
+
+    #[test]
+    fn test_reload_lists_tracked_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git").arg("-C").arg(&root).args(args).status();
+            assert!(status.unwrap().success());
+        };
+        git(&["init", "-q"]);
+        let file = root.join("new.rs");
+        std::fs::write(&file, "").unwrap();
+        let mut tracked = TrackedFiles::new(std::slice::from_ref(&root)).unwrap();
+
+        git(&["add", "new.rs"]);
+        assert!(!tracked.is_listed(&file, &root));
+        reload(Some(&mut tracked));
+        assert!(tracked.is_listed(&file, &root));
+    }
+
+    #[test]
+    fn test_wait_for_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("build");
+        let (events_tx, events) = unbounded();
+        let mut pending = Vec::new();
+        let timeout = Duration::from_millis(200);
+        assert_eq!(wait_for_target(&target, timeout, &events, &mut pending), Some(false));
+
+        let created = target.clone();
+        let creator = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            std::fs::create_dir(created).unwrap();
+        });
+        let timeout = Duration::from_secs(5);
+        assert_eq!(wait_for_target(&target, timeout, &events, &mut pending), Some(true));
+        creator.join().unwrap();
+        drop(events_tx);
+
+        let mut args = Args::try_parse_from(["rex", "make"]).unwrap();
+        args.validate().unwrap();
+        let (tx, _rx) = unbounded();
+        let mut watcher = get_watcher(tx, &args, false).unwrap();
+        assert!(register_watch_for_file(&mut watcher, target.to_str().unwrap()).is_ok());
+    }
+
+    /// Watcher refusing every path
+    struct FailingWatcher;
+
+    impl Watcher for FailingWatcher {
+        fn new<F: EventHandler>(_: F, _: Config) -> notify::Result<Self> {
+            Ok(Self)
+        }
+        fn watch(&mut self, _: &Path, _: RecursiveMode) -> notify::Result<()> {
+            Err(notify::Error::generic("no more watches available"))
+        }
+        fn unwatch(&mut self, _: &Path) -> notify::Result<()> {
+            Ok(())
+        }
+        fn kind() -> WatcherKind {
+            WatcherKind::NullWatcher
+        }
+    }
+
+    #[test]
+    fn test_watch_registration_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut watcher: Box<dyn Watcher> = Box::new(FailingWatcher);
+
+        let error =
+            register_watch_for_file(&mut watcher, dir.path().to_str().unwrap()).unwrap_err();
+        match error {
+            ProgramError::RuntimeError(RuntimeError::WatchRegistrationError(path, message)) => {
+                assert_eq!(PathBuf::from(path), dir.path().canonicalize().unwrap());
+                assert_eq!(message, "no more watches available");
+            }
+            other => panic!("Unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_updates_dropped_during_run() {
+        let status = Arc::new(QueueStatus::default());
+        let file = Path::new("/project/target/out.o");
+        assert!(!is_dropped_during_run(file, None, &status));
+
+        let active = status.track_worker();
+        assert!(is_dropped_during_run(file, None, &status));
+        assert!(is_dropped_during_run(file, Some(Path::new("/project/target")), &status));
+        assert!(!is_dropped_during_run(file, Some(Path::new("/project/src")), &status));
+
+        drop(active);
+        assert!(!is_dropped_during_run(file, None, &status));
+    }
+
+    #[test]
+    fn test_quit_while_waiting_for_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let (tx, rx) = unbounded();
+        let mut pending = Vec::new();
+
+        // The other events are kept, quitting stops the wait right away
+        let quitter = std::thread::spawn(move || {
+            tx.send(Event::RunNow).unwrap();
+            std::thread::sleep(Duration::from_millis(150));
+            tx.send(Event::Term(TermEvents::Quit)).unwrap();
+        });
+        let start = Instant::now();
+        assert_eq!(wait_for_target(&missing, Duration::from_secs(60), &rx, &mut pending), None);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(matches!(pending[..], [Event::RunNow]));
+        quitter.join().unwrap();
+    }
+
+    #[test]
+    fn test_dry_run_ignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let watch = dir.path().canonicalize().unwrap();
+        let (source, notes) = (watch.join("main.rs"), watch.join("notes.txt"));
+        std::fs::write(&source, "").unwrap();
+        std::fs::write(&notes, "").unwrap();
+        let mut args =
+            Args::try_parse_from(["rex", "-e", "rs", "--dry-run-ignore", "echo {file}"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+
+        // Reported, but never sent to the queue
+        assert!(!accept_update(&source, &watch, &args, None, &mut output));
+        assert!(!accept_update(&notes, &watch, &args, None, &mut output));
+        let lines: Vec<&String> = output.printed_lines().iter().rev().take(2).collect();
+        assert!(lines[1].contains("would run") && lines[1].contains("main.rs"), "{lines:?}");
+        assert!(lines[0].contains("notes.txt (extension not in allow list)"), "{lines:?}");
+
+        args.dry_run_ignore = false;
+        assert!(accept_update(&source, &watch, &args, None, &mut output));
+        assert!(!accept_update(&notes, &watch, &args, None, &mut output));
     }
 }
@@ -0,0 +1,59 @@
+use crate::event::Event;
+use crossbeam_channel::Sender;
+use signal_hook::consts::{SIGHUP, SIGUSR1};
+use signal_hook::iterator::Signals;
+
+/// Forwards the control signals to the main loop from a dedicated thread:
+/// - SIGUSR1 runs the command now
+/// - SIGHUP reloads the git configuration and the tracked files, and redraws
+///   the screen
+pub fn monitor_signals(tx: Sender<Event>) -> std::io::Result<()> {
+    let mut signals = Signals::new([SIGHUP, SIGUSR1])?;
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            log::debug!("Received signal {signal}");
+            let event = match signal {
+                SIGUSR1 => Event::RunNow,
+                SIGHUP => Event::Reload,
+                _ => continue,
+            };
+            if tx.send(event).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use std::time::Duration;
+
+    /// Raises a signal and waits for the event it gives. The tests run in
+    /// the same process, so the signals of other tests are skipped
+    fn raise_and_wait(signal: libc::c_int, expected: fn(&Event) -> bool) {
+        let (tx, rx) = unbounded();
+        monitor_signals(tx).unwrap();
+
+        unsafe { libc::raise(signal) };
+        loop {
+            match rx.recv_timeout(Duration::from_secs(5)) {
+                Ok(event) if expected(&event) => return,
+                Ok(Event::RunNow | Event::Reload) => {}
+                other => panic!("Expected an event for signal {signal}, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_sigusr1_runs_command() {
+        raise_and_wait(SIGUSR1, |e| matches!(e, Event::RunNow));
+    }
+
+    #[test]
+    fn test_sighup_reloads() {
+        raise_and_wait(SIGHUP, |e| matches!(e, Event::Reload));
+    }
+}
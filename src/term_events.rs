@@ -1,6 +1,11 @@
 use crate::event::Event;
 use crossbeam_channel::Sender;
 use crossterm::event::{Event as CrosstermEvent, KeyCode, KeyModifiers};
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 #[derive(Debug)]
@@ -11,34 +16,206 @@ pub enum TermEvents {
     Resize(u16, u16),
     /// User wishes to clear the screen
     ClearScreen,
+    /// User wishes to enter or leave focus mode. When leaving, `run` tells if
+    /// the command runs for the updates dropped meanwhile
+    ToggleFocus { run: bool },
+    /// User wishes to see the files waiting for a run
+    ListQueue,
+    /// Text typed in the environment variable prompt, None once it is closed
+    EnvPrompt(Option<String>),
+    /// User wishes to run the last command again
+    Rerun,
 }
 
-pub fn monitor_key_inputs(tx: Sender<Event>) {
+/// Outcome of a key typed in the environment variable prompt
+#[derive(Debug, PartialEq)]
+enum PromptStep {
+    /// The input changed
+    Edited,
+    /// The key does nothing
+    Ignored,
+    /// The prompt is closed without running anything
+    Cancel,
+    /// The prompt is closed with a KEY=VALUE input
+    Submit(String, String),
+}
+
+/// Applies a key typed in the environment variable prompt to its input
+fn prompt_key(input: &mut String, code: KeyCode) -> PromptStep {
+    match code {
+        KeyCode::Char(c) => {
+            input.push(c);
+            PromptStep::Edited
+        }
+        KeyCode::Backspace => {
+            input.pop();
+            PromptStep::Edited
+        }
+        KeyCode::Esc => PromptStep::Cancel,
+        KeyCode::Enter => match input.split_once('=') {
+            Some((key, value)) if !key.is_empty() => {
+                PromptStep::Submit(key.to_string(), value.to_string())
+            }
+            _ => PromptStep::Ignored,
+        },
+        _ => PromptStep::Ignored,
+    }
+}
+
+/// Actions that keys can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Quit,
+    ClearScreen,
+    TogglePause,
+    AbortOngoing,
+    /// Enter focus mode, or leave it and run the command
+    Focus,
+    /// Leave focus mode without running the command
+    FocusSkip,
+    ListQueue,
+    /// Run the command now, for the pending files or the watched files/dirs
+    RunNow,
+    /// Run the last command again, for the same files
+    Rerun,
+    /// Open the prompt to run the last command with an environment variable
+    RerunWithEnv,
+    ToggleTitle,
+}
+
+impl KeyAction {
+    /// Names of the actions, as given to --bind
+    const NAMES: &[(&str, KeyAction)] = &[
+        ("quit", Self::Quit),
+        ("clear", Self::ClearScreen),
+        ("pause", Self::TogglePause),
+        ("abort", Self::AbortOngoing),
+        ("focus", Self::Focus),
+        ("focus-skip", Self::FocusSkip),
+        ("queue", Self::ListQueue),
+        ("run", Self::RunNow),
+        ("rerun", Self::Rerun),
+        ("rerun-env", Self::RerunWithEnv),
+        ("title", Self::ToggleTitle),
+    ];
+}
+
+/// A key along with the Ctrl and Alt modifiers held with it, e.g. Ctrl-r
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Shift is already part of the character typed, e.g. 'F'
+        let modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        Self { code, modifiers }
+    }
+}
+
+/// Actions of the keys, the defaults can be overridden with --bind
+pub struct Keymap {
+    bindings: HashMap<KeyChord, KeyAction>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let plain = |c| KeyChord::new(KeyCode::Char(c), KeyModifiers::NONE);
+        let ctrl = |c| KeyChord::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let bindings = HashMap::from([
+            (plain('q'), KeyAction::Quit),
+            (plain('Q'), KeyAction::Quit),
+            (KeyChord::new(KeyCode::Esc, KeyModifiers::NONE), KeyAction::Quit),
+            (ctrl('l'), KeyAction::ClearScreen),
+            (plain('k'), KeyAction::TogglePause),
+            (plain('a'), KeyAction::AbortOngoing),
+            (plain('f'), KeyAction::Focus),
+            (plain('F'), KeyAction::FocusSkip),
+            (plain('l'), KeyAction::ListQueue),
+            (plain('r'), KeyAction::Rerun),
+            (ctrl('r'), KeyAction::Rerun),
+            (plain('e'), KeyAction::RerunWithEnv),
+            (plain('t'), KeyAction::ToggleTitle),
+        ]);
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// Binds a key to an action, replacing its previous action
+    pub fn bind(&mut self, chord: KeyChord, action: KeyAction) {
+        self.bindings.insert(chord, action);
+    }
+
+    /// Returns the action bound to a key, if any
+    pub fn action(&self, chord: KeyChord) -> Option<KeyAction> {
+        self.bindings.get(&chord).copied()
+    }
+}
+
+/// Parses a key binding given as `KEY=ACTION`, e.g. `ctrl-b=rerun` or
+/// `esc=pause`
+pub fn parse_key_binding(value: &str) -> Result<(KeyChord, KeyAction), String> {
+    let (key, action) = value.split_once('=').ok_or("expected KEY=ACTION, e.g. ctrl-b=rerun")?;
+    let action = KeyAction::NAMES
+        .iter()
+        .find(|(name, _)| *name == action)
+        .map(|(_, action)| *action)
+        .ok_or_else(|| {
+            let names: Vec<&str> = KeyAction::NAMES.iter().map(|(name, _)| *name).collect();
+            format!("unknown action: {action}, expected one of {}", names.join(", "))
+        })?;
+
+    let mut modifiers = KeyModifiers::NONE;
+    let mut key = key;
     loop {
+        let lower = key.to_lowercase();
+        if lower.starts_with("ctrl-") {
+            modifiers |= KeyModifiers::CONTROL;
+        } else if lower.starts_with("alt-") {
+            modifiers |= KeyModifiers::ALT;
+        } else {
+            break;
+        }
+        key = key.split_once('-').unwrap().1;
+    }
+    let mut chars = key.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match key.to_lowercase().as_str() {
+            "esc" => KeyCode::Esc,
+            "enter" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "space" => KeyCode::Char(' '),
+            _ => return Err(format!("unknown key: {key}")),
+        },
+    };
+    Ok((KeyChord::new(code, modifiers), action))
+}
+
+/// Sends the key inputs as events, according to the keymap. Keys are left
+/// alone while `suspended` is set, e.g. for an interactive command.
+/// Ctrl-c always quits, even while a prompt is open.
+pub fn monitor_key_inputs(tx: Sender<Event>, suspended: Arc<AtomicBool>, keymap: Keymap) {
+    // Input of the environment variable prompt, while it is open
+    let mut prompt: Option<String> = None;
+    loop {
+        if suspended.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(100));
+            continue;
+        }
         if crossterm::event::poll(Duration::from_millis(100)).unwrap() {
             match crossterm::event::read().unwrap() {
                 CrosstermEvent::FocusGained => {}
                 CrosstermEvent::FocusLost => {}
-                CrosstermEvent::Key(key_event) => match key_event.code {
-                    KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
-                        let _ = tx.send(Event::Term(TermEvents::Quit));
+                CrosstermEvent::Key(key_event) => {
+                    let chord = KeyChord::new(key_event.code, key_event.modifiers);
+                    if handle_key(chord, &keymap, &mut prompt, &tx).is_break() {
                         return;
                     }
-                    KeyCode::Char('l') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
-                        let _ = tx.send(Event::Term(TermEvents::ClearScreen));
-                    }
-                    KeyCode::Char('q') | KeyCode::Char('Q') | KeyCode::Esc => {
-                        let _ = tx.send(Event::Term(TermEvents::Quit));
-                        return;
-                    }
-                    KeyCode::Char('k') => {
-                        let _ = tx.send(Event::TogglePause);
-                    }
-                    KeyCode::Char('a') => {
-                        let _ = tx.send(Event::AbortOngoingCommands);
-                    }
-                    _ => {}
-                },
+                }
                 CrosstermEvent::Mouse(_) => {}
                 CrosstermEvent::Paste(_) => {}
                 CrosstermEvent::Resize(c, r) => {
@@ -48,3 +225,172 @@ pub fn monitor_key_inputs(tx: Sender<Event>) {
         }
     }
 }
+
+/// Sends the event of a typed key. Breaks once the user wishes to quit
+fn handle_key(
+    chord: KeyChord,
+    keymap: &Keymap,
+    prompt: &mut Option<String>,
+    tx: &Sender<Event>,
+) -> ControlFlow<()> {
+    if chord == KeyChord::new(KeyCode::Char('c'), KeyModifiers::CONTROL) {
+        let _ = tx.send(Event::Term(TermEvents::Quit));
+        return ControlFlow::Break(());
+    }
+    if let Some(input) = prompt {
+        match prompt_key(input, chord.code) {
+            PromptStep::Edited => {
+                let _ = tx.send(Event::Term(TermEvents::EnvPrompt(prompt.clone())));
+            }
+            PromptStep::Ignored => {}
+            PromptStep::Cancel => {
+                *prompt = None;
+                let _ = tx.send(Event::Term(TermEvents::EnvPrompt(None)));
+            }
+            PromptStep::Submit(key, value) => {
+                *prompt = None;
+                let _ = tx.send(Event::Term(TermEvents::EnvPrompt(None)));
+                let _ = tx.send(Event::RerunWithEnv(key, value));
+            }
+        }
+        return ControlFlow::Continue(());
+    }
+
+    let event = match keymap.action(chord) {
+        None => return ControlFlow::Continue(()),
+        Some(KeyAction::Quit) => {
+            let _ = tx.send(Event::Term(TermEvents::Quit));
+            return ControlFlow::Break(());
+        }
+        Some(KeyAction::RerunWithEnv) => {
+            *prompt = Some(String::new());
+            Event::Term(TermEvents::EnvPrompt(prompt.clone()))
+        }
+        Some(KeyAction::ClearScreen) => Event::Term(TermEvents::ClearScreen),
+        Some(KeyAction::TogglePause) => Event::TogglePause,
+        Some(KeyAction::AbortOngoing) => Event::AbortOngoingCommands,
+        Some(KeyAction::Focus) => Event::Term(TermEvents::ToggleFocus { run: true }),
+        Some(KeyAction::FocusSkip) => Event::Term(TermEvents::ToggleFocus { run: false }),
+        Some(KeyAction::ListQueue) => Event::Term(TermEvents::ListQueue),
+        Some(KeyAction::RunNow) => Event::RunNow,
+        Some(KeyAction::Rerun) => Event::Term(TermEvents::Rerun),
+        Some(KeyAction::ToggleTitle) => Event::ToggleTitle,
+    };
+    let _ = tx.send(event);
+    ControlFlow::Continue(())
+}
+
+/// Runs the command now for every line read, e.g. from a piped stdin.
+/// Lines do not have to be valid UTF-8. Returns once the input is closed,
+/// or on a read error, which would otherwise repeat forever
+pub fn monitor_input_lines<R: BufRead>(input: R, tx: Sender<Event>) {
+    for line in input.split(b'\n') {
+        if let Err(e) = line {
+            log::warn!("Could not read the input, no more runs from it: {e}");
+            return;
+        }
+        if tx.send(Event::RunNow).is_err() {
+            return;
+        }
+    }
+    log::debug!("Input closed, no more runs from it");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+
+    #[test]
+    fn test_env_prompt() {
+        let mut input = String::new();
+        for c in "RUST_LOX".chars() {
+            assert_eq!(prompt_key(&mut input, KeyCode::Char(c)), PromptStep::Edited);
+        }
+        prompt_key(&mut input, KeyCode::Backspace);
+        for c in "G=debug=1".chars() {
+            prompt_key(&mut input, KeyCode::Char(c));
+        }
+        assert_eq!(
+            prompt_key(&mut input, KeyCode::Enter),
+            PromptStep::Submit("RUST_LOG".into(), "debug=1".into())
+        );
+
+        let mut input = "=oops".to_string();
+        assert_eq!(prompt_key(&mut input, KeyCode::Enter), PromptStep::Ignored);
+        assert_eq!(prompt_key(&mut input, KeyCode::Esc), PromptStep::Cancel);
+    }
+
+    #[test]
+    fn test_ctrl_key_action() {
+        let (tx, rx) = unbounded();
+        let keymap = Keymap::default();
+        let mut prompt = None;
+
+        let ctrl_r = KeyChord::new(KeyCode::Char('r'), KeyModifiers::CONTROL);
+        assert!(handle_key(ctrl_r, &keymap, &mut prompt, &tx).is_continue());
+        assert!(matches!(rx.try_recv(), Ok(Event::Term(TermEvents::Rerun))));
+
+        // Ctrl-l clears the screen, l alone lists the queue
+        let ctrl_l = KeyChord::new(KeyCode::Char('l'), KeyModifiers::CONTROL);
+        let _ = handle_key(ctrl_l, &keymap, &mut prompt, &tx);
+        assert!(matches!(rx.try_recv(), Ok(Event::Term(TermEvents::ClearScreen))));
+        let l = KeyChord::new(KeyCode::Char('l'), KeyModifiers::NONE);
+        let _ = handle_key(l, &keymap, &mut prompt, &tx);
+        assert!(matches!(rx.try_recv(), Ok(Event::Term(TermEvents::ListQueue))));
+
+        let ctrl_c = KeyChord::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert!(handle_key(ctrl_c, &keymap, &mut prompt, &tx).is_break());
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let (chord, action) = parse_key_binding("Ctrl-Alt-x=pause").unwrap();
+        let modifiers = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        assert_eq!(chord, KeyChord::new(KeyCode::Char('x'), modifiers));
+        assert_eq!(action, KeyAction::TogglePause);
+        assert_eq!(parse_key_binding("esc=run").unwrap().0.code, KeyCode::Esc);
+        assert!(parse_key_binding("x").is_err());
+        assert!(parse_key_binding("x=nope").is_err());
+        assert!(parse_key_binding("ctrl-xy=quit").is_err());
+
+        // Overrides the default action of the key
+        let mut keymap = Keymap::default();
+        let (chord, action) = parse_key_binding("q=pause").unwrap();
+        keymap.bind(chord, action);
+        let q = KeyChord::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert_eq!(keymap.action(q), Some(KeyAction::TogglePause));
+        // Shift is part of the character
+        let shift_f = KeyChord::new(KeyCode::Char('F'), KeyModifiers::SHIFT);
+        assert_eq!(keymap.action(shift_f), Some(KeyAction::FocusSkip));
+    }
+
+    #[test]
+    fn test_input_lines_trigger_runs() {
+        let (tx, rx) = unbounded();
+        monitor_input_lines(std::io::Cursor::new("\nbuild\n"), tx);
+        assert!(matches!(rx.try_recv(), Ok(Event::RunNow)));
+        assert!(matches!(rx.try_recv(), Ok(Event::RunNow)));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_input_closed() {
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        let (tx, rx) = unbounded();
+        let handle = std::thread::spawn(move || {
+            monitor_input_lines(std::io::BufReader::new(reader), tx);
+        });
+        std::io::Write::write_all(&mut writer, b"\xff\n").unwrap();
+        assert!(matches!(rx.recv_timeout(Duration::from_secs(5)), Ok(Event::RunNow)));
+
+        // Closing the pipe ends the thread without any more runs
+        drop(writer);
+        let start = std::time::Instant::now();
+        while !handle.is_finished() {
+            assert!(start.elapsed() < Duration::from_secs(5), "Still reading a closed input");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(rx.try_recv().is_err());
+    }
+}
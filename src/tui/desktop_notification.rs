@@ -0,0 +1,70 @@
+use crate::command::exit_code::{ExitCode, get_exit_reason};
+use crate::tui::PROGRAM_NAME;
+use notify_rust::Notification;
+
+/// Desktop notification of a finished command, sent with --notify
+#[derive(Debug, PartialEq)]
+pub struct RunNotification {
+    /// Title: the command and how it ended
+    pub summary: String,
+    /// Files the command was run for
+    pub body: String,
+}
+
+impl RunNotification {
+    /// Describes a finished command. `command_number` is the one shown in
+    /// the UI
+    pub fn new(command_number: usize, exit_code: ExitCode, files: &[String]) -> Self {
+        let outcome = match exit_code {
+            Some(0) => "succeeded".to_string(),
+            Some(code) => match get_exit_reason(exit_code) {
+                Some(reason) => format!("failed: {reason}"),
+                None => format!("failed with exit code {code}"),
+            },
+            None => "did not finish".to_string(),
+        };
+        Self {
+            summary: format!("{PROGRAM_NAME}: #{command_number} {outcome}"),
+            body: files.join("\n"),
+        }
+    }
+
+    /// Shows the notification. The notification service may be slow to
+    /// answer, so it is contacted from another thread
+    pub fn show(self) {
+        let spawned =
+            std::thread::Builder::new().name("rex-notification".to_string()).spawn(move || {
+                let shown = Notification::new()
+                    .appname(PROGRAM_NAME)
+                    .summary(&self.summary)
+                    .body(&self.body)
+                    .show();
+                if let Err(e) = shown {
+                    log::error!("Could not show the desktop notification: {e}");
+                }
+            });
+        if let Err(e) = spawned {
+            log::error!("Could not start the desktop notification thread: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notification_text() {
+        let files = vec!["a.rs".to_string(), "b.rs".to_string()];
+        let notification = RunNotification::new(3, Some(2), &files);
+        assert_eq!(notification.summary, format!("{PROGRAM_NAME}: #3 failed with exit code 2"));
+        assert_eq!(notification.body, "a.rs\nb.rs");
+
+        let notification = RunNotification::new(4, Some(0), &[]);
+        assert_eq!(notification.summary, format!("{PROGRAM_NAME}: #4 succeeded"));
+        assert_eq!(
+            RunNotification::new(5, None, &[]).summary,
+            format!("{PROGRAM_NAME}: #5 did not finish")
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use colored::{Color, ColoredString, Colorize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Default colors of the file names, by extension
+const DEFAULT_COLORS: &[(&str, Color)] = &[
+    ("rs", Color::TrueColor { r: 222, g: 118, b: 33 }),
+    ("md", Color::Blue),
+    ("toml", Color::Yellow),
+    ("json", Color::Yellow),
+    ("py", Color::Green),
+    ("sh", Color::Green),
+    ("js", Color::BrightYellow),
+    ("ts", Color::BrightBlue),
+    ("c", Color::Cyan),
+    ("h", Color::Cyan),
+    ("cpp", Color::Cyan),
+    ("go", Color::BrightCyan),
+    ("html", Color::Magenta),
+    ("css", Color::Magenta),
+];
+
+/// Colors of the file names shown in the UI, by extension.
+/// The default instance does not color anything
+#[derive(Default)]
+pub struct FileColors {
+    by_extension: HashMap<String, Color>,
+}
+
+impl FileColors {
+    /// Default colors, replaced or completed by the given ones
+    pub fn new(overrides: &[(String, Color)]) -> Self {
+        let mut by_extension: HashMap<String, Color> =
+            DEFAULT_COLORS.iter().map(|(ext, color)| (ext.to_string(), *color)).collect();
+        by_extension.extend(overrides.iter().cloned());
+        Self { by_extension }
+    }
+
+    /// Colors a file name according to its extension
+    pub fn paint(&self, name: &str) -> ColoredString {
+        let color = Path::new(name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| self.by_extension.get(&e.to_lowercase()));
+        match color {
+            Some(color) => name.color(*color),
+            None => name.normal(),
+        }
+    }
+}
+
+/// Parses a file color given as `ext=color`, e.g. `rs=red` or `.md=bright blue`
+pub fn parse_file_color(value: &str) -> Result<(String, Color), String> {
+    let (ext, color) = value.split_once('=').ok_or("expected EXT=COLOR, e.g. rs=red")?;
+    let ext = ext.trim_start_matches('.').to_lowercase();
+    if ext.is_empty() {
+        return Err("missing extension, expected EXT=COLOR".to_string());
+    }
+    let color = color.parse::<Color>().map_err(|_| format!("unknown color: {color}"))?;
+    Ok((ext, color))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_colors() {
+        let colors = FileColors::new(&[("md".to_string(), Color::Red)]);
+        let rs = colors.paint("main.rs");
+        assert_eq!(rs.input, "main.rs");
+        assert_eq!(rs.fgcolor, Some(Color::TrueColor { r: 222, g: 118, b: 33 }));
+        assert_eq!(colors.paint("README.MD").fgcolor, Some(Color::Red));
+        assert_eq!(colors.paint("Makefile").fgcolor, None);
+        assert_eq!(FileColors::default().paint("main.rs").fgcolor, None);
+    }
+
+    #[test]
+    fn test_parse_file_color() {
+        assert_eq!(parse_file_color(".RS=bright red"), Ok(("rs".to_string(), Color::BrightRed)));
+        assert!(parse_file_color("rs").is_err());
+        assert!(parse_file_color("rs=nope").is_err());
+        assert!(parse_file_color("=red").is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::command::execution_report::ExecMessage;
+use crate::tui::status_file::json_string;
+use std::io::Write;
+
+/// Serializes an execution report as a single line JSON object, for
+/// --format json. Command numbers are the ones shown in the UI. Reports
+/// that only refresh the UI give nothing
+pub fn to_json_line(message: &ExecMessage, timestamp: &str) -> Option<String> {
+    let (event, command_number, fields) = match message {
+        ExecMessage::Start(report) => {
+            let files = report
+                .files
+                .iter()
+                .map(|f| {
+                    format!("{{\"name\":{},\"kind\":\"{}\"}}", json_string(&f.name), f.kind.name())
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            let label = report.label.as_deref().map_or("null".to_string(), json_string);
+            ("start", report.command_number, format!("\"label\":{label},\"files\":[{files}]"))
+        }
+        ExecMessage::Output(report) => {
+            let mut fields = Vec::new();
+            if let Some(stdout) = &report.stdout {
+                fields.push(format!("\"stdout\":{}", json_string(stdout)));
+            }
+            if let Some(stderr) = &report.stderr {
+                fields.push(format!("\"stderr\":{}", json_string(stderr)));
+            }
+            ("output", report.command_number, fields.join(","))
+        }
+        ExecMessage::Error(report) => {
+            ("error", report.command_number, format!("\"error\":{}", json_string(&report.error)))
+        }
+        ExecMessage::Finish(report) => {
+            let exit_code = report.exit_code.map_or("null".to_string(), |c| c.to_string());
+            let duration =
+                report.duration.map_or("null".to_string(), |d| d.as_millis().to_string());
+            let fields = format!("\"exit_code\":{exit_code},\"duration_ms\":{duration}");
+            ("finish", report.command_number, fields)
+        }
+        ExecMessage::Running(_) => return None,
+    };
+    let separator = if fields.is_empty() { "" } else { "," };
+    Some(format!(
+        "{{\"event\":\"{event}\",\"command_number\":{},\"timestamp\":{}{separator}{fields}}}\n",
+        command_number + 1,
+        json_string(timestamp)
+    ))
+}
+
+/// Prints a line right away, so that consumers can stream the events
+pub fn print_line(line: &str) {
+    let mut stdout = std::io::stdout().lock();
+    if let Err(e) = stdout.write_all(line.as_bytes()).and_then(|_| stdout.flush()) {
+        log::error!("Could not print a JSON event: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::execution_report::{
+        ChangeKind, ExecCode, ExecFile, ExecOutput, ExecRunning, ExecStart,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_json_lines() {
+        let start = ExecMessage::Start(ExecStart {
+            command_number: 0,
+            files: vec![ExecFile { name: "a \"b\".rs".into(), kind: ChangeKind::Created }],
+            label: None,
+        });
+        assert_eq!(
+            to_json_line(&start, "t").unwrap(),
+            "{\"event\":\"start\",\"command_number\":1,\"timestamp\":\"t\",\"label\":null,\
+             \"files\":[{\"name\":\"a \\\"b\\\".rs\",\"kind\":\"created\"}]}\n"
+        );
+
+        let output = ExecMessage::Output(ExecOutput {
+            command_number: 0,
+            stdout: None,
+            stderr: Some("warning:\tunused".into()),
+        });
+        assert_eq!(
+            to_json_line(&output, "t").unwrap(),
+            "{\"event\":\"output\",\"command_number\":1,\"timestamp\":\"t\",\
+             \"stderr\":\"warning:\\tunused\"}\n"
+        );
+
+        let finish = ExecMessage::Finish(ExecCode {
+            command_number: 1,
+            exit_code: None,
+            duration: Some(Duration::from_millis(1500)),
+            stopped: false,
+        });
+        assert_eq!(
+            to_json_line(&finish, "t").unwrap(),
+            "{\"event\":\"finish\",\"command_number\":2,\"timestamp\":\"t\",\
+             \"exit_code\":null,\"duration_ms\":1500}\n"
+        );
+
+        let running =
+            ExecMessage::Running(ExecRunning { command_number: 0, elapsed: Duration::ZERO });
+        assert_eq!(to_json_line(&running, "t"), None);
+    }
+}
@@ -5,5 +5,12 @@ pub use output::PROGRAM_NAME;
 pub mod term;
 pub use term::RawModeGuard;
 
+pub mod desktop_notification;
+pub mod file_colors;
+pub mod json_events;
+pub mod run_summary;
+pub mod status_file;
+pub mod theme;
+
 pub mod duration;
 pub use duration::format_duration;
@@ -1,34 +1,68 @@
 use crate::{
-    args::{Args, FILE_SUBSTITUTION, FILES_SUBSTITUTION},
-    command::{execution_report::ExecMessage, exit_code::get_exit_code_string},
+    args::{
+        Args, DurationThresholds, FILES_SUBSTITUTION, LABEL_SUBSTITUTION, NotifyOn, OutputFormat,
+        PER_FILE_SUBSTITUTIONS,
+    },
+    command::{
+        execution_report::{ChangeKind, ExecFile, ExecMessage},
+        exit_code::{
+            ExitCode, get_exit_code_string, get_exit_reason, get_restarted_string,
+            process_exit_code,
+        },
+        queue_status::QueueStatus,
+    },
+    event::Event,
+    files::utils::IgnoreReason,
+    tui::desktop_notification::RunNotification,
+    tui::file_colors::FileColors,
     tui::format_duration,
+    tui::json_events,
+    tui::run_summary::RunSummary,
+    tui::status_file::RunStatus,
+    tui::theme::{Theme, Themed},
 };
 use chrono::Local;
-use colored::Colorize;
-use crossterm::{ExecutableCommand, cursor, terminal};
+use colored::{ColoredString, Colorize};
+use crossbeam_channel::Receiver;
+use crossterm::{ExecutableCommand, cursor, style, terminal};
 use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
-use std::collections::{HashMap, VecDeque};
+use notify::EventKind;
+use regex::Regex;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 pub static PROGRAM_NAME: &str = env!("CARGO_PKG_NAME");
 // const TICK_STRINGS: [&str; 8] = ["⢹", "⢺", "⢼", "⣸", "⣇", "⡧", "⡗", "⡏"];
 const TICK_CHARS: &str = "⣼⣹⢻⠿⡟⣏⣧⣶ ";
 const NUMBER_OF_PB_ON_SCREEN: usize = 5;
+/// Below this number of rows, the UI switches to a compact layout
+const COMPACT_MODE_ROWS: u16 = 16;
 const MAX_CACHED_OUTPUT_LINES: usize = 100;
+/// Number of stderr lines kept in the errors pane
+const ERRORS_PANE_LINES: usize = 5;
 
 /// Information saved for each command / progress bar
 struct CommandCache {
     pub progress_bar: ProgressBar,
     pub file_list: String,
+    /// Label of the watches the files belong to
+    pub label: Option<String>,
     pub time: Option<String>,
-    /// Formatted elapsed duration string (set when the command finishes)
+    /// Rendered elapsed duration segment (set when the command finishes)
     pub elapsed_str: Option<String>,
 }
 
 /// Helper to manage the output on the screen while
 /// the programm is running
 pub struct Output {
-    /// Top level title
-    title: String,
+    /// Command shown in the title
+    command: String,
+    /// Do we show the full command in the title, even if it does not fit
+    full_title: bool,
     /// MultiProgress handle
     multi: MultiProgress,
     /// Caching information associated with each command
@@ -47,20 +81,115 @@ pub struct Output {
     help_bar: Option<ProgressBar>,
     /// Indication if the program is paused or not
     paused: bool,
+    /// Indication if the program is in focus mode or not
+    focus: bool,
+    /// Input of the environment variable prompt, while it is open
+    prompt: Option<String>,
+    /// Status line at the very bottom, if enabled
+    footer: Option<Footer>,
+    /// Last stderr lines, pinned above the help bar, if enabled
+    errors_pane: Option<ErrorsPane>,
+    /// Do we keep everything off the terminal
+    headless: bool,
+    /// Is the terminal too small for the full layout: only the latest
+    /// progress bar is shown, without help bar
+    compact: bool,
+    /// Do we print a separator before the output of each command
+    group_output: bool,
+    /// Do we clear the screen and the finished commands before each run
+    clear: bool,
+    /// Colors of the UI
+    theme: Theme,
+    /// Command that printed the last output line
+    last_output_command: Option<usize>,
+    /// Do we prefix output lines with the number of the command
+    prefix_output: bool,
+    /// Do we prefix output lines with the file of the command
+    prefix_file: bool,
+    /// File names of the running commands, used as output prefixes
+    prefixes: HashMap<usize, String>,
+    /// Do we mark commands that finished without output
+    mark_empty: bool,
+    /// Commands that printed at least one line
+    with_output: HashSet<usize>,
+    /// Was the terminal already given back by finish()
+    torn_down: bool,
+    /// Extracts the progress of the commands from their output, if enabled
+    progress_regex: Option<Regex>,
+    /// Colors of the file names
+    file_colors: FileColors,
+    /// Limits used to color the duration of finished commands
+    duration_thresholds: DurationThresholds,
+    /// File rewritten after each finished command, if enabled
+    status_file: Option<PathBuf>,
+    /// Finished commands getting a desktop notification, with --notify
+    notify_on: Option<NotifyOn>,
+    /// File names of the running commands, for the status file and the
+    /// desktop notifications
+    run_files: HashMap<usize, Vec<String>>,
+    /// Totals printed on quit instead of any output, with --count-only
+    summary: Option<RunSummary>,
+    /// Exit code of the last finished command
+    last_exit_code: Option<ExitCode>,
+    /// Consecutive failures needed to ring the bell, with --notify-after
+    notify_after: Option<usize>,
+    /// Commands that failed in a row, reset by a successful one
+    consecutive_failures: usize,
+    /// Times the bell was rung
+    bells: usize,
+    /// Commands are long-running and stopped by the next update, with
+    /// --restart
+    restart: bool,
+    /// Execution reports are printed as JSON lines instead of the UI, with
+    /// --format json
+    json: bool,
+}
+
+/// Region showing the last stderr lines of the commands
+struct ErrorsPane {
+    /// Progress bar used to display the lines
+    progress_bar: ProgressBar,
+    /// Last stderr lines, oldest first
+    lines: VecDeque<String>,
+}
+
+/// Status line showing the aggregate state of the program
+struct Footer {
+    /// Progress bar used to display the status line
+    progress_bar: ProgressBar,
+    /// Live state of the command queue
+    status: Arc<QueueStatus>,
+    /// Exit code of the last finished command
+    last_exit_code: Option<ExitCode>,
 }
 
 impl Output {
     /// Creates a new instance
     pub fn new(args: &Args) -> Self {
-        let mut command = args.command.join(" ");
-        for s in &[FILES_SUBSTITUTION, FILE_SUBSTITUTION] {
-            command = command.replace(s, s.italic().bold().to_string().as_str());
-        }
-        let title = format!("{} | {}", PROGRAM_NAME.bold(), command.green());
+        Self::create(args, args.count_only || args.format == OutputFormat::Json)
+    }
 
+    /// Creates an instance that does not draw anything on the terminal
+    #[cfg(test)]
+    pub fn headless(args: &Args) -> Self {
+        Self::create(args, true)
+    }
+
+    fn create(args: &Args, headless: bool) -> Self {
+        // With --task, the title lists the commands of the tasks
+        let command = if args.tasks.is_empty() {
+            args.command.join(" ")
+        } else {
+            args.tasks
+                .iter()
+                .map(|t| format!("{}: {}", t.name, t.command))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        };
         let mut output = Self {
-            title,
-            multi: MultiProgress::new(),
+            command,
+            full_title: false,
+            multi: Self::new_multi_progress(headless),
             cache: HashMap::new(),
             quiet: args.quiet,
             time: args.time,
@@ -69,6 +198,43 @@ impl Output {
             pending_output: Vec::new(),
             help_bar: None,
             paused: false,
+            focus: false,
+            prompt: None,
+            footer: None,
+            errors_pane: args.errors_pane.then(|| ErrorsPane {
+                progress_bar: ProgressBar::hidden(),
+                lines: VecDeque::with_capacity(ERRORS_PANE_LINES),
+            }),
+            headless,
+            compact: !headless
+                && terminal::size().is_ok_and(|(_, rows)| Self::is_small_terminal(rows)),
+            group_output: args.group_by_command_number,
+            clear: args.clear,
+            theme: args.theme,
+            last_output_command: None,
+            prefix_output: args.prefix_output,
+            prefix_file: args.prefix_file && !args.batch_exec,
+            prefixes: HashMap::new(),
+            mark_empty: args.mark_empty && !args.quiet,
+            with_output: HashSet::new(),
+            torn_down: false,
+            progress_regex: args.progress_regex.clone(),
+            file_colors: if args.no_file_colors || args.theme.is_mono() {
+                FileColors::default()
+            } else {
+                FileColors::new(&args.file_color)
+            },
+            duration_thresholds: args.duration_thresholds,
+            status_file: args.status_file.clone(),
+            notify_on: args.notify.then_some(args.notify_on),
+            run_files: HashMap::new(),
+            summary: args.count_only.then(RunSummary::default),
+            last_exit_code: None,
+            notify_after: args.notify_after,
+            consecutive_failures: 0,
+            bells: 0,
+            restart: args.restart,
+            json: args.format == OutputFormat::Json,
         };
 
         output.generate_title();
@@ -91,6 +257,12 @@ impl Output {
         self.pending_output.push(s);
     }
 
+    /// Lines printed so far, most recent last
+    #[cfg(test)]
+    pub fn printed_lines(&self) -> &VecDeque<String> {
+        &self.output_lines
+    }
+
     /// Advances every active spinner by one frame.
     /// Called from the main-thread 100 ms timer so there is no background
     /// draw thread competing with our rendering.
@@ -107,6 +279,9 @@ impl Output {
     /// clear-bars → print-all → redraw-bars pass, instead of one full redraw
     /// per line (which caused visible bar jumping at high output volumes).
     pub fn flush_output(&mut self) {
+        if self.summary.is_some() || self.json {
+            self.pending_output.clear();
+        }
         if self.pending_output.is_empty() {
             return;
         }
@@ -121,16 +296,32 @@ impl Output {
         });
     }
 
+    /// Renders the execution reports still waiting in the channel and flushes
+    /// the output, so that the final frame is complete when quitting
+    pub fn drain_reports(&mut self, rx: &Receiver<Event>) {
+        for event in rx.try_iter() {
+            if let Event::Exec(update) = event {
+                self.update(update);
+            }
+        }
+        self.flush_output();
+    }
+
     /// Returns how many lines of child-process output can be displayed without
     /// overflowing into the title / progress-bar area.
     fn available_output_lines(&self) -> usize {
         let term_height = terminal::size().map(|(_, r)| r as usize).unwrap_or(24);
         // title area  : blank line + separator + title       = 3 lines
         // progress bars: up to NUMBER_OF_PB_ON_SCREEN bars   = 0..5 lines
-        // help bar     : separator + help text               = 2 lines
+        // errors pane  : separator + stderr lines, if enabled = 0..6 lines
+        // help bar     : separator + help text, if shown     = 0..2 lines
+        // footer       : status line, if enabled             = 0..1 line
         // buffer       : breathing room                      = 2 lines
-        let bar_count = (self.cache.len().saturating_sub(1)).min(NUMBER_OF_PB_ON_SCREEN);
-        let ui_lines = 3 + bar_count + 2 + 2;
+        let bar_count = (self.cache.len().saturating_sub(1)).min(self.bars_on_screen());
+        let pane_lines = self.errors_pane.as_ref().map_or(0, |pane| 1 + pane.lines.len());
+        let help_lines = if self.help_bar.is_some() { 2 } else { 0 };
+        let footer_lines = if self.footer.is_some() { 1 } else { 0 };
+        let ui_lines = 3 + bar_count + pane_lines + help_lines + footer_lines + 2;
         term_height.saturating_sub(ui_lines)
     }
 
@@ -138,93 +329,340 @@ impl Output {
     pub fn generate_title(&mut self) {
         let pb = self.multi.insert(0, ProgressBar::no_length());
         pb.set_style(Self::title_style());
-        pb.set_message(format!("{}\n{}", Self::separator_line(None), self.title));
+        pb.set_message(format!("{}\n{}", Self::separator_line(None, &self.theme), self.title()));
         pb.finish();
         let cache = CommandCache {
             progress_bar: pb,
             file_list: String::from(""),
+            label: None,
             time: None,
             elapsed_str: None,
         };
         self.cache.insert(0, cache);
     }
 
+    fn new_multi_progress(headless: bool) -> MultiProgress {
+        if headless {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        }
+    }
+
+    /// Title for the current terminal width
+    fn title(&self) -> String {
+        let term_width = terminal::size().map(|(c, _)| c as usize).unwrap_or(80);
+        Self::format_title(&self.command, term_width, self.full_title, &self.theme)
+    }
+
+    /// Formats the title, truncating the command so that it fits on one line of
+    /// the given width, unless the full command is requested
+    fn format_title(command: &str, width: usize, full: bool, theme: &Theme) -> String {
+        let available = width.saturating_sub(PROGRAM_NAME.width() + " | ".len());
+        let mut command = if full || command.width() <= available {
+            command.to_string()
+        } else {
+            let mut truncated = String::new();
+            let mut used = 0;
+            for c in command.chars() {
+                used += c.width().unwrap_or(0);
+                if used + 1 > available {
+                    break;
+                }
+                truncated.push(c);
+            }
+            truncated.push('…');
+            truncated
+        };
+        let placeholders = [FILES_SUBSTITUTION, LABEL_SUBSTITUTION];
+        for s in placeholders.iter().chain(&PER_FILE_SUBSTITUTIONS) {
+            command = command.replace(s, s.italic().bold().to_string().as_str());
+        }
+        format!("{} | {}", PROGRAM_NAME.bold(), command.themed(theme.command))
+    }
+
+    /// Shows the full command in the title, or truncates it to the terminal
+    /// width again
+    pub fn toggle_full_title(&mut self) {
+        self.full_title = !self.full_title;
+        self.redraw();
+    }
+
     /// Adds the help bar at the bottom of the MultiProgress
     fn add_help_bar(&mut self) {
-        let separator = Self::separator_line(None);
-        let pause_or_resume = if self.paused { "resume" } else { "pause" };
-        let help_text = format!(
-            "  {} quit  {}  {} clear  {}  {} {}  {}  {} abort ongoing",
-            "q/Ctrl-c".cyan().bold(),
-            "·".bright_black(),
-            "Ctrl-l".cyan().bold(),
-            "·".bright_black(),
-            "k".cyan().bold(),
-            pause_or_resume,
-            "·".bright_black(),
-            "a".cyan().bold(),
-        );
-        let pb = self.multi.add(ProgressBar::no_length());
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template(&format!("{separator}\n{help_text}"))
-                .expect("no help bar template error"),
-        );
-        pb.finish();
-        self.help_bar = Some(pb);
+        // The errors pane stays right below the progress bars
+        if let Some(pane) = &mut self.errors_pane {
+            pane.progress_bar = self.multi.add(ProgressBar::no_length());
+            pane.progress_bar.set_style(Self::footer_style());
+        }
+        self.refresh_errors_pane();
+
+        // No room for the help bar in compact mode, unless for the prompt
+        if !self.compact || self.prompt.is_some() {
+            let separator = Self::separator_line(None, &self.theme);
+            let pause_or_resume = if self.paused { "resume" } else { "pause" };
+            let help_text = if let Some(input) = &self.prompt {
+                format!(
+                    "  {} {input}▏",
+                    "Rerun with env (KEY=VALUE, Enter to run, Esc to cancel):"
+                        .themed(self.theme.accent)
+                )
+            } else {
+                format!(
+                    "  {} quit  {}  {} clear  {}  {} {}  {}  {} abort ongoing  {}  {} focus  {}  {} queue  {}  {} rerun  {}  {} rerun with env  {}  {} full command",
+                    "q/Ctrl-c".themed(self.theme.accent).bold(),
+                    "·".themed(self.theme.dim),
+                    "Ctrl-l".themed(self.theme.accent).bold(),
+                    "·".themed(self.theme.dim),
+                    "k".themed(self.theme.accent).bold(),
+                    pause_or_resume,
+                    "·".themed(self.theme.dim),
+                    "a".themed(self.theme.accent).bold(),
+                    "·".themed(self.theme.dim),
+                    "f".themed(self.theme.accent).bold(),
+                    "·".themed(self.theme.dim),
+                    "l".themed(self.theme.accent).bold(),
+                    "·".themed(self.theme.dim),
+                    "r".themed(self.theme.accent).bold(),
+                    "·".themed(self.theme.dim),
+                    "e".themed(self.theme.accent).bold(),
+                    "·".themed(self.theme.dim),
+                    "t".themed(self.theme.accent).bold(),
+                )
+            };
+            let pb = self.multi.add(ProgressBar::no_length());
+            // As a message, so that braces typed in the prompt are not read
+            // as template placeholders
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template("{msg}")
+                    .expect("no help bar template error"),
+            );
+            pb.set_message(format!("{separator}\n{help_text}"));
+            pb.finish();
+            self.help_bar = Some(pb);
+        }
+
+        // The footer stays below the help bar
+        if let Some(footer) = &mut self.footer {
+            footer.progress_bar = self.multi.add(ProgressBar::no_length());
+            footer.progress_bar.set_style(Self::footer_style());
+        }
+        self.refresh_footer();
     }
 
     /// Removes the help bar so new progress bars are inserted above it
     fn remove_help_bar(&mut self) {
+        if let Some(pane) = &self.errors_pane {
+            self.multi.remove(&pane.progress_bar);
+        }
         if let Some(pb) = self.help_bar.take() {
             self.multi.remove(&pb);
         }
+        if let Some(footer) = &self.footer {
+            self.multi.remove(&footer.progress_bar);
+        }
+    }
+
+    /// Shows the input of the environment variable prompt in place of the
+    /// key hints, or the key hints again once the prompt is closed
+    pub fn set_prompt(&mut self, prompt: Option<String>) {
+        self.remove_help_bar();
+        self.prompt = prompt;
+        self.add_help_bar();
+    }
+
+    /// Shows a status line at the bottom of the screen, reporting the state
+    /// of the given queue
+    pub fn show_footer(&mut self, status: Arc<QueueStatus>) {
+        self.remove_help_bar();
+        self.footer =
+            Some(Footer { progress_bar: ProgressBar::hidden(), status, last_exit_code: None });
+        self.add_help_bar();
+    }
+
+    /// Updates the status line with the current state
+    pub fn refresh_footer(&mut self) {
+        let Some(footer) = &self.footer else {
+            return;
+        };
+        let text = Self::footer_text(
+            footer.status.active_workers(),
+            footer.status.pending_files(),
+            footer.last_exit_code,
+            &self.theme,
+        );
+        if footer.progress_bar.message() != text {
+            footer.progress_bar.set_message(text);
+        }
+    }
+
+    /// Shows a stderr line in the errors pane, dropping the oldest one if
+    /// the pane is full
+    fn push_error_line(&mut self, line: String) {
+        let Some(pane) = &mut self.errors_pane else {
+            return;
+        };
+        if pane.lines.len() >= ERRORS_PANE_LINES {
+            pane.lines.pop_front();
+        }
+        pane.lines.push_back(line);
+        self.refresh_errors_pane();
+    }
+
+    /// Updates the errors pane with its current lines
+    fn refresh_errors_pane(&mut self) {
+        let Some(pane) = &self.errors_pane else {
+            return;
+        };
+        let mut text = Self::separator_line(Some(" errors "), &self.theme);
+        for line in &pane.lines {
+            text.push('\n');
+            text.push_str(line);
+        }
+        pane.progress_bar.set_message(text);
+    }
+
+    /// Formats the status line
+    fn footer_text(
+        running: usize,
+        pending: usize,
+        last_exit_code: Option<ExitCode>,
+        theme: &Theme,
+    ) -> String {
+        let separator = "·".themed(theme.dim);
+        let last = match last_exit_code {
+            Some(code) => get_exit_code_string(code, theme).trim_end().to_string(),
+            None => "-".to_string(),
+        };
+        format!(
+            "  running: {running}  {separator}  pending: {pending}  {separator}  last exit: {last}  {separator}  press {} to quit",
+            "q".themed(theme.accent).bold()
+        )
+    }
+
+    /// Style for the footer, a single line message
+    fn footer_style() -> ProgressStyle {
+        ProgressStyle::default_bar()
+            .template("{wide_msg}")
+            .expect("no footer template error")
     }
 
     /// Returns a separator line of ─ characters spanning the terminal width
     /// With an optional message at the beginning of the separator
-    fn separator_line(message: Option<&str>) -> String {
+    fn separator_line(message: Option<&str>, theme: &Theme) -> String {
         let term_width = terminal::size().map(|(c, _)| c as usize).unwrap_or(80);
         if let Some(m) = message {
-            let message_width = unicode_width::UnicodeWidthStr::width(m);
+            let message_width = m.width();
             if term_width < message_width + 1 {
                 // Message does not fit - we just skip it.
-                "─".repeat(term_width).cyan().to_string()
+                "─".repeat(term_width).themed(theme.accent).to_string()
             } else {
                 // formats in cyan ─message───────
                 format!(
                     "{}{}{}",
-                    "─".cyan(),
-                    m.cyan(),
-                    "─".repeat(term_width - message_width - 1).cyan()
+                    "─".themed(theme.accent),
+                    m.themed(theme.accent),
+                    "─".repeat(term_width - message_width - 1).themed(theme.accent)
                 )
             }
         } else {
-            "─".repeat(term_width).cyan().to_string()
+            "─".repeat(term_width).themed(theme.accent).to_string()
         }
     }
 
     /// Checks the index of the last progress bar and remove old
     /// progress bar that should not be on screen anymore
     pub fn remove_old_progress_bars(&mut self, last_index: usize) {
-        if last_index <= NUMBER_OF_PB_ON_SCREEN {
-            return;
+        let bars = self.bars_on_screen();
+        let old: Vec<usize> = self
+            .cache
+            .keys()
+            .filter(|&&i| i != 0 && i + bars <= last_index)
+            .cloned()
+            .collect();
+        for index in old {
+            if let Some(cache) = self.cache.remove(&index) {
+                self.multi.remove(&cache.progress_bar);
+            }
         }
-        let pop_index = last_index - NUMBER_OF_PB_ON_SCREEN;
-        let pop_pb = self.cache.remove(&pop_index);
+    }
 
-        if pop_pb.is_none() {
-            return;
+    /// Removes the progress bars of the finished commands, for --clear
+    fn remove_finished_progress_bars(&mut self) {
+        let finished: Vec<usize> = self
+            .cache
+            .iter()
+            .filter(|&(&i, cache)| i != 0 && cache.progress_bar.is_finished())
+            .map(|(&i, _)| i)
+            .collect();
+        for index in finished {
+            if let Some(cache) = self.cache.remove(&index) {
+                self.multi.remove(&cache.progress_bar);
+            }
+        }
+    }
+
+    /// Number of command progress bars kept on screen
+    fn bars_on_screen(&self) -> usize {
+        if self.compact { 1 } else { NUMBER_OF_PB_ON_SCREEN }
+    }
+
+    /// Tells if the terminal has too few rows for the full layout
+    fn is_small_terminal(rows: u16) -> bool {
+        rows < COMPACT_MODE_ROWS
+    }
+
+    /// Adapts the layout to the new terminal size and redraws the screen.
+    /// Small terminals get the compact layout, so that the progress bars do
+    /// not overflow the screen
+    pub fn resize(&mut self, rows: u16) {
+        let compact = Self::is_small_terminal(rows);
+        if compact != self.compact {
+            self.compact = compact;
+            self.remove_help_bar();
+            if let Some(&last_index) = self.cache.keys().max() {
+                self.remove_old_progress_bars(last_index);
+            }
         }
-        let pop_pb = pop_pb.unwrap().progress_bar;
-        self.multi.remove(&pop_pb);
+        self.redraw();
     }
 
     /// Finishes all the progres bars
     pub fn finish(&mut self) {
+        if self.torn_down {
+            return;
+        }
+        self.torn_down = true;
+        self.flush_output();
+        if let Some(summary) = &self.summary {
+            println!("{summary}");
+        }
         for c in self.cache.values() {
             c.progress_bar.finish();
         }
+        // Keep the last errors on screen, but not the key hints or the
+        // status line
+        if let Some(pane) = self.errors_pane.take() {
+            pane.progress_bar.finish();
+        }
+        self.remove_help_bar();
+        if !self.headless {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(Self::teardown_sequence().as_bytes());
+            let _ = stdout.flush();
+        }
+    }
+
+    /// Exit code for rex, reflecting the one of the last finished command
+    pub fn exit_code(&self) -> i32 {
+        process_exit_code(self.last_exit_code)
+    }
+
+    /// Escape sequence giving the terminal back in its default state: cursor
+    /// shown and colors reset
+    fn teardown_sequence() -> String {
+        format!("{}{}", cursor::Show, style::ResetColor)
     }
 
     /// Clears the cached output lines and redraws the screen
@@ -234,6 +672,55 @@ impl Output {
         self.redraw();
     }
 
+    /// Clears the UI and stops drawing until the next redraw(), leaving the
+    /// terminal to an interactive command
+    pub fn step_back(&mut self) {
+        self.flush_output();
+        let _ = self.multi.clear();
+        self.multi.set_draw_target(ProgressDrawTarget::hidden());
+    }
+
+    /// Tells the output if the program is currently in focus mode or not
+    pub fn set_focus(&mut self, focus: bool) {
+        self.focus = focus;
+        self.redraw();
+    }
+
+    /// Prints the files waiting for a run
+    pub fn print_queue(&mut self, files: &[PathBuf]) {
+        if files.is_empty() {
+            self.println("No file waiting for a run".themed(self.theme.dim).to_string());
+            return;
+        }
+        self.println(
+            format!("{} file(s) waiting for a run:", files.len())
+                .themed(self.theme.dim)
+                .to_string(),
+        );
+        for f in files {
+            self.println(format!("  {}", f.display()).themed(self.theme.dim).to_string());
+        }
+    }
+
+    /// Prints whether a file update would run the command, or why it is
+    /// ignored, for --dry-run-ignore
+    pub fn print_ignore_preview(&mut self, path: &Path, reason: Option<IgnoreReason>) {
+        let line = match reason {
+            Some(reason) => {
+                format!("{} {} ({reason})", "ignored".themed(self.theme.dim), path.display())
+            }
+            None => format!("{} {}", "would run".themed(self.theme.success), path.display()),
+        };
+        self.println(line);
+    }
+
+    /// Prints the watch and the receiver a file update comes from, for
+    /// --show-source
+    pub fn print_event_source(&mut self, path: &Path, kind: EventKind, watch: &Path, rx: usize) {
+        let source = format!("<- {} (receiver {rx})", watch.display());
+        self.println(format!("{kind:?} {} {}", path.display(), source.themed(self.theme.dim)));
+    }
+
     /// Tells the output if the program is currently paused or not
     pub fn set_pause(&mut self, paused: bool) {
         self.paused = paused;
@@ -257,15 +744,23 @@ impl Output {
         if let Some(ref hb) = self.help_bar {
             hb.set_draw_target(ProgressDrawTarget::hidden());
         }
+        if let Some(footer) = &self.footer {
+            footer.progress_bar.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        if let Some(pane) = &self.errors_pane {
+            pane.progress_bar.set_draw_target(ProgressDrawTarget::hidden());
+        }
 
         let _ = self.multi.clear();
 
         // Move cursor to top-left and clear the entire visible terminal.
-        let mut stdout = std::io::stdout();
-        let _ = stdout.execute(cursor::MoveTo(0, 0));
-        let _ = stdout.execute(terminal::Clear(terminal::ClearType::All));
+        if !self.headless {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.execute(cursor::MoveTo(0, 0));
+            let _ = stdout.execute(terminal::Clear(terminal::ClearType::All));
+        }
 
-        self.multi = MultiProgress::new();
+        self.multi = Self::new_multi_progress(self.headless);
 
         // Replay the most-recent output lines that fit above the UI.
         let available = self.available_output_lines();
@@ -286,14 +781,29 @@ impl Output {
             let was_finished = old_cache.progress_bar.is_finished();
             let old_prefix = old_cache.progress_bar.prefix().to_string();
             let file_list = old_cache.file_list.clone();
+            let label = old_cache.label.clone();
             let time = old_cache.time.clone();
             let elapsed_str = old_cache.elapsed_str.clone();
+            let progress = old_cache
+                .progress_bar
+                .length()
+                .map(|length| (length, old_cache.progress_bar.position()));
 
             let pb = if index == 0 {
                 let pb = self.multi.insert(0, ProgressBar::no_length());
                 pb.set_style(Self::title_style());
-                let message = if self.paused { Some("paused") } else { None };
-                pb.set_message(format!("{}\n{}", Self::separator_line(message), self.title));
+                let message = if self.paused {
+                    Some("paused")
+                } else if self.focus {
+                    Some("focus mode (f: run updates, F: discard)")
+                } else {
+                    None
+                };
+                pb.set_message(format!(
+                    "{}\n{}",
+                    Self::separator_line(message, &self.theme),
+                    self.title()
+                ));
                 pb.finish();
                 pb
             } else {
@@ -301,23 +811,34 @@ impl Output {
                 if was_finished {
                     let style = match elapsed_str.as_deref() {
                         Some(s) => Self::progress_bar_finished_style_with_duration(s),
-                        None => Self::progress_bar_finished_style(),
+                        None => Self::progress_bar_finished_style(&self.theme),
                     };
                     pb.set_style(style);
+                } else if let Some((length, position)) = progress {
+                    pb.set_style(Self::progress_bar_determinate_style(&self.theme));
+                    pb.set_length(length);
+                    pb.set_position(position);
                 } else {
-                    pb.set_style(Self::progress_bar_style());
+                    pb.set_style(Self::progress_bar_style(&self.theme));
                     // No enable_steady_tick; tick_spinners() drives animation.
                 }
                 pb.set_prefix(old_prefix);
-                pb.set_message(format!("{}: {}", self.file_str.bold(), file_list));
+                pb.set_message(Self::command_message(
+                    self.file_str,
+                    label.as_deref(),
+                    &file_list,
+                    &self.theme,
+                ));
                 if was_finished {
                     pb.finish();
                 }
                 pb
             };
 
-            self.cache
-                .insert(index, CommandCache { progress_bar: pb, file_list, time, elapsed_str });
+            self.cache.insert(
+                index,
+                CommandCache { progress_bar: pb, file_list, label, time, elapsed_str },
+            );
         }
 
         self.add_help_bar();
@@ -325,47 +846,161 @@ impl Output {
 
     /// Updates progress bars based on an exec report
     pub fn update(&mut self, update: ExecMessage) {
+        if self.json
+            && let Some(line) = json_events::to_json_line(&update, &Local::now().to_rfc3339())
+        {
+            json_events::print_line(&line);
+        }
         match update {
             ExecMessage::Start(report) => {
                 let index = report.command_number + 1;
+                if self.prefix_file
+                    && let Some(file) = report.files.first()
+                {
+                    self.prefixes.insert(report.command_number, file.name.clone());
+                }
+                if self.status_file.is_some() || self.notify_on.is_some() {
+                    let names = report.files.iter().map(|f| f.name.clone()).collect();
+                    self.run_files.insert(report.command_number, names);
+                }
+                if self.clear {
+                    self.remove_finished_progress_bars();
+                    self.clear_output();
+                }
                 self.remove_old_progress_bars(index);
                 self.remove_help_bar();
                 let pb = self.multi.insert(index, ProgressBar::new_spinner());
-                let files = report.files.join(", ");
+                let files = Self::format_file_list(&report.files, &self.file_colors, &self.theme);
                 let time = if self.time { Some(Self::get_local_time()) } else { None };
 
-                pb.set_style(Self::progress_bar_style());
+                pb.set_style(Self::progress_bar_style(&self.theme));
                 let prefix = if let Some(ref t) = time {
                     format!("#{}. {}", index, t)
                 } else {
                     format!("#{index}.")
                 };
-                pb.set_prefix(prefix.bright_black().to_string());
-                pb.set_message(format!("{}: {}", self.file_str.bold(), files));
+                pb.set_prefix(prefix.themed(self.theme.dim).to_string());
+                pb.set_message(Self::command_message(
+                    self.file_str,
+                    report.label.as_deref(),
+                    &files,
+                    &self.theme,
+                ));
                 // Do NOT call enable_steady_tick — that spawns a background draw thread
                 // which races with our main-thread rendering.  Spinners are advanced
                 // manually by tick_spinners() from the 100 ms flush timer.
 
-                let c =
-                    CommandCache { progress_bar: pb, file_list: files, time, elapsed_str: None };
+                let c = CommandCache {
+                    progress_bar: pb,
+                    file_list: files,
+                    label: report.label,
+                    time,
+                    elapsed_str: None,
+                };
                 self.cache.insert(index, c);
                 self.add_help_bar();
             }
             ExecMessage::Output(report) => {
+                if let Some(regex) = &self.progress_regex
+                    && let Some(cache) = self.cache.get(&(report.command_number + 1))
+                {
+                    for line in report.stdout.iter().chain(report.stderr.iter()) {
+                        Self::update_progress(regex, line, &cache.progress_bar, &self.theme);
+                    }
+                }
                 if self.quiet {
                     return;
                 }
-                // TODO: We could consider prepeding output with the command number and avoid
-                // mixing them
+                if self.group_output && self.last_output_command != Some(report.command_number) {
+                    let anchor = format!(" #{} ", report.command_number + 1);
+                    self.println(Self::separator_line(Some(&anchor), &self.theme));
+                }
+                self.last_output_command = Some(report.command_number);
+                if self.mark_empty {
+                    self.with_output.insert(report.command_number);
+                }
+                let mut prefix = String::new();
+                if self.prefix_output {
+                    let number = format!("#{}|", report.command_number + 1);
+                    prefix.push_str(&number.themed(self.theme.dim).to_string());
+                }
+                if let Some(file) = self.prefixes.get(&report.command_number) {
+                    prefix.push_str(&format!("{} ", format!("[{file}]").themed(self.theme.dim)));
+                }
                 if let Some(stdout) = report.stdout {
-                    self.println(stdout);
+                    self.println(format!("{prefix}{stdout}"));
                 }
                 if let Some(stderr) = report.stderr {
-                    self.println(stderr);
+                    if self.errors_pane.is_some() {
+                        self.push_error_line(format!("{prefix}{stderr}"));
+                    } else {
+                        self.println(format!("{prefix}{stderr}"));
+                    }
                 }
             }
+            ExecMessage::Error(report) => {
+                let index = report.command_number + 1;
+                let line = format!("#{index}. could not start: {}", report.error);
+                self.println(line.themed(self.theme.failure).to_string());
+            }
+            ExecMessage::Running(report) => {
+                let index = report.command_number + 1;
+                let Some(cache) = self.cache.get(&index) else {
+                    return;
+                };
+                let hint = format!("still running ({}s)", report.elapsed.as_secs());
+                let message = Self::command_message(
+                    self.file_str,
+                    cache.label.as_deref(),
+                    &cache.file_list,
+                    &self.theme,
+                );
+                cache
+                    .progress_bar
+                    .set_message(format!("{message} {}", hint.themed(self.theme.warning)));
+            }
             ExecMessage::Finish(report) => {
                 let index = report.command_number + 1;
+                self.prefixes.remove(&report.command_number);
+                if let Some(summary) = &mut self.summary {
+                    summary.record(&report);
+                }
+                // A stopped long-running command did not fail, it was replaced
+                let restarted = self.restart && report.stopped;
+                if !restarted {
+                    self.last_exit_code = Some(report.exit_code);
+                    self.notify_failures(report.exit_code);
+                }
+                let files = self.run_files.remove(&report.command_number).unwrap_or_default();
+                if !restarted && self.notify_on.is_some_and(|on| on.matches(report.exit_code)) {
+                    RunNotification::new(index, report.exit_code, &files).show();
+                }
+                if let Some(path) = &self.status_file {
+                    let status = RunStatus {
+                        command_number: index,
+                        exit_code: report.exit_code,
+                        files: &files,
+                    };
+                    if let Err(e) = status.write(path) {
+                        log::error!("Could not write the status file {:?}: {e}", path);
+                    }
+                }
+                if self.mark_empty && !self.with_output.remove(&report.command_number) {
+                    self.println(
+                        format!("#{index}. (no output)").themed(self.theme.dim).to_string(),
+                    );
+                }
+                if let Some(footer) = &mut self.footer {
+                    footer.last_exit_code = Some(report.exit_code);
+                    self.refresh_footer();
+                }
+                if let Some(reason) = get_exit_reason(report.exit_code) {
+                    self.println(
+                        format!("#{index}. terminated: {reason}")
+                            .themed(self.theme.failure)
+                            .to_string(),
+                    );
+                }
                 let cache = self.cache.get_mut(&index);
                 // If progress bar disappeared (due to scrolling), we just ignore the update
                 if cache.is_none() {
@@ -374,41 +1009,150 @@ impl Output {
                 let cache = cache.unwrap();
                 let pb = &cache.progress_bar;
 
-                let elapsed_str = report.duration.map(format_duration);
+                let thresholds = self.duration_thresholds;
+                let elapsed_str = report
+                    .duration
+                    .map(|d| Self::elapsed_segment(d, thresholds, &self.theme).to_string());
                 let style = match elapsed_str.as_deref() {
                     Some(s) => Self::progress_bar_finished_style_with_duration(s),
-                    None => Self::progress_bar_finished_style(),
+                    None => Self::progress_bar_finished_style(&self.theme),
                 };
                 pb.set_style(style);
+                let code = if restarted {
+                    get_restarted_string(&self.theme)
+                } else {
+                    get_exit_code_string(report.exit_code, &self.theme)
+                };
                 let prefix = if let Some(t) = &cache.time {
-                    format!("#{}. {} {}", index, t, get_exit_code_string(report.exit_code))
+                    format!("#{}. {} {}", index, t, code)
                 } else {
-                    format!("#{}. {}", index, get_exit_code_string(report.exit_code))
+                    format!("#{}. {}", index, code)
                 };
-                pb.set_prefix(prefix.bright_black().to_string());
-                pb.set_message(format!("{}: {}", self.file_str.bold(), cache.file_list));
+                pb.set_prefix(prefix.themed(self.theme.dim).to_string());
+                pb.set_message(Self::command_message(
+                    self.file_str,
+                    cache.label.as_deref(),
+                    &cache.file_list,
+                    &self.theme,
+                ));
                 cache.elapsed_str = elapsed_str;
                 pb.finish();
             }
         }
     }
 
+    /// Counts the failures in a row and rings the bell once there are enough
+    /// of them. Interrupted commands neither fail nor succeed
+    fn notify_failures(&mut self, exit_code: ExitCode) {
+        let Some(notify_after) = self.notify_after else {
+            return;
+        };
+        match exit_code {
+            Some(0) => self.consecutive_failures = 0,
+            Some(_) => self.consecutive_failures += 1,
+            None => return,
+        }
+        if self.consecutive_failures < notify_after {
+            return;
+        }
+        self.bells += 1;
+        if !self.headless {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(b"\x07");
+            let _ = stdout.flush();
+        }
+    }
+
+    /// Turns the spinner of a command into a progress bar when an output line
+    /// reports its progress
+    fn update_progress(regex: &Regex, line: &str, pb: &ProgressBar, theme: &Theme) {
+        let Some(captures) = regex.captures(line) else {
+            return;
+        };
+        let number = |i| captures.get(i).and_then(|m| m.as_str().parse::<u64>().ok());
+        let (Some(done), Some(total)) = (number(1), number(2)) else {
+            return;
+        };
+        if pb.is_finished() || total == 0 {
+            return;
+        }
+        if pb.length().is_none() {
+            pb.set_style(Self::progress_bar_determinate_style(theme));
+        }
+        pb.set_length(total);
+        pb.set_position(done.min(total));
+    }
+
+    /// Message shown next to a command: its watch label and files
+    fn command_message(
+        file_str: &str,
+        label: Option<&str>,
+        file_list: &str,
+        theme: &Theme,
+    ) -> String {
+        match label {
+            Some(l) => format!("[{}] {}: {}", l.themed(theme.accent), file_str.bold(), file_list),
+            None => format!("{}: {}", file_str.bold(), file_list),
+        }
+    }
+
+    /// Formats the files of a command, each prefixed with a marker showing how
+    /// it changed
+    fn format_file_list(files: &[ExecFile], colors: &FileColors, theme: &Theme) -> String {
+        files
+            .iter()
+            .map(|f| format!("{} {}", Self::change_marker(f.kind, theme), colors.paint(&f.name)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Diff-style marker for a kind of change
+    fn change_marker(kind: ChangeKind, theme: &Theme) -> ColoredString {
+        match kind {
+            ChangeKind::Created => "+".themed(theme.success),
+            ChangeKind::Modified => "~".themed(theme.warning),
+            ChangeKind::Deleted => "-".themed(theme.failure),
+        }
+    }
+
+    /// Style of a running command that reports its progress
+    fn progress_bar_determinate_style(theme: &Theme) -> ProgressStyle {
+        ProgressStyle::default_bar()
+            .template(
+                format!(
+                    "{{prefix}} {} {{pos}}/{{len}}  {{wide_msg}} {}",
+                    &Self::progress_template("bar:20", theme),
+                    "[{elapsed}] ".themed(theme.elapsed)
+                )
+                .as_str(),
+            )
+            .expect("no determinate template error")
+    }
+
     /// Returns the default / pre-configured progress style
-    fn progress_bar_style() -> ProgressStyle {
+    fn progress_bar_style(theme: &Theme) -> ProgressStyle {
         ProgressStyle::default_spinner()
             //.tick_strings(&TICK_STRINGS)
             .tick_chars(TICK_CHARS)
             .template(
                 format!(
                     "{{prefix}} {}   {{wide_msg}} {}",
-                    "{spinner}".magenta(),
-                    "[{elapsed}] ".blue()
+                    Self::progress_template("spinner", theme),
+                    "[{elapsed}] ".themed(theme.elapsed)
                 )
                 .as_str(),
             )
             .expect("no default template error")
     }
 
+    /// Template placeholder of a spinner or bar, in the progress color
+    fn progress_template(key: &str, theme: &Theme) -> String {
+        match theme.progress {
+            Some(color) => format!("{{{key}.{color}}}"),
+            None => format!("{{{key}}}"),
+        }
+    }
+
     /// Style for the title bar (separator + title), uses {msg} to support
     /// multi-line
     fn title_style() -> ProgressStyle {
@@ -418,26 +1162,536 @@ impl Output {
     }
 
     /// Style for finished progress bars
-    fn progress_bar_finished_style() -> ProgressStyle {
+    fn progress_bar_finished_style(theme: &Theme) -> ProgressStyle {
         ProgressStyle::default_spinner()
-            .template(format!("{{prefix}} {{wide_msg}} {}", "[{elapsed}] ".blue()).as_str())
+            .template(
+                format!("{{prefix}} {{wide_msg}} {}", "[{elapsed}] ".themed(theme.elapsed))
+                    .as_str(),
+            )
             .expect("no finished template error")
     }
 
-    /// Style for finished progress bars with a custom duration string baked in.
-    /// The duration string replaces indicatif's `{elapsed}` so the exact
+    /// Style for finished progress bars with a custom duration segment baked
+    /// in. The segment replaces indicatif's `{elapsed}` so the exact
     /// measured time (µs / ms / s) is shown at the right.
-    fn progress_bar_finished_style_with_duration(duration_str: &str) -> ProgressStyle {
+    fn progress_bar_finished_style_with_duration(segment: &str) -> ProgressStyle {
         ProgressStyle::default_spinner()
-            .template(
-                format!("{{prefix}} {{wide_msg}} {}", format!("[{}] ", duration_str).blue())
-                    .as_str(),
-            )
+            .template(format!("{{prefix}} {{wide_msg}} {segment}").as_str())
             .expect("no finished-with-duration template error")
     }
 
+    /// Duration segment of a finished command, colored by how slow it was
+    fn elapsed_segment(
+        duration: Duration,
+        thresholds: DurationThresholds,
+        theme: &Theme,
+    ) -> ColoredString {
+        let segment = format!("[{}] ", format_duration(duration));
+        if duration < thresholds.fast {
+            segment.themed(theme.success)
+        } else if duration < thresholds.slow {
+            segment.themed(theme.warning)
+        } else {
+            segment.themed(theme.failure)
+        }
+    }
+
     fn get_local_time() -> String {
         let now = Local::now();
         now.format("%H:%M:%S").to_string()
     }
 }
+
+impl Drop for Output {
+    /// Gives the terminal back even if finish() was not called, e.g. on error
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::execution_report::{ExecCode, ExecOutput, ExecStart};
+    use clap::Parser;
+    use colored::Color;
+
+    #[test]
+    fn test_created_file_marker() {
+        let files = [ExecFile { name: String::from("main.rs"), kind: ChangeKind::Created }];
+        let list = Output::format_file_list(&files, &FileColors::default(), &Theme::default());
+        assert!(list.contains('+'));
+        assert!(list.ends_with(" main.rs"));
+        assert_eq!(
+            Output::change_marker(ChangeKind::Created, &Theme::default()).fgcolor,
+            Some(Color::Green)
+        );
+    }
+
+    #[test]
+    fn test_file_list_markers() {
+        let files = [
+            ExecFile { name: String::from("a.rs"), kind: ChangeKind::Modified },
+            ExecFile { name: String::from("b.rs"), kind: ChangeKind::Deleted },
+        ];
+        let list = Output::format_file_list(&files, &FileColors::default(), &Theme::default());
+        assert!(list.contains('~'));
+        assert!(list.contains('-'));
+        assert_eq!(
+            Output::change_marker(ChangeKind::Deleted, &Theme::default()).fgcolor,
+            Some(Color::Red)
+        );
+    }
+
+    #[test]
+    fn test_file_names_colored_by_extension() {
+        let mut args = Args::try_parse_from(["rex", "make"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+        output.update(ExecMessage::Start(ExecStart {
+            command_number: 0,
+            files: vec![ExecFile { name: "main.rs".into(), kind: ChangeKind::Modified }],
+            label: None,
+        }));
+        let painted = FileColors::new(&[]).paint("main.rs");
+        assert!(painted.fgcolor.is_some());
+        assert!(output.cache[&1].file_list.ends_with(&painted.to_string()));
+
+        let mut args = Args::try_parse_from(["rex", "--no-file-colors", "make"]).unwrap();
+        args.validate().unwrap();
+        assert_eq!(Output::headless(&args).file_colors.paint("main.rs").fgcolor, None);
+    }
+
+    #[test]
+    fn test_late_output_flushed() {
+        let mut args = Args::try_parse_from(["rex", "make"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        for line in ["first", "last"] {
+            let report = ExecOutput { command_number: 0, stdout: Some(line.into()), stderr: None };
+            tx.send(Event::Exec(ExecMessage::Output(report))).unwrap();
+        }
+        output.drain_reports(&rx);
+
+        assert!(output.pending_output.is_empty());
+        assert_eq!(output.output_lines, ["first", "last"]);
+    }
+
+    #[test]
+    fn test_errors_pane() {
+        let mut args = Args::try_parse_from(["rex", "--errors-pane", "make"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+
+        for i in 0..7 {
+            let report = ExecOutput {
+                command_number: 0,
+                stdout: Some(format!("out {i}")),
+                stderr: Some(format!("err {i}")),
+            };
+            output.update(ExecMessage::Output(report));
+        }
+
+        assert!(output.output_lines.iter().all(|line| line.starts_with("out")));
+        assert_eq!(output.output_lines.len(), 7);
+        let pane = output.errors_pane.as_ref().unwrap();
+        assert_eq!(pane.lines, ["err 2", "err 3", "err 4", "err 5", "err 6"]);
+        assert!(pane.progress_bar.message().ends_with("\nerr 5\nerr 6"));
+    }
+
+    #[test]
+    fn test_command_output_anchor() {
+        colored::control::set_override(false);
+        let mut args = Args::try_parse_from(["rex", "--group-by-command-number", "make"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+
+        for (command_number, line) in [(41, "a"), (41, "b"), (42, "c")] {
+            let report = ExecOutput { command_number, stdout: Some(line.into()), stderr: None };
+            output.update(ExecMessage::Output(report));
+        }
+
+        let lines: Vec<&String> = output.output_lines.iter().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].contains("─ #42 ─"));
+        assert_eq!(lines[1..3], ["a", "b"]);
+        assert!(lines[3].contains("─ #43 ─"));
+        assert_eq!(lines[4], "c");
+    }
+
+    #[test]
+    fn test_output_prefixed_with_file() {
+        colored::control::set_override(false);
+        let mut args = Args::try_parse_from(["rex", "--prefix-file", "cat {file}"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+
+        for (command_number, name) in [(0, "a.rs"), (1, "b.rs")] {
+            output.update(ExecMessage::Start(ExecStart {
+                command_number,
+                files: vec![ExecFile { name: name.into(), kind: ChangeKind::Modified }],
+                label: None,
+            }));
+        }
+        let report = ExecOutput { command_number: 1, stdout: Some("line".into()), stderr: None };
+        output.update(ExecMessage::Output(report));
+
+        assert_eq!(output.output_lines, ["[b.rs] line"]);
+    }
+
+    #[test]
+    fn test_clear_before_run() {
+        let mut args = Args::try_parse_from(["rex", "--clear", "make"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+        let start = |command_number| {
+            ExecMessage::Start(ExecStart {
+                command_number,
+                files: vec![ExecFile { name: "a.rs".into(), kind: ChangeKind::Modified }],
+                label: None,
+            })
+        };
+
+        output.update(start(0));
+        let report = ExecOutput { command_number: 0, stdout: Some("old".into()), stderr: None };
+        output.update(ExecMessage::Output(report));
+        let finish =
+            ExecCode { command_number: 0, exit_code: Some(0), duration: None, stopped: false };
+        output.update(ExecMessage::Finish(finish));
+        output.update(start(1));
+
+        assert!(output.output_lines.is_empty());
+        let mut indices: Vec<usize> = output.cache.keys().cloned().collect();
+        indices.sort_unstable();
+        // The title is kept, the finished command is gone
+        assert_eq!(indices, [0, 2]);
+    }
+
+    #[test]
+    fn test_output_prefixed_with_command_number() {
+        colored::control::set_override(false);
+        let mut args = Args::try_parse_from(["rex", "--prefix-output", "make"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+
+        for (command_number, line) in [(1, "b"), (0, "a")] {
+            let report =
+                ExecOutput { command_number, stdout: Some(line.into()), stderr: Some("e".into()) };
+            output.update(ExecMessage::Output(report));
+        }
+
+        assert_eq!(output.output_lines, ["#2|b", "#2|e", "#1|a", "#1|e"]);
+    }
+
+    #[test]
+    fn test_silent_command_marked() {
+        let mut args = Args::try_parse_from(["rex", "--mark-empty", "true"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+
+        for command_number in [0, 1] {
+            output.update(ExecMessage::Start(ExecStart {
+                command_number,
+                files: vec![ExecFile { name: "a.rs".into(), kind: ChangeKind::Modified }],
+                label: None,
+            }));
+        }
+        let report = ExecOutput { command_number: 0, stdout: Some("line".into()), stderr: None };
+        output.update(ExecMessage::Output(report));
+        for command_number in [0, 1] {
+            output.update(ExecMessage::Finish(ExecCode {
+                command_number,
+                exit_code: Some(0),
+                duration: None,
+                stopped: false,
+            }));
+        }
+
+        assert_eq!(output.output_lines.len(), 2);
+        assert_eq!(output.output_lines[0], "line");
+        assert!(output.output_lines[1].contains("#2. (no output)"));
+    }
+
+    #[test]
+    fn test_status_file_follows_last_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status.json");
+        let mut args =
+            Args::try_parse_from(["rex", "--status-file", path.to_str().unwrap(), "true"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+
+        for (command_number, file, exit_code) in [(0, "a.rs", 0), (1, "b.rs", 2)] {
+            output.update(ExecMessage::Start(ExecStart {
+                command_number,
+                files: vec![ExecFile { name: file.into(), kind: ChangeKind::Modified }],
+                label: None,
+            }));
+            output.update(ExecMessage::Finish(ExecCode {
+                command_number,
+                exit_code: Some(exit_code),
+                duration: None,
+                stopped: false,
+            }));
+        }
+
+        let status = std::fs::read_to_string(&path).unwrap();
+        assert!(status.starts_with("{\"command_number\":2,\"exit_code\":2,"));
+        assert!(status.ends_with("\"files\":[\"b.rs\"]}\n"));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restarted_command_is_not_a_failure() {
+        colored::control::set_override(false);
+        let mut args =
+            Args::try_parse_from(["rex", "--restart", "--notify-after", "1", "serve"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+        output.update(ExecMessage::Start(ExecStart {
+            command_number: 0,
+            files: vec![],
+            label: None,
+        }));
+        output.update(ExecMessage::Finish(ExecCode {
+            command_number: 0,
+            exit_code: Some(128 + libc::SIGTERM),
+            duration: None,
+            stopped: true,
+        }));
+        assert!(output.cache[&1].progress_bar.prefix().starts_with("#1. ↻"));
+        assert_eq!((output.bells, output.exit_code()), (0, 0));
+
+        // Terminated by someone else: the command failed
+        output.update(ExecMessage::Start(ExecStart {
+            command_number: 1,
+            files: vec![],
+            label: None,
+        }));
+        output.update(ExecMessage::Finish(ExecCode {
+            command_number: 1,
+            exit_code: Some(128 + libc::SIGTERM),
+            duration: None,
+            stopped: false,
+        }));
+        assert!(!output.cache[&2].progress_bar.prefix().contains('↻'));
+        assert_eq!((output.bells, output.exit_code()), (1, 128 + libc::SIGTERM));
+    }
+
+    #[test]
+    fn test_bell_after_consecutive_failures() {
+        let mut args = Args::try_parse_from(["rex", "--notify-after", "2", "true"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+        let mut bells = Vec::new();
+        for (command_number, exit_code) in
+            [Some(1), Some(0), Some(1), None, Some(2), Some(3)].into_iter().enumerate()
+        {
+            output.update(ExecMessage::Finish(ExecCode {
+                command_number,
+                exit_code,
+                duration: None,
+                stopped: false,
+            }));
+            bells.push(output.bells);
+        }
+        assert_eq!(bells, [0, 0, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_count_only_summary() {
+        let mut args = Args::try_parse_from(["rex", "--count-only", "true"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+
+        for (command_number, exit_code) in [(0, Some(0)), (1, Some(1)), (2, None), (3, Some(0))] {
+            output.update(ExecMessage::Start(ExecStart {
+                command_number,
+                files: vec![],
+                label: None,
+            }));
+            output.update(ExecMessage::Output(ExecOutput {
+                command_number,
+                stdout: Some("hidden".into()),
+                stderr: None,
+            }));
+            output.update(ExecMessage::Finish(ExecCode {
+                command_number,
+                exit_code,
+                duration: Some(Duration::from_millis(250)),
+                stopped: false,
+            }));
+        }
+        output.flush_output();
+
+        assert!(output.pending_output.is_empty());
+        let summary = output.summary.as_ref().unwrap().to_string();
+        assert_eq!(summary, "runs=4 ok=2 failed=2 duration_ms=1000");
+    }
+
+    #[test]
+    fn test_exit_code_of_last_command() {
+        let mut args = Args::try_parse_from(["rex", "true"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+        assert_eq!(output.exit_code(), 0);
+
+        for (command_number, exit_code, expected) in
+            [(0, Some(2), 2), (1, None, 1), (2, Some(0), 0)]
+        {
+            output.update(ExecMessage::Finish(ExecCode {
+                command_number,
+                exit_code,
+                duration: None,
+                stopped: false,
+            }));
+            assert_eq!(output.exit_code(), expected);
+        }
+    }
+
+    #[test]
+    fn test_slow_command_duration_red() {
+        let mut args = Args::try_parse_from(["rex", "true"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+        output.update(ExecMessage::Start(ExecStart {
+            command_number: 0,
+            files: vec![ExecFile { name: "a.rs".into(), kind: ChangeKind::Modified }],
+            label: None,
+        }));
+        output.update(ExecMessage::Finish(ExecCode {
+            command_number: 0,
+            exit_code: Some(0),
+            duration: Some(Duration::from_secs(15)),
+            stopped: false,
+        }));
+
+        let thresholds = args.duration_thresholds;
+        let segment =
+            Output::elapsed_segment(Duration::from_secs(15), thresholds, &Theme::default());
+        assert_eq!(segment.input, "[15s] ");
+        assert_eq!(segment.fgcolor, Some(colored::Color::Red));
+        assert_eq!(output.cache[&1].elapsed_str.as_deref(), Some(segment.to_string().as_str()));
+
+        let fast =
+            Output::elapsed_segment(Duration::from_millis(20), thresholds, &Theme::default());
+        assert_eq!(fast.fgcolor, Some(colored::Color::Green));
+        let normal = Output::elapsed_segment(Duration::from_secs(3), thresholds, &Theme::default());
+        assert_eq!(normal.fgcolor, Some(colored::Color::Yellow));
+    }
+
+    #[test]
+    fn test_progress_parsed_from_output() {
+        let mut args =
+            Args::try_parse_from(["rex", "--parse-progress", r"(\d+)/(\d+)", "make"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+        output.update(ExecMessage::Start(ExecStart {
+            command_number: 0,
+            files: vec![ExecFile { name: "a.rs".into(), kind: ChangeKind::Modified }],
+            label: None,
+        }));
+        assert_eq!(output.cache[&1].progress_bar.length(), None);
+
+        for line in ["Compiling 3/10", "no progress here"] {
+            let report = ExecOutput { command_number: 0, stdout: Some(line.into()), stderr: None };
+            output.update(ExecMessage::Output(report));
+        }
+        let pb = &output.cache[&1].progress_bar;
+        assert_eq!(pb.length(), Some(10));
+        assert_eq!(pb.position(), 3);
+    }
+
+    #[test]
+    fn test_compact_mode_on_small_terminal() {
+        let mut args = Args::try_parse_from(["rex", "true"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+        for command_number in 0..3 {
+            output.update(ExecMessage::Start(ExecStart {
+                command_number,
+                files: vec![ExecFile { name: "a.rs".into(), kind: ChangeKind::Modified }],
+                label: None,
+            }));
+        }
+        assert!(!output.compact);
+        assert_eq!(output.cache.len(), 4);
+
+        output.resize(10);
+        assert!(output.compact);
+        assert!(output.help_bar.is_none());
+        let mut indices: Vec<usize> = output.cache.keys().cloned().collect();
+        indices.sort_unstable();
+        assert_eq!(indices, vec![0, 3]);
+
+        output.resize(40);
+        assert!(!output.compact);
+        assert!(output.help_bar.is_some());
+    }
+
+    #[test]
+    fn test_env_prompt_in_help_bar() {
+        let mut args = Args::try_parse_from(["rex", "true"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+
+        output.set_prompt(Some("A={x}".into()));
+        let help = output.help_bar.as_ref().unwrap().message();
+        assert!(help.contains("A={x}"), "{help}");
+
+        output.set_prompt(None);
+        let help = output.help_bar.as_ref().unwrap().message();
+        assert!(help.contains("rerun with env"), "{help}");
+    }
+
+    #[test]
+    fn test_teardown_on_finish() {
+        let mut args = Args::try_parse_from(["rex", "true"]).unwrap();
+        args.validate().unwrap();
+        let mut output = Output::headless(&args);
+        output.println("pending");
+        assert!(output.help_bar.is_some());
+
+        output.finish();
+        assert!(output.torn_down);
+        assert!(output.help_bar.is_none());
+        assert!(output.pending_output.is_empty());
+        assert_eq!(Output::teardown_sequence(), "\x1b[?25h\x1b[0m");
+    }
+
+    #[test]
+    fn test_footer_pending_files() {
+        colored::control::set_override(false);
+        let status = QueueStatus::default();
+        status.replace_pending_files(0, 2);
+        let text = Output::footer_text(
+            status.active_workers(),
+            status.pending_files(),
+            Some(Some(1)),
+            &Theme::default(),
+        );
+        assert!(text.contains("running: 0"));
+        assert!(text.contains("pending: 2"));
+        assert!(text.contains("last exit: 1"));
+    }
+
+    #[test]
+    fn test_title_fits_width() {
+        colored::control::set_override(false);
+        let command = "cargo build --workspace && cargo test --workspace -- --nocapture";
+        for width in [20, 40, 60] {
+            let title = Output::format_title(command, width, false, &Theme::default());
+            assert!(title.width() <= width, "{title:?} does not fit in {width}");
+            assert!(title.ends_with('…'));
+        }
+        assert!(
+            Output::format_title(command, 40, true, &Theme::default()).ends_with("--nocapture")
+        );
+        assert!(
+            Output::format_title(command, 200, false, &Theme::default()).ends_with("--nocapture")
+        );
+    }
+}
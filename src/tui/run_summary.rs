@@ -0,0 +1,38 @@
+use crate::command::execution_report::ExecCode;
+use std::fmt;
+use std::time::Duration;
+
+/// Totals of the finished commands, printed on quit with --count-only
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    runs: usize,
+    ok: usize,
+    failed: usize,
+    duration: Duration,
+}
+
+impl RunSummary {
+    /// Counts a finished command
+    pub fn record(&mut self, report: &ExecCode) {
+        self.runs += 1;
+        if report.exit_code == Some(0) {
+            self.ok += 1;
+        } else {
+            self.failed += 1;
+        }
+        self.duration += report.duration.unwrap_or_default();
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "runs={} ok={} failed={} duration_ms={}",
+            self.runs,
+            self.ok,
+            self.failed,
+            self.duration.as_millis()
+        )
+    }
+}
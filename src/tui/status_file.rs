@@ -0,0 +1,76 @@
+use crate::command::exit_code::ExitCode;
+use chrono::Local;
+use std::path::{Path, PathBuf};
+
+/// Summary of the last finished command, written to --status-file
+pub struct RunStatus<'a> {
+    /// ID of the command, as shown in the UI
+    pub command_number: usize,
+    /// Exit code of the command
+    pub exit_code: ExitCode,
+    /// Files the command was run for
+    pub files: &'a [String],
+}
+
+impl RunStatus<'_> {
+    /// Serializes the status as a single line JSON object
+    fn to_json(&self, timestamp: &str) -> String {
+        let exit_code = self.exit_code.map_or("null".to_string(), |c| c.to_string());
+        let files = self.files.iter().map(|f| json_string(f)).collect::<Vec<_>>().join(",");
+        format!(
+            "{{\"command_number\":{},\"exit_code\":{},\"timestamp\":{},\"files\":[{}]}}\n",
+            self.command_number,
+            exit_code,
+            json_string(timestamp),
+            files
+        )
+    }
+
+    /// Replaces the content of the status file. The status is written to a
+    /// temporary file first, then renamed, so readers never see a partial file
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let json = self.to_json(&Local::now().to_rfc3339());
+        let mut tmp = PathBuf::from(path);
+        tmp.as_mut_os_string().push(".tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+/// Quotes and escapes a string for JSON
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_json() {
+        let files = vec!["a.rs".to_string(), "we\"ird\n.rs".to_string()];
+        let status = RunStatus { command_number: 3, exit_code: Some(1), files: &files };
+        assert_eq!(
+            status.to_json("2026-01-01T00:00:00+00:00"),
+            "{\"command_number\":3,\"exit_code\":1,\"timestamp\":\"2026-01-01T00:00:00+00:00\",\
+             \"files\":[\"a.rs\",\"we\\\"ird\\n.rs\"]}\n"
+        );
+
+        let status = RunStatus { command_number: 1, exit_code: None, files: &[] };
+        assert!(status.to_json("t").contains("\"exit_code\":null"));
+    }
+}
@@ -14,6 +14,20 @@ impl RawModeGuard {
         Ok(Self)
     }
 
+    /// Gives the terminal back in its normal mode, e.g. to an interactive
+    /// command
+    pub fn suspend() {
+        let _ = disable_raw_mode();
+    }
+
+    /// Enables raw mode again after a suspend()
+    pub fn resume() {
+        if enable_raw_mode().is_ok() {
+            #[cfg(unix)]
+            Self::enable_output_processing();
+        }
+    }
+
     #[cfg(unix)]
     fn enable_output_processing() {
         unsafe {
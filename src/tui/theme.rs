@@ -0,0 +1,118 @@
+use colored::{Color, ColoredString, Colorize};
+
+/// Names of the preset themes, for --theme
+pub const THEME_NAMES: [&str; 3] = ["default", "mono", "solarized"];
+
+/// Colors of the UI, by role. None leaves the text uncolored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// Command in the title
+    pub command: Option<Color>,
+    /// Secondary text: command numbers, separators between key hints
+    pub dim: Option<Color>,
+    /// Separator lines, key hints and watch labels
+    pub accent: Option<Color>,
+    /// Successful exit codes, fast commands and created files
+    pub success: Option<Color>,
+    /// Interrupted commands, slow commands and modified files
+    pub warning: Option<Color>,
+    /// Failed commands, very slow commands and deleted files
+    pub failure: Option<Color>,
+    /// Unknown exit codes
+    pub unknown: Option<Color>,
+    /// Elapsed time of the running commands
+    pub elapsed: Option<Color>,
+    /// Spinners and progress bars, as an indicatif style
+    pub progress: Option<&'static str>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            command: Some(Color::Green),
+            dim: Some(Color::BrightBlack),
+            accent: Some(Color::Cyan),
+            success: Some(Color::Green),
+            warning: Some(Color::Yellow),
+            failure: Some(Color::Red),
+            unknown: Some(Color::BrightYellow),
+            elapsed: Some(Color::Blue),
+            progress: Some("magenta"),
+        }
+    }
+}
+
+impl Theme {
+    /// Theme without any color, for terminals with an unusual palette
+    pub fn mono() -> Self {
+        Self {
+            command: None,
+            dim: None,
+            accent: None,
+            success: None,
+            warning: None,
+            failure: None,
+            unknown: None,
+            elapsed: None,
+            progress: None,
+        }
+    }
+
+    /// Colors of the Solarized palette
+    pub fn solarized() -> Self {
+        let rgb = |r, g, b| Some(Color::TrueColor { r, g, b });
+        Self {
+            command: rgb(133, 153, 0),
+            dim: rgb(88, 110, 117),
+            accent: rgb(42, 161, 152),
+            success: rgb(133, 153, 0),
+            warning: rgb(181, 137, 0),
+            failure: rgb(220, 50, 47),
+            unknown: rgb(203, 75, 22),
+            elapsed: rgb(38, 139, 210),
+            progress: Some("magenta"),
+        }
+    }
+
+    /// Does the theme color anything
+    pub fn is_mono(&self) -> bool {
+        *self == Self::mono()
+    }
+}
+
+/// Colors a text with a color of the theme, e.g. `"q".themed(theme.accent)`
+pub trait Themed {
+    fn themed(&self, color: Option<Color>) -> ColoredString;
+}
+
+impl Themed for str {
+    fn themed(&self, color: Option<Color>) -> ColoredString {
+        match color {
+            Some(color) => self.color(color),
+            None => self.normal(),
+        }
+    }
+}
+
+/// Parses the name of a preset theme
+pub fn parse_theme(name: &str) -> Result<Theme, String> {
+    match name {
+        "default" => Ok(Theme::default()),
+        "mono" => Ok(Theme::mono()),
+        "solarized" => Ok(Theme::solarized()),
+        _ => Err(format!("unknown theme, expected one of: {}", THEME_NAMES.join(", "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_theme() {
+        assert_eq!(parse_theme("mono"), Ok(Theme::mono()));
+        assert!(parse_theme("mono").unwrap().is_mono());
+        assert!(!parse_theme("default").unwrap().is_mono());
+        assert!(parse_theme("dracula").unwrap_err().contains("default, mono, solarized"));
+    }
+}